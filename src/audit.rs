@@ -0,0 +1,128 @@
+use chrono::Utc;
+use log::{error, info};
+use serde::Serialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+
+use crate::config::Config;
+
+/// Characters of a tool result kept in its audit entry.
+const RESULT_PREVIEW_CHARS: usize = 500;
+
+/// One tool invocation, written as a JSON line.
+#[derive(Debug, Serialize)]
+pub struct AuditEntry {
+    pub timestamp: String,
+    pub tool: String,
+    pub arguments: Value,
+    pub success: bool,
+    /// Length of the full result (or error) in characters.
+    pub result_chars: usize,
+    /// The start of the result, or the error message when the tool failed.
+    pub result_preview: String,
+    /// Set for calls made through `/tools/{name}/invoke` rather than by the model.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub direct: bool,
+}
+
+impl AuditEntry {
+    /// An entry for a call made through `/tools/{name}/invoke`.
+    pub fn direct(tool: &str, arguments: &Value, result: Result<&str, &str>) -> Self {
+        Self { direct: true, ..Self::new(tool, arguments, result) }
+    }
+
+    pub fn new(tool: &str, arguments: &Value, result: Result<&str, &str>) -> Self {
+        let (success, text) = match result {
+            Ok(text) => (true, text),
+            Err(text) => (false, text),
+        };
+        Self {
+            timestamp: Utc::now().to_rfc3339(),
+            tool: tool.to_string(),
+            arguments: arguments.clone(),
+            success,
+            result_chars: text.chars().count(),
+            result_preview: text.chars().take(RESULT_PREVIEW_CHARS).collect(),
+            direct: false,
+        }
+    }
+}
+
+/// Append-only JSON lines log of tool invocations. Entries are handed to a background task so
+/// recording never blocks the chat loop. When the file grows past the size limit it is renamed
+/// to `<path>.1` (replacing any previous one) and a new file is started.
+pub struct AuditLog {
+    sender: Option<mpsc::UnboundedSender<AuditEntry>>,
+}
+
+impl AuditLog {
+    /// A log that discards every entry.
+    pub fn disabled() -> Self {
+        Self { sender: None }
+    }
+
+    /// Starts the writer task when `AUDIT_LOG_PATH` is set. Must be called inside the runtime.
+    pub fn from_config(config: &Config) -> Self {
+        let Some(path) = config.audit_log_path.clone() else {
+            return Self::disabled();
+        };
+        info!("Writing tool audit log to {}", path.display());
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(write_entries(path, config.audit_log_max_bytes, receiver));
+        Self { sender: Some(sender) }
+    }
+
+    pub fn record(&self, entry: AuditEntry) {
+        if let Some(sender) = &self.sender {
+            if sender.send(entry).is_err() {
+                error!("Audit log writer has stopped; dropping entry");
+            }
+        }
+    }
+}
+
+async fn write_entries(path: PathBuf, max_bytes: u64, mut receiver: mpsc::UnboundedReceiver<AuditEntry>) {
+    while let Some(entry) = receiver.recv().await {
+        let mut line = match serde_json::to_vec(&entry) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Failed to serialize audit entry: {}", e);
+                continue;
+            }
+        };
+        line.push(b'\n');
+
+        if let Err(e) = rotate_if_needed(&path, max_bytes).await {
+            error!("Failed to rotate audit log {}: {}", path.display(), e);
+        }
+        if let Err(e) = append(&path, &line).await {
+            error!("Failed to write audit log {}: {}", path.display(), e);
+        }
+    }
+}
+
+async fn rotate_if_needed(path: &Path, max_bytes: u64) -> std::io::Result<()> {
+    match tokio::fs::metadata(path).await {
+        Ok(metadata) if metadata.len() >= max_bytes => {
+            let mut rotated = path.as_os_str().to_owned();
+            rotated.push(".1");
+            tokio::fs::rename(path, PathBuf::from(rotated)).await
+        }
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+async fn append(path: &Path, line: &[u8]) -> std::io::Result<()> {
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(line).await?;
+    file.flush().await
+}
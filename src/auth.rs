@@ -0,0 +1,36 @@
+use actix_web::{HttpRequest, HttpResponse};
+
+use crate::config::Config;
+
+/// Compares without short-circuiting so the key can't be guessed byte by byte from timings.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Guards admin routes: requires `Authorization: Bearer <ADMIN_API_KEY>`.
+/// Admin routes are disabled entirely when no key is configured.
+pub fn require_admin(req: &HttpRequest, config: &Config) -> Result<(), HttpResponse> {
+    let Some(expected) = &config.admin_api_key else {
+        return Err(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "This endpoint is disabled. Set ADMIN_API_KEY to enable it."
+        })));
+    };
+
+    let provided = req
+        .headers()
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or("");
+
+    if constant_time_eq(provided.as_bytes(), expected.as_bytes()) {
+        Ok(())
+    } else {
+        Err(HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Missing or invalid API key"
+        })))
+    }
+}
@@ -0,0 +1,448 @@
+use log::error;
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::llm::ollama::{DEFAULT_OLLAMA_HOST, DEFAULT_OLLAMA_MAX_RESPONSE_BYTES};
+use crate::tools::ranking::RankerKind;
+use crate::tools::websearch::{
+    ExtractionMode, SafeSearch, DEFAULT_FETCH_CONTENT_TYPES, DEFAULT_SEARCH_BREAKER_COOLDOWN_SECS,
+    DEFAULT_SEARCH_BREAKER_THRESHOLD, DEFAULT_SEARCH_COUNT, DEFAULT_SEARCH_FETCH_CONCURRENCY,
+    DEFAULT_SEARCH_MAX_RESPONSE_BYTES, DEFAULT_SEARCH_TIMEOUT_SECS,
+};
+
+/// Server configuration resolved from environment variables (and `.env`) at startup.
+/// Serializes with secrets redacted, for `GET /config`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Config {
+    /// Base URLs of the Ollama servers to use (`OLLAMA_HOSTS`, comma separated). Requests are
+    /// balanced round-robin across them.
+    #[serde(serialize_with = "redact_urls")]
+    pub ollama_hosts: Vec<String>,
+    /// Model used when a chat request doesn't name one (`DEFAULT_MODEL`).
+    pub default_model: Option<String>,
+    /// Model loaded into Ollama with a one-token chat at startup (`WARMUP_MODEL`). No warm-up when unset.
+    pub warmup_model: Option<String>,
+    /// Maximum characters of a single tool result fed back to the model (`MAX_TOOL_OUTPUT_CHARS`).
+    pub max_tool_output_chars: usize,
+    /// Number of search results when a request or tool call doesn't specify one (`DEFAULT_SEARCH_COUNT`).
+    pub default_search_count: usize,
+    /// How many result pages `/search` fetches at once for `full_content` (`SEARCH_FETCH_CONCURRENCY`).
+    pub search_fetch_concurrency: usize,
+    /// Maximum chat calls sent to Ollama at once (`OLLAMA_MAX_CONCURRENT`); further calls queue.
+    /// Unlimited when unset.
+    pub ollama_max_concurrent: Option<usize>,
+    /// How long a queued chat call waits for a slot before failing with 503 (`OLLAMA_QUEUE_TIMEOUT_SECS`).
+    pub ollama_queue_timeout_secs: u64,
+    /// Largest Ollama chat response read before the call fails (`OLLAMA_MAX_RESPONSE_BYTES`).
+    pub ollama_max_response_bytes: usize,
+    /// How long Ollama keeps a model loaded after a request (`OLLAMA_KEEP_ALIVE`), e.g. `"30m"` or `-1`.
+    pub keep_alive: Option<Value>,
+    /// Directory file-based tools may read from (`FILE_ROOT`). File tools are disabled when unset.
+    pub file_root: Option<PathBuf>,
+    /// Offer the `download_file` tool, which saves files under `FILE_ROOT` (`ALLOW_DOWNLOADS`).
+    pub allow_downloads: bool,
+    /// Largest file `download_file` will save (`DOWNLOAD_MAX_BYTES`).
+    pub download_max_bytes: u64,
+    /// Hosts outbound tool requests may contact (`EGRESS_ALLOW_HOSTS`, comma separated). Empty allows all.
+    pub egress_allow_hosts: Vec<String>,
+    /// Hosts outbound tool requests may never contact (`EGRESS_DENY_HOSTS`, comma separated).
+    pub egress_deny_hosts: Vec<String>,
+    /// Retry a search once with a simplified query when it returns nothing (`SEARCH_REFORMULATE_EMPTY`).
+    pub search_reformulate_empty: bool,
+    /// Keep DuckDuckGo `!bang` commands in search queries (`SEARCH_ALLOW_BANGS`). They are stripped by
+    /// default since they redirect away from the results page.
+    pub search_allow_bangs: bool,
+    /// How text is extracted from fetched pages (`FETCH_EXTRACTION_MODE`): `all` or `readability`.
+    pub fetch_extraction_mode: ExtractionMode,
+    /// Media types fetched pages may have (`FETCH_CONTENT_TYPES`, comma separated, `type/*` allowed).
+    pub fetch_content_types: Vec<String>,
+    /// Allow `/search` requests to ask for the raw HTML of fetched pages (`DEBUG_RAW_HTML`).
+    pub debug_raw_html: bool,
+    /// Timeout for a single search engine request in seconds (`SEARCH_TIMEOUT_SECS`).
+    pub search_timeout_secs: u64,
+    /// Limit on a whole search in seconds, across result pages and the reformulated retry
+    /// (`SEARCH_DEADLINE_SECS`). Zero disables it.
+    pub search_deadline_secs: u64,
+    /// Maximum bytes read from a search engine response (`SEARCH_MAX_RESPONSE_BYTES`).
+    pub search_max_response_bytes: usize,
+    /// Search results whose snippet is shorter than this many characters are dropped (`SEARCH_MIN_CONTENT_LEN`).
+    pub search_min_content_len: usize,
+    /// Maximum DuckDuckGo result pages fetched per search (`SEARCH_MAX_PAGES`). Paging stops as soon
+    /// as enough results passing `SEARCH_MIN_CONTENT_LEN` have been collected.
+    pub search_max_pages: usize,
+    /// Rate-limited search requests in a row, retries included, before searching pauses
+    /// (`SEARCH_BREAKER_THRESHOLD`). Zero disables the pause.
+    pub search_breaker_threshold: u32,
+    /// Seconds searching pauses for once the breaker opens (`SEARCH_BREAKER_COOLDOWN_SECS`).
+    pub search_breaker_cooldown_secs: u64,
+    /// DuckDuckGo region for searches that don't name one (`SEARCH_REGION`), e.g. `us-en`.
+    pub search_region: Option<String>,
+    /// DuckDuckGo safe search level for searches that don't name one (`SEARCH_SAFE_SEARCH`).
+    pub search_safe_search: Option<SafeSearch>,
+    /// How search results are ordered (`SEARCH_RANKER`): `term_overlap` or `engine`.
+    pub search_ranker: RankerKind,
+    /// Characters of each search result's content passed to the model by the `websearch` tool
+    /// and `use_search_id` (`SEARCH_TOOL_MAX_CONTENT_CHARS`). Zero disables the limit.
+    pub search_tool_max_content_chars: usize,
+    /// Characters of each result's content returned by `/search` (`SEARCH_API_MAX_CONTENT_CHARS`).
+    /// Zero disables the limit.
+    pub search_api_max_content_chars: usize,
+    /// Exchange rate endpoint for the `convert` tool (`CURRENCY_API_URL`), with `{base}` standing
+    /// for the source currency, e.g. `https://open.er-api.com/v6/latest/{base}`. Currency
+    /// conversion is disabled when unset.
+    #[serde(serialize_with = "redact_url")]
+    pub currency_api_url: Option<String>,
+    /// Patterns that make the `python_invoker` tool reject a script (`PYTHON_DENYLIST`, comma
+    /// separated), e.g. `import os,subprocess,open(,__import__`. Empty disables the check.
+    pub python_denylist: Vec<String>,
+    /// Let `python_invoker` calls with a `session_id` share a persistent interpreter (`PYTHON_SESSIONS`).
+    pub python_sessions: bool,
+    /// Seconds of inactivity after which a Python session is shut down (`PYTHON_SESSION_IDLE_SECS`).
+    pub python_session_idle_secs: u64,
+    /// Live Python sessions allowed at once (`PYTHON_MAX_SESSIONS`). Starting another evicts the
+    /// least recently used idle one.
+    pub python_max_sessions: usize,
+    /// Directory Python scripts run in (`PYTHON_WORKING_DIR`), shared by all runs so files persist
+    /// between calls. When unset, each run or session gets a fresh scratch directory that is
+    /// removed afterwards.
+    pub python_working_dir: Option<PathBuf>,
+    /// Re-prompt once when the final answer claims a lack of data even though tools ran
+    /// (`REPROMPT_IGNORED_TOOLS`).
+    pub reprompt_ignored_tools: bool,
+    /// Case-insensitive phrases that mark an answer as ignoring tool results
+    /// (`IGNORED_TOOLS_PHRASES`, comma separated).
+    pub ignored_tools_phrases: Vec<String>,
+    /// Remove leaked special tokens and tags from final answers and tidy their whitespace
+    /// (`STRIP_ARTIFACTS`).
+    pub strip_artifacts: bool,
+    /// Strings removed by `STRIP_ARTIFACTS` (`ARTIFACT_PATTERNS`, comma separated). An entry
+    /// `family=artifact` applies only to models whose name contains `family`.
+    pub artifact_patterns: Vec<String>,
+    /// Wraps each tool result fed back to the model (`TOOL_RESULT_TEMPLATE`), with `{tool}` and
+    /// `{output}` replaced by the tool name and its output. Results are sent as-is when unset.
+    pub tool_result_template: Option<String>,
+    /// How many times a JSON-mode answer that can't be parsed or repaired is sent back to the
+    /// model with the parse error (`JSON_REPAIR_ATTEMPTS`).
+    pub json_repair_attempts: usize,
+    /// Model calls the tool-calling loop makes before giving up on a final answer (`MAX_TOOL_ITERATIONS`).
+    pub max_tool_iterations: usize,
+    /// At the iteration cap, make one more call without tools asking for a direct answer instead
+    /// of failing (`TOOL_LIMIT_FALLBACK`).
+    pub tool_limit_fallback: bool,
+    /// Number of actix worker threads (`WORKERS`). Defaults to one per CPU when unset.
+    pub workers: Option<usize>,
+    /// Seconds an idle keep-alive connection is held open (`KEEP_ALIVE_SECS`); `0` disables
+    /// keep-alive. actix's default of 5 seconds applies when unset.
+    pub keep_alive_secs: Option<u64>,
+    /// Milliseconds a client has to send its request headers (`CLIENT_REQUEST_TIMEOUT_MS`);
+    /// `0` disables the timeout. actix's default of 5000 applies when unset.
+    pub client_request_timeout_ms: Option<u64>,
+    /// Maximum concurrent connections per worker (`MAX_CONNECTIONS`). actix's default of 25,000
+    /// applies when unset.
+    pub max_connections: Option<usize>,
+    /// Longest `/session/{id}/poll` waits for new events before returning none (`POLL_TIMEOUT_SECS`).
+    pub poll_timeout_secs: u64,
+    /// Requests handled at once before further ones are rejected with 503 (`MAX_IN_FLIGHT_REQUESTS`).
+    /// Unlimited when unset.
+    pub max_in_flight_requests: Option<usize>,
+    /// Compress responses with gzip, deflate, brotli or zstd when the client's `Accept-Encoding`
+    /// allows it (`COMPRESS_RESPONSES`).
+    pub compress_responses: bool,
+    /// JSON lines file recording every tool call the model makes (`AUDIT_LOG_PATH`). Disabled when unset.
+    pub audit_log_path: Option<PathBuf>,
+    /// Size at which the audit log is rotated to `<path>.1` (`AUDIT_LOG_MAX_BYTES`).
+    pub audit_log_max_bytes: u64,
+    /// PEM file with an extra root certificate to trust for outbound HTTPS (`CA_CERT`), e.g. the CA
+    /// of a TLS-inspecting proxy.
+    pub ca_cert: Option<PathBuf>,
+    /// Models that are never sent tools (`NO_TOOL_MODELS`, comma separated). An entry without a tag,
+    /// e.g. `gemma`, matches every tag of that model.
+    pub no_tool_models: Vec<String>,
+    /// Models clients may request (`ALLOWED_MODELS`, comma separated). An entry without a tag
+    /// matches every tag of that model. Empty allows any model.
+    pub allowed_models: Vec<String>,
+    /// Ask Ollama's `/api/show` whether a model supports tools before sending them (`DETECT_TOOL_SUPPORT`).
+    pub detect_tool_support: bool,
+    /// Drive tools through a ReAct text prompt on `/api/generate` instead of the chat API's tool
+    /// calls (`REACT_MODE`). Requests can override it with `react`.
+    pub react_mode: bool,
+    /// Bearer token required by admin routes such as tool invocation (`ADMIN_API_KEY`).
+    /// Admin routes are disabled when unset.
+    #[serde(serialize_with = "redact_secret")]
+    pub admin_api_key: Option<String>,
+}
+
+const DEFAULT_IGNORED_TOOLS_PHRASES: &[&str] = &[
+    "don't have access to",
+    "do not have access to",
+    "don't have real-time",
+    "do not have real-time",
+    "unable to browse",
+    "cannot browse",
+    "can't browse",
+    "my knowledge cutoff",
+    "as of my last update",
+];
+const DEFAULT_ARTIFACT_PATTERNS: &[&str] = &[
+    "<tool_call>",
+    "</tool_call>",
+    "llama3=<|begin_of_text|>",
+    "llama3=<|start_header_id|>",
+    "llama3=<|end_header_id|>",
+    "llama3=<|eot_id|>",
+    "llama3=<|eom_id|>",
+    "qwen=<|im_start|>",
+    "qwen=<|im_end|>",
+    "qwen=<|endoftext|>",
+    "mistral=[INST]",
+    "mistral=[/INST]",
+    "mistral=</s>",
+    "gemma=<start_of_turn>",
+    "gemma=<end_of_turn>",
+    "phi=<|end|>",
+    "phi=<|endoftext|>",
+];
+const DEFAULT_AUDIT_LOG_MAX_BYTES: u64 = 10 * 1024 * 1024;
+const DEFAULT_OLLAMA_QUEUE_TIMEOUT_SECS: u64 = 60;
+const DEFAULT_MAX_TOOL_OUTPUT_CHARS: usize = 16_000;
+const DEFAULT_SEARCH_DEADLINE_SECS: u64 = 30;
+const DEFAULT_SEARCH_MAX_PAGES: usize = 1;
+const DEFAULT_SEARCH_TOOL_MAX_CONTENT_CHARS: usize = 1_000;
+const DEFAULT_SEARCH_API_MAX_CONTENT_CHARS: usize = 20_000;
+const DEFAULT_JSON_REPAIR_ATTEMPTS: usize = 1;
+const DEFAULT_MAX_TOOL_ITERATIONS: usize = 10;
+const DEFAULT_POLL_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_PYTHON_SESSION_IDLE_SECS: u64 = 600;
+const DEFAULT_PYTHON_MAX_SESSIONS: usize = 8;
+const DEFAULT_DOWNLOAD_MAX_BYTES: u64 = 100 * 1024 * 1024;
+
+impl Config {
+    /// Re-reads `.env` and builds the configuration from it layered over the environment, picking
+    /// up values changed since startup. Variables that came from the process environment keep
+    /// precedence, and ones deleted from `.env` keep the value loaded at startup.
+    pub fn reload() -> Result<Self, dotenvy::Error> {
+        let values = read_dotenv()?;
+        DOTENV_OVERLAY.with(|overlay| *overlay.borrow_mut() = Some(values));
+        let _guard = OverlayGuard;
+        Ok(Self::from_env())
+    }
+
+    pub fn from_env() -> Self {
+        let mut ollama_hosts = env_list("OLLAMA_HOSTS");
+        if ollama_hosts.is_empty() {
+            ollama_hosts.push(DEFAULT_OLLAMA_HOST.to_string());
+        }
+
+        let mut fetch_content_types: Vec<String> = env_list("FETCH_CONTENT_TYPES").iter().map(|t| t.to_lowercase()).collect();
+        if fetch_content_types.is_empty() {
+            fetch_content_types = DEFAULT_FETCH_CONTENT_TYPES.iter().map(|t| t.to_string()).collect();
+        }
+
+        let mut ignored_tools_phrases = env_list("IGNORED_TOOLS_PHRASES");
+        if ignored_tools_phrases.is_empty() {
+            ignored_tools_phrases = DEFAULT_IGNORED_TOOLS_PHRASES.iter().map(|p| p.to_string()).collect();
+        }
+
+        let mut artifact_patterns = env_list("ARTIFACT_PATTERNS");
+        if artifact_patterns.is_empty() {
+            artifact_patterns = DEFAULT_ARTIFACT_PATTERNS.iter().map(|p| p.to_string()).collect();
+        }
+
+        Self {
+            ollama_hosts,
+            default_model: env_string("DEFAULT_MODEL"),
+            warmup_model: env_string("WARMUP_MODEL"),
+            max_tool_output_chars: env_parse("MAX_TOOL_OUTPUT_CHARS").unwrap_or(DEFAULT_MAX_TOOL_OUTPUT_CHARS),
+            default_search_count: env_parse("DEFAULT_SEARCH_COUNT").unwrap_or(DEFAULT_SEARCH_COUNT),
+            search_fetch_concurrency: env_parse("SEARCH_FETCH_CONCURRENCY").unwrap_or(DEFAULT_SEARCH_FETCH_CONCURRENCY),
+            ollama_max_concurrent: env_parse::<usize>("OLLAMA_MAX_CONCURRENT").filter(|&n| n > 0),
+            ollama_queue_timeout_secs: env_parse("OLLAMA_QUEUE_TIMEOUT_SECS").unwrap_or(DEFAULT_OLLAMA_QUEUE_TIMEOUT_SECS),
+            ollama_max_response_bytes: env_parse("OLLAMA_MAX_RESPONSE_BYTES").unwrap_or(DEFAULT_OLLAMA_MAX_RESPONSE_BYTES),
+            // Ollama accepts either a duration string or a number of seconds
+            keep_alive: env_string("OLLAMA_KEEP_ALIVE")
+                .map(|v| v.parse::<i64>().map(Value::from).unwrap_or(Value::String(v))),
+            file_root: env_string("FILE_ROOT").map(PathBuf::from),
+            allow_downloads: env_parse("ALLOW_DOWNLOADS").unwrap_or(false),
+            download_max_bytes: env_parse("DOWNLOAD_MAX_BYTES").unwrap_or(DEFAULT_DOWNLOAD_MAX_BYTES),
+            egress_allow_hosts: env_list("EGRESS_ALLOW_HOSTS"),
+            egress_deny_hosts: env_list("EGRESS_DENY_HOSTS"),
+            search_reformulate_empty: env_parse("SEARCH_REFORMULATE_EMPTY").unwrap_or(false),
+            search_allow_bangs: env_parse("SEARCH_ALLOW_BANGS").unwrap_or(false),
+            fetch_extraction_mode: env_parse("FETCH_EXTRACTION_MODE").unwrap_or_default(),
+            fetch_content_types,
+            debug_raw_html: env_parse("DEBUG_RAW_HTML").unwrap_or(false),
+            search_timeout_secs: env_parse("SEARCH_TIMEOUT_SECS").unwrap_or(DEFAULT_SEARCH_TIMEOUT_SECS),
+            search_deadline_secs: env_parse("SEARCH_DEADLINE_SECS").unwrap_or(DEFAULT_SEARCH_DEADLINE_SECS),
+            search_max_response_bytes: env_parse("SEARCH_MAX_RESPONSE_BYTES").unwrap_or(DEFAULT_SEARCH_MAX_RESPONSE_BYTES),
+            search_min_content_len: env_parse("SEARCH_MIN_CONTENT_LEN").unwrap_or(0),
+            search_max_pages: env_parse::<usize>("SEARCH_MAX_PAGES").filter(|&n| n > 0).unwrap_or(DEFAULT_SEARCH_MAX_PAGES),
+            search_breaker_threshold: env_parse("SEARCH_BREAKER_THRESHOLD").unwrap_or(DEFAULT_SEARCH_BREAKER_THRESHOLD),
+            search_breaker_cooldown_secs: env_parse("SEARCH_BREAKER_COOLDOWN_SECS")
+                .unwrap_or(DEFAULT_SEARCH_BREAKER_COOLDOWN_SECS),
+            search_region: env_string("SEARCH_REGION"),
+            search_safe_search: env_parse("SEARCH_SAFE_SEARCH"),
+            search_ranker: env_parse("SEARCH_RANKER").unwrap_or_default(),
+            search_tool_max_content_chars: env_parse("SEARCH_TOOL_MAX_CONTENT_CHARS")
+                .unwrap_or(DEFAULT_SEARCH_TOOL_MAX_CONTENT_CHARS),
+            search_api_max_content_chars: env_parse("SEARCH_API_MAX_CONTENT_CHARS")
+                .unwrap_or(DEFAULT_SEARCH_API_MAX_CONTENT_CHARS),
+            currency_api_url: env_string("CURRENCY_API_URL"),
+            python_denylist: env_list("PYTHON_DENYLIST"),
+            python_sessions: env_parse("PYTHON_SESSIONS").unwrap_or(false),
+            python_session_idle_secs: env_parse("PYTHON_SESSION_IDLE_SECS").unwrap_or(DEFAULT_PYTHON_SESSION_IDLE_SECS),
+            python_working_dir: env_string("PYTHON_WORKING_DIR").map(PathBuf::from),
+            python_max_sessions: env_parse::<usize>("PYTHON_MAX_SESSIONS").filter(|&n| n > 0).unwrap_or(DEFAULT_PYTHON_MAX_SESSIONS),
+            reprompt_ignored_tools: env_parse("REPROMPT_IGNORED_TOOLS").unwrap_or(false),
+            ignored_tools_phrases,
+            strip_artifacts: env_parse("STRIP_ARTIFACTS").unwrap_or(true),
+            artifact_patterns,
+            tool_result_template: env_string("TOOL_RESULT_TEMPLATE"),
+            json_repair_attempts: env_parse("JSON_REPAIR_ATTEMPTS").unwrap_or(DEFAULT_JSON_REPAIR_ATTEMPTS),
+            max_tool_iterations: env_parse::<usize>("MAX_TOOL_ITERATIONS").unwrap_or(DEFAULT_MAX_TOOL_ITERATIONS).max(1),
+            tool_limit_fallback: env_parse("TOOL_LIMIT_FALLBACK").unwrap_or(false),
+            workers: env_parse::<usize>("WORKERS").filter(|&n| n > 0),
+            keep_alive_secs: env_parse("KEEP_ALIVE_SECS"),
+            client_request_timeout_ms: env_parse("CLIENT_REQUEST_TIMEOUT_MS"),
+            max_connections: env_parse::<usize>("MAX_CONNECTIONS").filter(|&n| n > 0),
+            poll_timeout_secs: env_parse("POLL_TIMEOUT_SECS").unwrap_or(DEFAULT_POLL_TIMEOUT_SECS),
+            max_in_flight_requests: env_parse::<usize>("MAX_IN_FLIGHT_REQUESTS").filter(|&n| n > 0),
+            compress_responses: env_parse("COMPRESS_RESPONSES").unwrap_or(true),
+            audit_log_path: env_string("AUDIT_LOG_PATH").map(PathBuf::from),
+            audit_log_max_bytes: env_parse("AUDIT_LOG_MAX_BYTES").unwrap_or(DEFAULT_AUDIT_LOG_MAX_BYTES),
+            ca_cert: env_string("CA_CERT").map(PathBuf::from),
+            no_tool_models: env_list("NO_TOOL_MODELS"),
+            allowed_models: env_list("ALLOWED_MODELS"),
+            detect_tool_support: env_parse("DETECT_TOOL_SUPPORT").unwrap_or(false),
+            react_mode: env_parse("REACT_MODE").unwrap_or(false),
+            admin_api_key: env_string("ADMIN_API_KEY"),
+        }
+    }
+}
+
+/// Variables set before `.env` was first loaded. `.env` never overrides them, on reload either.
+static PROCESS_ENV: OnceLock<HashSet<String>> = OnceLock::new();
+
+/// Loads `.env` into the process environment without overriding variables that are already set.
+/// Only safe at startup, before other threads read the environment; `Config::reload` never writes it.
+pub fn load_dotenv() -> Result<PathBuf, dotenvy::Error> {
+    PROCESS_ENV.get_or_init(|| env::vars_os().filter_map(|(key, _)| key.into_string().ok()).collect());
+    dotenvy::dotenv()
+}
+
+thread_local! {
+    /// `.env` values re-read by `Config::reload`, consulted while it builds the configuration.
+    /// Kept per thread and out of the process environment, which other threads read concurrently.
+    static DOTENV_OVERLAY: RefCell<Option<HashMap<String, String>>> = const { RefCell::new(None) };
+}
+
+/// Clears the overlay however `Config::reload` ends.
+struct OverlayGuard;
+
+impl Drop for OverlayGuard {
+    fn drop(&mut self) {
+        DOTENV_OVERLAY.with(|overlay| overlay.borrow_mut().take());
+    }
+}
+
+/// Parses `.env` without touching the process environment. A missing file reads as empty.
+fn read_dotenv() -> Result<HashMap<String, String>, dotenvy::Error> {
+    let iter = match dotenvy::dotenv_iter() {
+        Ok(iter) => iter,
+        Err(e) if e.not_found() => return Ok(HashMap::new()),
+        Err(e) => return Err(e),
+    };
+    iter.collect()
+}
+
+/// A variable's raw value. While `Config::reload` runs, a variable from `.env` takes the re-read
+/// value unless it came from the process environment.
+fn env_var(key: &str) -> Option<String> {
+    let from_process_env = PROCESS_ENV.get().is_some_and(|keys| keys.contains(key));
+    if !from_process_env {
+        let reloaded = DOTENV_OVERLAY.with(|overlay| overlay.borrow().as_ref().and_then(|values| values.get(key).cloned()));
+        if reloaded.is_some() {
+            return reloaded;
+        }
+    }
+    env::var(key).ok()
+}
+
+/// Reads a variable, treating unset and blank values alike.
+pub fn env_string(key: &str) -> Option<String> {
+    env_var(key)
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+/// Reads a comma separated list, skipping blank entries.
+pub fn env_list(key: &str) -> Vec<String> {
+    env_string(key)
+        .map(|v| {
+            v.split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Reads and parses a variable, logging and ignoring values that fail to parse.
+pub fn env_parse<T: FromStr>(key: &str) -> Option<T> {
+    let value = env_string(key)?;
+    match value.parse() {
+        Ok(parsed) => Some(parsed),
+        Err(_) => {
+            error!("Ignoring invalid value for {}: {:?}", key, value);
+            None
+        }
+    }
+}
+
+const REDACTED: &str = "[redacted]";
+
+/// Serializes a set secret as a placeholder, so its presence is visible but not its value.
+fn redact_secret<S: Serializer>(value: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(_) => serializer.serialize_str(REDACTED),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Hides credentials and query parameter values (which often carry API keys) in a URL.
+fn sanitize_url(raw: &str) -> String {
+    let mut url = match url::Url::parse(raw) {
+        Ok(url) => url,
+        Err(_) => return REDACTED.to_string(),
+    };
+    if !url.username().is_empty() {
+        let _ = url.set_username(REDACTED);
+    }
+    if url.password().is_some() {
+        let _ = url.set_password(Some(REDACTED));
+    }
+    if url.query().is_some() {
+        let keys: Vec<String> = url.query_pairs().map(|(key, _)| key.into_owned()).collect();
+        url.query_pairs_mut()
+            .clear()
+            .extend_pairs(keys.iter().map(|key| (key.as_str(), REDACTED)));
+    }
+    // Keep placeholders such as `{base}` readable
+    url.to_string().replace("%7B", "{").replace("%7D", "}")
+}
+
+fn redact_url<S: Serializer>(value: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(url) => serializer.serialize_str(&sanitize_url(url)),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn redact_urls<S: Serializer>(values: &[String], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(values.iter().map(|url| sanitize_url(url)))
+}
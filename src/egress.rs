@@ -0,0 +1,82 @@
+use thiserror::Error;
+use url::Url;
+
+use crate::config::Config;
+
+/// Redirects followed per request, as reqwest does by default.
+const MAX_REDIRECTS: usize = 10;
+
+#[derive(Error, Debug)]
+pub enum EgressError {
+    #[error("Invalid URL {0:?}: {1}")]
+    InvalidUrl(String, url::ParseError),
+    #[error("URL {0:?} has no host")]
+    MissingHost(String),
+    #[error("Outbound requests to host {0:?} are not allowed by the egress policy")]
+    Denied(String),
+}
+
+/// Which hosts outbound HTTP clients may contact. A host matches an entry when it equals it
+/// or is a subdomain of it. Denied hosts take precedence; an empty allowlist allows any host.
+#[derive(Debug, Clone, Default)]
+pub struct EgressPolicy {
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+fn host_matches(host: &str, entry: &str) -> bool {
+    host == entry || host.ends_with(&format!(".{}", entry))
+}
+
+impl EgressPolicy {
+    pub fn new(allow: Vec<String>, deny: Vec<String>) -> Self {
+        let normalize = |hosts: Vec<String>| -> Vec<String> {
+            hosts.into_iter()
+                .map(|h| h.trim().trim_start_matches("*.").trim_start_matches('.').to_lowercase())
+                .filter(|h| !h.is_empty())
+                .collect()
+        };
+        Self {
+            allow: normalize(allow),
+            deny: normalize(deny),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(config.egress_allow_hosts.clone(), config.egress_deny_hosts.clone())
+    }
+
+    pub fn check_host(&self, host: &str) -> Result<(), EgressError> {
+        let host = host.trim_end_matches('.').to_lowercase();
+        let denied = self.deny.iter().any(|entry| host_matches(&host, entry));
+        let allowed = self.allow.is_empty() || self.allow.iter().any(|entry| host_matches(&host, entry));
+        if denied || !allowed {
+            return Err(EgressError::Denied(host));
+        }
+        Ok(())
+    }
+
+    /// Checks a URL before any connection is attempted.
+    pub fn check_url(&self, url: &str) -> Result<(), EgressError> {
+        let parsed = Url::parse(url).map_err(|e| EgressError::InvalidUrl(url.to_string(), e))?;
+        let host = parsed
+            .host_str()
+            .ok_or_else(|| EgressError::MissingHost(url.to_string()))?;
+        // IPv6 hosts are reported in brackets
+        self.check_host(host.trim_start_matches('[').trim_end_matches(']'))
+    }
+
+    /// A redirect policy that checks every hop against this policy before it is requested, so a
+    /// permitted URL can't redirect a client to a denied or internal host.
+    pub fn redirect_policy(self) -> reqwest::redirect::Policy {
+        reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                return attempt.error("too many redirects");
+            }
+            match self.check_url(attempt.url().as_str()) {
+                Ok(()) => attempt.follow(),
+                Err(e) => attempt.error(e),
+            }
+        })
+    }
+}
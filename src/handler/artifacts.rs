@@ -0,0 +1,45 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+static TRAILING_SPACE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?m)[ \t]+$").unwrap());
+static BLANK_LINES: Lazy<Regex> = Lazy::new(|| Regex::new(r"\n{3,}").unwrap());
+
+/// Literal strings removed from final answers, each either for every model or for one model family.
+pub struct ArtifactFilter {
+    /// `(family, artifact)`, where a `None` family applies to every model.
+    artifacts: Vec<(Option<String>, String)>,
+}
+
+/// Splits a `family=artifact` entry. The part before `=` only counts as a family when it looks
+/// like a model name, so artifacts that themselves contain `=` can still be listed bare.
+fn parse_entry(entry: &str) -> (Option<String>, String) {
+    if let Some((family, artifact)) = entry.split_once('=') {
+        let is_family = !family.is_empty()
+            && family.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'));
+        if is_family && !artifact.is_empty() {
+            return (Some(family.to_lowercase()), artifact.to_string());
+        }
+    }
+    (None, entry.to_string())
+}
+
+impl ArtifactFilter {
+    pub fn new(entries: &[String]) -> Self {
+        Self { artifacts: entries.iter().map(|entry| parse_entry(entry)).collect() }
+    }
+
+    /// Removes the artifacts that apply to `model` (those for every model, and those whose family
+    /// the model name contains), strips trailing spaces from each line, collapses runs of blank
+    /// lines into one and trims the result. Indentation is kept, so code blocks survive.
+    pub fn clean(&self, model: &str, text: &str) -> String {
+        let model = model.to_lowercase();
+        let mut text = text.to_string();
+        for (family, artifact) in &self.artifacts {
+            if family.as_ref().is_none_or(|family| model.contains(family.as_str())) {
+                text = text.replace(artifact.as_str(), "");
+            }
+        }
+        let text = TRAILING_SPACE.replace_all(&text, "");
+        BLANK_LINES.replace_all(&text, "\n\n").trim().to_string()
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+/// Signals that a chat request should stop. Checked between loop iterations and awaited
+/// alongside model calls and tool runs.
+#[derive(Default)]
+pub struct CancelToken {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// The raw flag, for blocking code that polls for cancellation.
+    pub fn flag(&self) -> &AtomicBool {
+        &self.cancelled
+    }
+
+    /// Resolves once the token is cancelled.
+    pub async fn cancelled(&self) {
+        let notified = self.notify.notified();
+        tokio::pin!(notified);
+        // Register for the notification before checking, so a cancel in between isn't missed
+        notified.as_mut().enable();
+        if self.is_cancelled() {
+            return;
+        }
+        notified.await;
+    }
+}
+
+/// Tokens of the chat requests currently running under a client-supplied `request_id`.
+#[derive(Default)]
+pub struct CancellationRegistry {
+    active: Mutex<HashMap<String, Arc<CancelToken>>>,
+}
+
+impl CancellationRegistry {
+    /// Registers a request, returning `None` if another request with the same id is running.
+    /// The id is released when the returned guard is dropped.
+    pub fn register(&self, request_id: &str) -> Option<ActiveRequest<'_>> {
+        let mut active = self.active.lock().unwrap();
+        if active.contains_key(request_id) {
+            return None;
+        }
+        let token = Arc::new(CancelToken::default());
+        active.insert(request_id.to_string(), token.clone());
+        Some(ActiveRequest {
+            registry: self,
+            request_id: request_id.to_string(),
+            token,
+        })
+    }
+
+    /// Cancels the running request with `request_id`. Returns false if there is none.
+    pub fn cancel(&self, request_id: &str) -> bool {
+        match self.active.lock().unwrap().get(request_id) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// A registered request; unregisters itself when dropped.
+pub struct ActiveRequest<'a> {
+    registry: &'a CancellationRegistry,
+    request_id: String,
+    pub token: Arc<CancelToken>,
+}
+
+impl Drop for ActiveRequest<'_> {
+    fn drop(&mut self) {
+        self.registry.active.lock().unwrap().remove(&self.request_id);
+    }
+}
@@ -0,0 +1,53 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::Value;
+
+use crate::llm::ollama::{FunctionCall, ToolCall};
+
+static TOOL_CALL_TAG: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?s)<tool_call>\s*(.*?)\s*</tool_call>").unwrap());
+static FENCED_JSON: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?s)```(?:json|tool_call)?\s*(\{.*?\})\s*```").unwrap());
+
+/// Recovers tool calls that a model wrote into its message content instead of Ollama's
+/// `tool_calls` field: `<tool_call>{...}</tool_call>` tags, fenced JSON blocks, or a message that
+/// is nothing but a JSON call. Only calls to one of `known_tools` are returned, so ordinary JSON
+/// in an answer isn't mistaken for a call.
+pub fn parse_embedded_tool_calls(content: &str, known_tools: &[String]) -> Vec<ToolCall> {
+    let mut candidates: Vec<&str> = TOOL_CALL_TAG
+        .captures_iter(content)
+        .filter_map(|c| c.get(1).map(|m| m.as_str()))
+        .collect();
+    if candidates.is_empty() {
+        candidates = FENCED_JSON
+            .captures_iter(content)
+            .filter_map(|c| c.get(1).map(|m| m.as_str()))
+            .collect();
+    }
+    if candidates.is_empty() && content.trim_start().starts_with('{') {
+        candidates.push(content.trim());
+    }
+
+    candidates
+        .into_iter()
+        .filter_map(|candidate| serde_json::from_str::<Value>(candidate).ok())
+        .filter_map(|value| to_tool_call(&value))
+        .filter(|call| known_tools.contains(&call.function.name))
+        .collect()
+}
+
+/// Accepts `{"name", "arguments"}`, `{"name", "parameters"}` and the OpenAI style
+/// `{"function": {"name", "arguments"}}`, with arguments as an object or a JSON string.
+fn to_tool_call(value: &Value) -> Option<ToolCall> {
+    let call = value.get("function").filter(|f| f.is_object()).unwrap_or(value);
+    let name = call.get("name")?.as_str()?.to_string();
+    let arguments = match call.get("arguments").or_else(|| call.get("parameters")) {
+        Some(Value::String(raw)) => serde_json::from_str(raw).unwrap_or(Value::Null),
+        Some(arguments) => arguments.clone(),
+        None => Value::Null,
+    };
+    Some(ToolCall {
+        id: None,
+        function: FunctionCall { name, arguments },
+    })
+}
@@ -0,0 +1,49 @@
+use crate::llm::ollama::ChatMessage;
+
+/// Checks a client-supplied conversation before it is sent to the model: only user, assistant
+/// and tool turns, user and assistant turns alternating, tool results only after an assistant
+/// turn that requested tools, and a user message last.
+pub fn validate_history(messages: &[ChatMessage]) -> Result<(), String> {
+    let Some(first) = messages.first() else {
+        return Err("\"messages\" must not be empty".to_string());
+    };
+    if first.role != "user" {
+        return Err(format!("The first message must be from the user, got role \"{}\"", first.role));
+    }
+
+    let mut previous: Option<&ChatMessage> = None;
+    for (i, message) in messages.iter().enumerate() {
+        let valid = match (previous.map(|p| p.role.as_str()), message.role.as_str()) {
+            (_, "system") => {
+                return Err(format!(
+                    "Message {} has role \"system\"; the server supplies the system prompt",
+                    i
+                ))
+            }
+            (None, "user") => true,
+            (Some("assistant"), "user") | (Some("tool"), "user") => true,
+            (Some("user"), "assistant") | (Some("tool"), "assistant") => true,
+            (Some("assistant"), "tool") => previous
+                .and_then(|p| p.tool_calls.as_ref())
+                .map(|calls| !calls.is_empty())
+                .unwrap_or(false),
+            (Some("tool"), "tool") => true,
+            (_, "user") | (_, "assistant") | (_, "tool") => false,
+            (_, other) => return Err(format!("Message {} has unknown role \"{}\"", i, other)),
+        };
+        if !valid {
+            return Err(format!(
+                "Message {} with role \"{}\" cannot follow a \"{}\" message",
+                i,
+                message.role,
+                previous.map(|p| p.role.as_str()).unwrap_or("")
+            ));
+        }
+        previous = Some(message);
+    }
+
+    match messages.last() {
+        Some(last) if last.role == "user" => Ok(()),
+        _ => Err("The last message must be from the user".to_string()),
+    }
+}
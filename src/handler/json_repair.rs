@@ -0,0 +1,113 @@
+use serde_json::Value;
+
+/// Strips a surrounding Markdown code fence, with or without a language tag.
+fn strip_code_fence(text: &str) -> &str {
+    let trimmed = text.trim();
+    let Some(inner) = trimmed.strip_prefix("```") else {
+        return trimmed;
+    };
+    let inner = inner.strip_suffix("```").unwrap_or(inner);
+    // Drop the language tag line, e.g. "json"
+    match inner.find('\n') {
+        Some(newline) if !inner[..newline].trim_start().starts_with(['{', '[']) => inner[newline + 1..].trim(),
+        _ => inner.trim(),
+    }
+}
+
+/// The text from the first `{` or `[`, dropping any prose the model put before the JSON.
+fn from_first_bracket(text: &str) -> &str {
+    match text.find(['{', '[']) {
+        Some(start) => &text[start..],
+        None => text,
+    }
+}
+
+/// Removes commas directly before a closing `}` or `]`, outside of strings.
+fn remove_trailing_commas(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, &c) in chars.iter().enumerate() {
+        if in_string {
+            out.push(c);
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+        if c == '"' {
+            in_string = true;
+        } else if c == ',' {
+            let next = chars[i + 1..].iter().find(|c| !c.is_whitespace());
+            if matches!(next, Some('}') | Some(']')) {
+                continue;
+            }
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Closes an unterminated string and any objects or arrays left open at the end, and drops
+/// anything after the top-level value is complete.
+fn balance_brackets(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut open = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in text.chars() {
+        out.push(c);
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => open.push('}'),
+            '[' => open.push(']'),
+            '}' | ']' => {
+                open.pop();
+                if open.is_empty() {
+                    return out;
+                }
+            }
+            _ => {}
+        }
+    }
+    if in_string {
+        out.push('"');
+    }
+    while let Some(close) = open.pop() {
+        out.push(close);
+    }
+    out
+}
+
+/// Parses a model's JSON answer, repairing the usual near misses: a Markdown code fence, prose
+/// around the JSON, trailing commas and missing closing brackets. Returns the original parse
+/// error when the text can't be repaired.
+pub fn parse_lenient(text: &str) -> Result<Value, String> {
+    let original_error = match serde_json::from_str::<Value>(text) {
+        Ok(value) => return Ok(value),
+        Err(e) => e.to_string(),
+    };
+
+    let candidate = from_first_bracket(strip_code_fence(text)).trim();
+    let repaired = balance_brackets(&remove_trailing_commas(candidate));
+    // Removing trailing commas again catches ones exposed by the closing brackets just added
+    [candidate.to_string(), repaired.clone(), remove_trailing_commas(&repaired)]
+        .iter()
+        .find_map(|attempt| serde_json::from_str::<Value>(attempt).ok())
+        .ok_or(original_error)
+}
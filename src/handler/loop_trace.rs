@@ -0,0 +1,101 @@
+use log::info;
+use serde::Serialize;
+use std::time::Duration;
+
+/// Why the tool-calling loop in `handle_chat` stopped.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TerminationReason {
+    /// The model answered without requesting any tools.
+    FinalAnswer,
+    /// The model produced no content, even after being nudged.
+    EmptyResponse,
+    /// The call to Ollama failed.
+    ModelError,
+    /// A tool call failed.
+    ToolError,
+    /// The client cancelled the request through `/chat/cancel`.
+    Cancelled,
+    /// The loop ran out of iterations (or ReAct steps) before the model gave a final answer.
+    StepLimit,
+    /// The loop ran out of iterations and the answer came from a final call without tools.
+    Fallback,
+}
+
+/// How long one step of a tool call took, e.g. the scrape of a web search.
+#[derive(Debug, Clone, Serialize)]
+pub struct PhaseTiming {
+    pub phase: &'static str,
+    pub ms: u64,
+}
+
+impl PhaseTiming {
+    pub fn new(phase: &'static str, elapsed: Duration) -> Self {
+        Self { phase, ms: elapsed.as_millis() as u64 }
+    }
+}
+
+/// How long a tool call took.
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolTiming {
+    /// The iteration that requested the call, counting from 1.
+    pub iteration: usize,
+    pub tool: String,
+    pub ms: u64,
+    /// Steps timed separately within the call.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub phases: Vec<PhaseTiming>,
+}
+
+/// Summary of a single chat request's tool-calling loop, for debugging.
+#[derive(Debug, Clone, Serialize)]
+pub struct LoopTrace {
+    /// Number of model calls made.
+    pub iterations: usize,
+    /// Tools requested by the model in each iteration, in order.
+    pub tools_per_iteration: Vec<Vec<String>>,
+    /// Duration of each tool call that produced a result, in the order they ran.
+    pub tool_timings: Vec<ToolTiming>,
+    pub termination: Option<TerminationReason>,
+}
+
+impl LoopTrace {
+    pub fn new() -> Self {
+        Self {
+            iterations: 0,
+            tools_per_iteration: Vec::new(),
+            tool_timings: Vec::new(),
+            termination: None,
+        }
+    }
+
+    /// Records a model call and the tools it requested.
+    pub fn record_iteration(&mut self, tools: Vec<String>) {
+        self.iterations += 1;
+        self.tools_per_iteration.push(tools);
+    }
+
+    /// Records how long a tool requested by the latest iteration took.
+    pub fn record_tool(&mut self, tool: &str, elapsed: Duration, phases: Vec<PhaseTiming>) {
+        self.tool_timings.push(ToolTiming {
+            iteration: self.iterations,
+            tool: tool.to_string(),
+            ms: elapsed.as_millis() as u64,
+            phases,
+        });
+    }
+
+    /// Sets the termination reason and logs the trace. Returns the trace if it should be
+    /// included in the response.
+    pub fn finish(mut self, reason: TerminationReason, include: bool) -> Option<LoopTrace> {
+        self.termination = Some(reason);
+        info!(
+            "Chat loop finished after {} iteration(s) ({:?}), tools per iteration: {:?}, {} ms in tools",
+            self.iterations,
+            reason,
+            self.tools_per_iteration,
+            self.tool_timings.iter().map(|timing| timing.ms).sum::<u64>()
+        );
+        if include { Some(self) } else { None }
+    }
+}
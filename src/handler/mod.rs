@@ -1,2 +1,17 @@
 pub mod query_handler;
+pub mod artifacts;
+pub mod loop_trace;
+pub mod options;
+pub mod history;
+pub mod embedded_tool_calls;
+pub mod cancellation;
+pub mod search_cache;
+pub mod session_events;
+pub mod tool_schema;
+pub mod tokens;
+pub mod tool_registry;
+pub mod json_repair;
+pub mod react;
+pub mod request_log;
+pub mod progress;
 pub use query_handler::QueryHandler;
@@ -0,0 +1,57 @@
+use serde_json::{Map, Value};
+
+/// Allowed range for a known numeric Ollama option.
+struct OptionRange {
+    name: &'static str,
+    min: f64,
+    max: f64,
+    integer: bool,
+}
+
+const KNOWN_OPTIONS: &[OptionRange] = &[
+    OptionRange { name: "temperature", min: 0.0, max: 2.0, integer: false },
+    OptionRange { name: "top_p", min: 0.0, max: 1.0, integer: false },
+    OptionRange { name: "min_p", min: 0.0, max: 1.0, integer: false },
+    OptionRange { name: "top_k", min: 0.0, max: 1000.0, integer: true },
+    OptionRange { name: "num_ctx", min: 1.0, max: 262_144.0, integer: true },
+    OptionRange { name: "num_predict", min: -2.0, max: 131_072.0, integer: true },
+    OptionRange { name: "repeat_penalty", min: 0.0, max: 10.0, integer: false },
+    OptionRange { name: "repeat_last_n", min: -1.0, max: 262_144.0, integer: true },
+    OptionRange { name: "presence_penalty", min: -2.0, max: 2.0, integer: false },
+    OptionRange { name: "frequency_penalty", min: -2.0, max: 2.0, integer: false },
+    OptionRange { name: "seed", min: i64::MIN as f64, max: i64::MAX as f64, integer: true },
+];
+
+/// Checks known Ollama model options against sane ranges. Unknown keys are left alone so
+/// newer Ollama options aren't blocked. Returns a message describing the first invalid option.
+pub fn validate_options(options: &Map<String, Value>) -> Result<(), String> {
+    for range in KNOWN_OPTIONS {
+        let Some(value) = options.get(range.name) else {
+            continue;
+        };
+        let number = value
+            .as_f64()
+            .ok_or_else(|| format!("Option \"{}\" must be a number, got {}", range.name, value))?;
+        if range.integer && !(value.is_i64() || value.is_u64()) {
+            return Err(format!("Option \"{}\" must be an integer, got {}", range.name, value));
+        }
+        if number < range.min || number > range.max {
+            return Err(format!(
+                "Option \"{}\" must be between {} and {}, got {}",
+                range.name, range.min, range.max, value
+            ));
+        }
+    }
+
+    if let Some(stop) = options.get("stop") {
+        let valid = stop
+            .as_array()
+            .map(|items| items.iter().all(|item| item.is_string()))
+            .unwrap_or(false);
+        if !valid {
+            return Err("Option \"stop\" must be an array of strings".to_string());
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+
+use super::session_events::Session;
+
+/// Longest argument excerpt shown in a `running_tool` event.
+const DETAIL_MAX_CHARS: usize = 100;
+/// Arguments that say what a tool call is about, checked in this order.
+const DETAIL_ARGUMENTS: &[&str] = &["query", "url", "path", "expression", "operation", "location", "data"];
+
+/// A milestone of the tool-calling loop, reported while it runs.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "stage", rename_all = "snake_case")]
+pub enum ChatEvent {
+    /// A model call is about to be made; `iteration` counts from 1.
+    CallingModel { iteration: usize },
+    /// A tool is about to run. `detail` is its main argument, e.g. the search query.
+    RunningTool { tool: String, detail: Option<String> },
+    /// A tool finished. `sources` is how many documents it surfaced, e.g. search results, and
+    /// `ms` how long it ran.
+    ToolFinished { tool: String, sources: usize, chars: usize, ms: u64 },
+}
+
+impl ChatEvent {
+    pub fn running_tool(tool: &str, args: &Value) -> Self {
+        let detail = DETAIL_ARGUMENTS
+            .iter()
+            .find_map(|name| args.get(*name).and_then(|v| v.as_str()))
+            .map(|value| value.chars().take(DETAIL_MAX_CHARS).collect());
+        ChatEvent::RunningTool { tool: tool.to_string(), detail }
+    }
+}
+
+/// Where loop milestones go. The default discards them, so callers that don't stream pay nothing.
+#[derive(Default)]
+pub struct Progress {
+    sender: Option<UnboundedSender<ChatEvent>>,
+    /// Also records events for `/session/{id}/poll` when the chat has a `request_id`.
+    session: Option<Arc<Session>>,
+}
+
+impl Progress {
+    /// A progress sink and the stream of events sent to it. The stream ends when the sink is dropped.
+    pub fn channel() -> (Self, UnboundedReceiver<ChatEvent>) {
+        let (sender, receiver) = mpsc::unbounded();
+        (Self { sender: Some(sender), session: None }, receiver)
+    }
+
+    /// A sink that sends to the same stream as this one and also records into `session`.
+    pub fn with_session(&self, session: Arc<Session>) -> Self {
+        Self { sender: self.sender.clone(), session: Some(session) }
+    }
+
+    pub fn emit(&self, event: ChatEvent) {
+        if let Some(session) = &self.session {
+            session.push(serde_json::to_value(&event).unwrap_or_default());
+        }
+        if let Some(sender) = &self.sender {
+            // The receiver is gone once the client disconnects; the loop carries on regardless
+            let _ = sender.unbounded_send(event);
+        }
+    }
+}
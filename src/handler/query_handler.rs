@@ -1,44 +1,415 @@
-use actix_web::{web, HttpResponse, Error};
+use actix_web::{body::{BoxBody, MessageBody}, http::{header, StatusCode}, web, HttpResponse, HttpResponseBuilder, Error};
 use chrono::Local;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use log::{info, error};
 use std::fs;
+use std::path::Path;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
 
-use crate::llm::ollama::{OllamaClient, ChatMessage, Tool, ChatResponse};
-use crate::tools::{WebSearchClient, PythonInvoker};
+use super::artifacts::ArtifactFilter;
+use super::cancellation::{CancelToken, CancellationRegistry};
+use super::embedded_tool_calls::parse_embedded_tool_calls;
+use super::history::validate_history;
+use super::json_repair::parse_lenient;
+use super::react::{build_prompt, parse_step, ReactStep, OBSERVATION_STOP};
+use super::request_log::RequestLog;
+use super::progress::{ChatEvent, Progress};
+use super::search_cache::SearchCache;
+use super::session_events::SessionEvents;
+use super::tool_registry::ToolRegistry;
+use super::tokens::estimate_tokens;
+use super::tool_schema::validate_arguments;
+use super::loop_trace::{LoopTrace, PhaseTiming, TerminationReason};
+use super::options::validate_options;
+use crate::audit::{AuditEntry, AuditLog};
+use crate::config::Config;
+use crate::llm::ollama::{OllamaClient, OllamaError, ChatMessage, Tool, ToolCall, FunctionCall, ChatResponse, ChatParams};
+use crate::tools::weather::{WeatherError, WeatherUnits};
+use crate::tools::websearch::{truncate_contents, SafeSearch, SearchFilters, SearchResult};
+use crate::tools::{codec, data_loader, datetime, diff, extract, feed, hashing, json_query, precise_math, qrcode, template};
+
+const SYSTEM_PROMPT_PATH: &str = "src/handler/system_prompt.txt";
+
+/// Follow-up sent once when the model ends the loop without producing any content.
+const EMPTY_RESPONSE_NUDGE: &str = "You did not provide an answer. Please provide a final answer to the user's question based on the conversation and tool results so far.";
+
+/// Follow-up sent once when the final answer claims a lack of data despite tool results being available.
+const IGNORED_TOOLS_NUDGE: &str = "The tool results above contain the information you need. Answer the user's question using those tool results instead of saying you lack access to the data.";
+
+/// Follow-up sent when a JSON-mode answer can't be parsed, followed by the parse error.
+const INVALID_JSON_NUDGE: &str = "Your answer was not valid JSON. Reply again with only the corrected JSON and nothing else.";
+
+/// Sent with the final tool-free call once the loop reaches `MAX_TOOL_ITERATIONS`.
+const TOOL_LIMIT_NUDGE: &str = "You have used all available tool calls. Do not call any more tools. Answer the user's question directly now, using the conversation and tool results so far.";
+
+/// Model turns allowed in ReAct mode before giving up on a final answer.
+const REACT_MAX_STEPS: usize = 10;
 
 #[derive(Debug, Deserialize)]
 pub struct ChatRequest {
+    /// The user's message. Optional when `messages` is supplied.
+    #[serde(default)]
     pub message: String,
-    pub model: String,
+    /// Full conversation history managed by the client. When present it is used instead of
+    /// starting a new conversation, with `message` (if any) appended as the final user turn.
+    pub messages: Option<Vec<ChatMessage>>,
+    /// Falls back to the configured default model when omitted.
+    pub model: Option<String>,
+    /// Overrides the configured keep_alive for this request, e.g. `"30m"` or `-1`.
+    pub keep_alive: Option<serde_json::Value>,
+    /// Return the loop trace (iterations, tools called, termination reason) in the response.
+    pub include_trace: Option<bool>,
+    /// Ollama model options (e.g. `temperature`, `num_ctx`), passed through after validation.
+    pub options: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Stop sequences for generation, sent as Ollama's `options.stop`. Takes precedence over a
+    /// `stop` given in `options`.
+    pub stop: Option<Vec<String>>,
+    /// Answer from the results of an earlier `/search` (its `X-Search-Id`) instead of searching again.
+    pub use_search_id: Option<String>,
+    /// Client-chosen id under which the request can be cancelled through `/chat/cancel`.
+    pub request_id: Option<String>,
+    /// Return the reasoning trace of the final answer, for models that produce one.
+    pub include_thinking: Option<bool>,
+    /// Ask for a JSON answer: `"json"` or a JSON schema, sent as Ollama's `format`. Slightly
+    /// malformed answers are repaired, or sent back to the model with the parse error.
+    pub format: Option<Value>,
+    /// Use ReAct mode for this request, overriding `REACT_MODE`.
+    pub react: Option<bool>,
+    /// Names of the tools the model may use for this request; all tools when omitted. An empty
+    /// list means pure chat: one model call without tools, and no tool-calling loop.
+    pub allowed_tools: Option<Vec<String>>,
+    /// Log this request's messages, raw model responses and tool calls at info level instead of debug.
+    pub debug: Option<bool>,
+    /// Return the fully assembled system prompt the model was given.
+    pub include_system_prompt: Option<bool>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Default, Serialize)]
 pub struct ChatApiResponse {
     pub response: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace: Option<LoopTrace>,
+    /// Titles and URLs of every search result shown to the model during the conversation.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<Source>,
+    /// The model's reasoning for the final answer, when requested with `include_thinking`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<String>,
+    /// The system prompt as sent to the model, when requested with `include_system_prompt`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    /// Set when the connection to Ollama dropped mid-answer and `response` is what arrived before.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub incomplete: bool,
+    /// Images tools produced during the conversation, such as QR codes and plots.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<ToolImage>,
+}
+
+/// An image produced by a tool, returned to the client alongside the answer.
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolImage {
+    pub tool: String,
+    /// Base64 encoded image bytes, PNG for `generate_qr`.
+    pub data: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CancelRequest {
+    pub request_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenizeRequest {
+    pub text: String,
+    /// Defaults to DEFAULT_MODEL; the estimate falls back to a generic ratio when neither is set.
+    pub model: Option<String>,
+}
+
+/// Non-standard "client closed request" status, so clients can tell a cancelled request from a failed one.
+fn status_cancelled() -> StatusCode {
+    StatusCode::from_u16(499).unwrap()
+}
+
+/// HTTP status to report for an Ollama failure.
+fn status_for_ollama_error(e: &OllamaError) -> StatusCode {
+    match e {
+        OllamaError::ModelNotFound(_) => StatusCode::NOT_FOUND,
+        OllamaError::ContextLengthExceeded(_) | OllamaError::ToolsNotSupported(_) => StatusCode::BAD_REQUEST,
+        OllamaError::OutOfMemory(_) | OllamaError::NoBackendAvailable | OllamaError::Busy(_) => StatusCode::SERVICE_UNAVAILABLE,
+        OllamaError::RequestError(_) | OllamaError::ApiError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        OllamaError::ResponseTooLarge(_) => StatusCode::BAD_GATEWAY,
+    }
+}
+
+/// The system prompt as last sent to the model, if the request asked for it.
+fn requested_system_prompt(req: &ChatRequest, messages: &[ChatMessage]) -> Option<String> {
+    if !req.include_system_prompt.unwrap_or(false) {
+        return None;
+    }
+    messages.iter().find(|m| m.role == "system").map(|m| m.content.clone())
+}
+
+/// Whether `model` is in `list`. An entry without a tag, e.g. `gemma`, matches every tag of that model.
+fn matches_model_list(list: &[String], model: &str) -> bool {
+    let base_name = model.split(':').next().unwrap_or(model);
+    list.iter().any(|m| m == model || (!m.contains(':') && m == base_name))
+}
+
+/// Gives each call without an Ollama-assigned `id` the next one from the conversation's counter,
+/// so the model can tell apart the results of two calls to the same tool.
+fn assign_call_ids(calls: &mut [ToolCall], next_id: &mut usize) {
+    for call in calls.iter_mut().filter(|call| call.id.is_none()) {
+        call.id = Some(format!("call_{}", next_id));
+        *next_id += 1;
+    }
+}
+
+/// Tool calls already in the conversation, where generated ids continue from so they stay unique
+/// when a client sends earlier turns back.
+fn count_tool_calls(messages: &[ChatMessage]) -> usize {
+    messages.iter().filter_map(|m| m.tool_calls.as_ref()).map(Vec::len).sum()
+}
+
+/// Formats search results as the text shown to the model.
+fn format_search_results(results: &[SearchResult]) -> String {
+    results.iter()
+        .map(|r| match &r.published {
+            Some(published) => format!("Title: {}\nURL: {}\nPublished: {}\nContent: {}\n---",
+                r.title, r.url, published, r.content),
+            None => format!("Title: {}\nURL: {}\nContent: {}\n---",
+                r.title, r.url, r.content),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn search_sources(results: &[SearchResult]) -> Vec<Source> {
+    results.iter()
+        .map(|r| Source { title: r.title.clone(), url: r.url.clone() })
+        .collect()
+}
+
+/// Starts an error response for an Ollama failure. When Ollama is busy, `Retry-After` tells the
+/// client how long the queue wait was.
+fn ollama_error_response(e: &OllamaError) -> HttpResponseBuilder {
+    let mut builder = HttpResponse::build(status_for_ollama_error(e));
+    if let OllamaError::Busy(waited) = e {
+        builder.insert_header((header::RETRY_AFTER, waited.as_secs().max(1).to_string()));
+    }
+    builder
+}
+
+/// Caps `output` at `max_chars` characters, noting how much was cut.
+fn truncate_tool_output(output: String, max_chars: usize) -> String {
+    let total_chars = output.chars().count();
+    if total_chars <= max_chars {
+        return output;
+    }
+    let truncated: String = output.chars().take(max_chars).collect();
+    format!("{}\n[truncated {} chars]", truncated, total_chars - max_chars)
+}
+
+/// Fills `{tool}` and `{output}` in the configured tool result template, or returns the output unchanged.
+fn apply_tool_result_template(template: Option<&str>, tool: &str, output: String) -> String {
+    match template {
+        Some(template) => template.replace("{tool}", tool).replace("{output}", &output),
+        None => output,
+    }
+}
+
+/// Result of running a single tool call, ready to be fed back to the model.
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolOutput {
+    pub name: String,
+    pub content: String,
+    /// Base64 encoded images produced by the tool, attached to the tool message.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<String>,
+    /// Documents surfaced to the model by this tool, reported back as citations.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<Source>,
+    /// Wall time of the whole call, set by `process_tool_calls`.
+    #[serde(skip)]
+    pub elapsed: std::time::Duration,
+    /// Steps the tool timed separately, e.g. the scrape of a web search.
+    #[serde(skip)]
+    pub phases: Vec<PhaseTiming>,
+    /// The `id` of the call that produced this output, set by `process_tool_calls`.
+    #[serde(skip)]
+    pub call_id: Option<String>,
+}
+
+impl ToolOutput {
+    /// A plain text result with no images or sources.
+    pub fn text(name: &str, content: String) -> Self {
+        Self {
+            name: name.to_string(),
+            content,
+            images: Vec::new(),
+            sources: Vec::new(),
+            elapsed: std::time::Duration::ZERO,
+            phases: Vec::new(),
+            call_id: None,
+        }
+    }
+}
+
+/// A document the model was shown during the conversation.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Source {
+    pub title: String,
+    pub url: String,
 }
 
+/// Called with the full message list right before it is sent to the model, e.g. to redact PII.
+pub type BeforeModelHook = Box<dyn Fn(&mut Vec<ChatMessage>) + Send + Sync>;
+/// Called with each tool's output before it re-enters the conversation.
+pub type AfterToolHook = Box<dyn Fn(&mut String) + Send + Sync>;
+
 pub struct QueryHandler {
     ollama_client: OllamaClient,
-    search_client: WebSearchClient,
-    python_invoker: PythonInvoker,
-    system_prompt: String,
+    /// Swapped whole on reload; each request and tool call works from the snapshot it took.
+    tool_registry: RwLock<Arc<ToolRegistry>>,
+    /// Swapped whole on reload, so readers never see a partially written prompt.
+    system_prompt: RwLock<String>,
+    default_model: Option<String>,
+    max_tool_output_chars: usize,
+    keep_alive: Option<serde_json::Value>,
+    reprompt_ignored_tools: bool,
+    ignored_tools_phrases: Vec<String>,
+    tool_result_template: Option<String>,
+    /// None when `STRIP_ARTIFACTS` is off.
+    artifact_filter: Option<ArtifactFilter>,
+    json_repair_attempts: usize,
+    max_tool_iterations: usize,
+    tool_limit_fallback: bool,
+    before_model: Option<BeforeModelHook>,
+    after_tool: Option<AfterToolHook>,
+    active_requests: CancellationRegistry,
+    audit_log: AuditLog,
+    no_tool_models: Vec<String>,
+    /// Models clients may use; empty allows any.
+    allowed_models: Vec<String>,
+    detect_tool_support: bool,
+    react_mode: bool,
+    /// Whether each model supports tools, as detected or learned from Ollama errors.
+    tool_support: Mutex<HashMap<String, bool>>,
+    search_cache: SearchCache,
+    /// Events of chats started with a `request_id`, for `/session/{id}/poll`.
+    sessions: SessionEvents,
+    /// Longest a poll waits for new events (`POLL_TIMEOUT_SECS`).
+    poll_timeout: std::time::Duration,
 }
 
 impl QueryHandler {
-    pub fn new() -> Self {
-        let system_prompt = fs::read_to_string("src/handler/system_prompt.txt").unwrap_or_else(|e| {
+    pub fn new(config: &Config) -> Self {
+        let system_prompt = Self::read_system_prompt(Path::new(SYSTEM_PROMPT_PATH)).unwrap_or_else(|e| {
             error!("Failed to read system_prompt.txt: {}. Using default prompt.", e);
             "You are a helpful assistant.".to_string()
         });
         Self {
-            ollama_client: OllamaClient::new(),
-            search_client: WebSearchClient::new(),
-            python_invoker: PythonInvoker::new(),
-            system_prompt,
+            ollama_client: OllamaClient::from_config(config),
+            tool_registry: RwLock::new(Arc::new(ToolRegistry::from_config(config))),
+            system_prompt: RwLock::new(system_prompt),
+            default_model: config.default_model.clone(),
+            max_tool_output_chars: config.max_tool_output_chars,
+            keep_alive: config.keep_alive.clone(),
+            reprompt_ignored_tools: config.reprompt_ignored_tools,
+            ignored_tools_phrases: config.ignored_tools_phrases.iter().map(|p| p.to_lowercase()).collect(),
+            tool_result_template: config.tool_result_template.clone(),
+            artifact_filter: config.strip_artifacts.then(|| ArtifactFilter::new(&config.artifact_patterns)),
+            json_repair_attempts: config.json_repair_attempts,
+            max_tool_iterations: config.max_tool_iterations,
+            tool_limit_fallback: config.tool_limit_fallback,
+            before_model: None,
+            after_tool: None,
+            active_requests: CancellationRegistry::default(),
+            audit_log: AuditLog::from_config(config),
+            no_tool_models: config.no_tool_models.clone(),
+            allowed_models: config.allowed_models.clone(),
+            detect_tool_support: config.detect_tool_support,
+            react_mode: config.react_mode,
+            tool_support: Mutex::new(HashMap::new()),
+            search_cache: SearchCache::default(),
+            sessions: SessionEvents::default(),
+            poll_timeout: std::time::Duration::from_secs(config.poll_timeout_secs),
+        }
+    }
+
+    /// Installs a hook that can inspect or rewrite the messages before every model call.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn with_before_model(mut self, hook: impl Fn(&mut Vec<ChatMessage>) + Send + Sync + 'static) -> Self {
+        self.before_model = Some(Box::new(hook));
+        self
+    }
+
+    /// Installs a hook that can inspect or rewrite tool output before it is added to the conversation.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn with_after_tool(mut self, hook: impl Fn(&mut String) + Send + Sync + 'static) -> Self {
+        self.after_tool = Some(Box::new(hook));
+        self
+    }
+
+    fn run_before_model(&self, messages: &mut Vec<ChatMessage>) {
+        if let Some(hook) = &self.before_model {
+            hook(messages);
+        }
+    }
+
+    /// Reads the whole prompt file into memory and checks it is valid, non-empty UTF-8.
+    fn read_system_prompt(path: &Path) -> Result<String, String> {
+        let bytes = fs::read(path).map_err(|e| e.to_string())?;
+        let prompt = String::from_utf8(bytes).map_err(|e| format!("not valid UTF-8: {}", e))?;
+        if prompt.trim().is_empty() {
+            return Err("file is empty".to_string());
+        }
+        Ok(prompt)
+    }
+
+    /// The current tool backends.
+    pub fn registry(&self) -> Arc<ToolRegistry> {
+        self.tool_registry.read().unwrap().clone()
+    }
+
+    /// Rebuilds the tool backends from `config` and swaps them in. Calls already running keep the
+    /// backends they started with.
+    pub fn reload_tools(&self, config: &Config) {
+        let registry = Arc::new(ToolRegistry::from_config(config));
+        *self.tool_registry.write().unwrap() = registry;
+        info!("Reloaded tools: {}", self.tool_names().join(", "));
+    }
+
+    /// Re-reads system_prompt.txt and swaps it in. On any error the previous prompt is kept.
+    pub fn reload_system_prompt(&self) -> Result<(), String> {
+        self.reload_system_prompt_from(Path::new(SYSTEM_PROMPT_PATH))
+    }
+
+    fn reload_system_prompt_from(&self, path: &Path) -> Result<(), String> {
+        let prompt = Self::read_system_prompt(path).map_err(|e| {
+            error!("Failed to reload system_prompt.txt, keeping the current prompt: {}", e);
+            e
+        })?;
+        *self.system_prompt.write().unwrap() = prompt;
+        info!("Reloaded system prompt");
+        Ok(())
+    }
+
+    /// Removes leaked template tokens and stray tags from a final answer, when `STRIP_ARTIFACTS` is on.
+    fn clean_answer(&self, model: &str, answer: &mut String) {
+        if let Some(filter) = &self.artifact_filter {
+            *answer = filter.clean(model, answer);
         }
     }
 
+    /// Whether `answer` contains one of the configured phrases that signal the model ignored its tool results.
+    fn ignores_tool_results(&self, answer: &str) -> bool {
+        let answer = answer.to_lowercase();
+        self.ignored_tools_phrases.iter().any(|phrase| answer.contains(phrase.as_str()))
+    }
+
     /**
         * Creates a websearch tool for the Ollama client.
         * This tool allows the model to perform web searches for the latest events and news.
@@ -62,6 +433,23 @@ impl QueryHandler {
                         "count": {
                             "type": "number",
                             "description": "Optional field to mention how many web search results are needed"
+                        },
+                        "recent_only": {
+                            "type": "boolean",
+                            "description": "Only return results published within the last month, newest first."
+                        },
+                        "site": {
+                            "type": "string",
+                            "description": "Optional domain to restrict results to, e.g. \"docs.python.org\". Subdomains are included."
+                        },
+                        "region": {
+                            "type": "string",
+                            "description": "Optional region code for localized results, e.g. \"us-en\", \"uk-en\" or \"de-de\"."
+                        },
+                        "safe_search": {
+                            "type": "string",
+                            "enum": ["off", "moderate", "strict"],
+                            "description": "Optional safe search level."
                         }
                     },
                     "required": ["query"]
@@ -70,175 +458,2188 @@ impl QueryHandler {
         }
     }
 
-    fn create_python_invoker_tool() -> Tool {
+    fn create_python_invoker_tool(sessions: bool) -> Tool {
+        let mut parameters = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "script": {
+                    "type": "string",
+                    "description": "The Python script to execute."
+                },
+                "args": {
+                    "type": "array",
+                    "description": "Optional arguments to pass to the script.",
+                    "items": {
+                        "type": "string"
+                    }
+                },
+                "binary_output": {
+                    "type": "boolean",
+                    "description": "Return stdout base64 encoded if the script writes binary data to it."
+                }
+            },
+            "required": ["script"]
+        });
+        if sessions {
+            parameters["properties"]["session_id"] = serde_json::json!({
+                "type": "string",
+                "description": "Optional name of a persistent interpreter. Calls with the same session_id share variables and imports, so later steps can reuse earlier results. args are not supported in a session."
+            });
+        }
+
         Tool {
             tool_type: "function".to_string(),
             function: crate::llm::ollama::ToolFunction {
                 name: "python_invoker".to_string(),
-                description: "Executes a python script provided as a string and returns its output.".to_string(),
+                description: "Executes a python script provided as a string and returns its output. Files written to the directory in the OUTPUT_DIR environment variable are returned as artifacts; images (e.g. plots saved as PNG) are attached to the result.".to_string(),
+                parameters,
+            },
+        }
+    }
+
+    fn create_arxiv_tool() -> Tool {
+        Tool {
+            tool_type: "function".to_string(),
+            function: crate::llm::ollama::ToolFunction {
+                name: "arxiv_search".to_string(),
+                description: "Search arXiv for academic papers. Returns titles, authors, abstracts and PDF links.".to_string(),
                 parameters: serde_json::json!({
                     "type": "object",
                     "properties": {
-                        "script": {
+                        "query": {
                             "type": "string",
-                            "description": "The Python script to execute."
+                            "description": "The search query, e.g. topic keywords or author names."
                         },
-                        "args": {
-                            "type": "array",
-                            "description": "Optional arguments to pass to the script.",
-                            "items": {
-                                "type": "string"
-                            }
+                        "max_results": {
+                            "type": "number",
+                            "description": "Optional maximum number of papers to return (default 5)."
                         }
                     },
-                    "required": ["script"]
+                    "required": ["query"]
                 }),
             },
         }
     }
 
-    /**
-        * Processes tool calls in the chat response.
-        * If the tool call is for the websearch tool, it performs a web search using the WebSearchClient.
-        * The search results are formatted and returned as a string.
-        * The function returns a Result containing the tool name and the search results as a string.
-        * If the tool call is not for the websearch tool, it returns None.
-        * If there is an error during the web search, it returns an error string.
-     */
-    async fn process_tool_calls(&self, chat_response: &ChatResponse) -> Result<Option<(String, String)>, String> {
-        if let Some(tool_calls) = &chat_response.message.tool_calls {
-            for tool_call in tool_calls {
-                let tool_name = tool_call.function.name.as_str();
-                let args = &tool_call.function.arguments;
-
-                match tool_name {
-                    "websearch" => {
-                        if let Some(query) = args.get("query").and_then(|q| q.as_str()) {
-                            let count = args.get("count")
-                                .and_then(|c| c.as_u64())
-                                .unwrap_or(5) as usize;
-
-                            match self.search_client.search(query.to_string(), count).await {
-                                Ok(results) => {
-                                    let results_text = results.iter()
-                                        .map(|r| format!("Title: {}\nURL: {}\nContent: {}\n---", 
-                                            r.title, r.url, r.content))
-                                        .collect::<Vec<_>>()
-                                        .join("\n");
-                                    
-                                    return Ok(Some((tool_call.function.name.clone(), results_text)));
-                                }
-                                Err(e) => {
-                                    error!("Web search error: {}", e);
-                                    return Err(format!("Web search failed: {}", e));
-                                }
-                            }
-                        }
-                    }
-                    "python_invoker" => {
-                        if let Some(script) = args.get("script").and_then(|s| s.as_str()) {
-                            let script_args: Vec<&str> = args.get("args")
-                                .and_then(|a| a.as_array())
-                                .map(|arr| arr.iter().filter_map(|v| v.as_str()).collect())
-                                .unwrap_or_else(Vec::new);
-                            
-                            match self.python_invoker.run_script(script, &script_args) {
-                                Ok(result) => {
-                                    let response = format!("Exit Code: {:?}\nStdout: {}\nStderr: {}", result.exit_code, result.stdout, result.stderr);
-                                    return Ok(Some((tool_call.function.name.clone(), response)));
-                                }
-                                Err(e) => {
-                                    error!("Python invoker error: {}", e);
-                                    return Err(format!("Python script execution failed: {}", e));
-                                }
-                            }
+    fn create_read_feed_tool() -> Tool {
+        Tool {
+            tool_type: "function".to_string(),
+            function: crate::llm::ollama::ToolFunction {
+                name: "read_feed".to_string(),
+                description: "Read an RSS or Atom feed, e.g. a news site's feed. Returns the newest items with title, link, published date and summary as JSON.".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "url": {
+                            "type": "string",
+                            "description": "URL of the RSS or Atom feed."
+                        },
+                        "max_items": {
+                            "type": "integer",
+                            "description": "Optional maximum number of items to return, newest first (default 10, at most 50)."
                         }
-                    }
-                    _ => {
-                        // Unknown tool
-                    }
-                }
-            }
+                    },
+                    "required": ["url"]
+                }),
+            },
         }
-
-        Ok(None)
     }
 
-    /// Handles chat requests by processing the message and interacting with the Ollama client.
-    pub async fn handle_chat(&self, req: web::Json<ChatRequest>) -> Result<HttpResponse, Error> {
-        info!("Processing chat request for model: {}", req.model);
-        
-        let now = Local::now();
-        let formatted_datetime = now.to_rfc3339();
-        let system_prompt = format!("{} Current date and time: {}", self.system_prompt, formatted_datetime);
+    fn create_convert_tool() -> Tool {
+        Tool {
+            tool_type: "function".to_string(),
+            function: crate::llm::ollama::ToolFunction {
+                name: "convert".to_string(),
+                description: "Convert a value between units of length, mass, time or temperature, or between currencies using live exchange rates. Use this instead of doing conversions yourself.".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "value": {
+                            "type": "number",
+                            "description": "The value to convert."
+                        },
+                        "from": {
+                            "type": "string",
+                            "description": "Source unit (e.g. km, lb, F, hours) or ISO currency code (e.g. USD)."
+                        },
+                        "to": {
+                            "type": "string",
+                            "description": "Target unit or ISO currency code."
+                        }
+                    },
+                    "required": ["value", "from", "to"]
+                }),
+            },
+        }
+    }
 
-        let mut messages = vec![
-            ChatMessage {
-                role: "system".to_string(),
-                content: system_prompt,
-                tool_calls: None,
+    fn create_weather_tool() -> Tool {
+        Tool {
+            tool_type: "function".to_string(),
+            function: crate::llm::ollama::ToolFunction {
+                name: "get_weather".to_string(),
+                description: "Get the current weather and a three-day forecast for a location.".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "location": {
+                            "type": "string",
+                            "description": "City or place name, optionally with region or country, e.g. \"Springfield, Illinois\"."
+                        },
+                        "units": {
+                            "type": "string",
+                            "enum": ["metric", "imperial"],
+                            "description": "Optional unit system (default metric)."
+                        }
+                    },
+                    "required": ["location"]
+                }),
             },
-            ChatMessage {
-                role: "user".to_string(),
-                content: req.message.clone(),
-                tool_calls: None,
-            }
-        ];
+        }
+    }
 
-        let mut response = String::new();
-        
-        loop {
-            // Call Ollama with the messages and websearch tool
-            let chat_response = match self.ollama_client
-                .chat(messages.clone(), req.model.clone(), vec![Self::create_websearch_tool(), Self::create_python_invoker_tool()])
-                .await {
-                    Ok(response) => response,
-                    Err(e) => {
-                        error!("Ollama chat error: {}", e);
-                        return Ok(HttpResponse::InternalServerError().json(ChatApiResponse {
-                            response: format!("Error: {}", e),
-                        }));
-                    }
-                };
-            
-            info!("Tool calls: {:?}", chat_response.message.tool_calls);
-            // Process any tool calls in the response
-            match self.process_tool_calls(&chat_response).await {
-                Ok(Some((_, tool_output))) => {
-                    // Add assistant message
-                    messages.push(ChatMessage {
-                        role: "assistant".to_string(),
-                        content: chat_response.message.content.clone(),
-                        tool_calls: chat_response.message.tool_calls.clone(),
-                    });
+    fn create_encode_decode_tool() -> Tool {
+        Tool {
+            tool_type: "function".to_string(),
+            function: crate::llm::ollama::ToolFunction {
+                name: "encode_decode".to_string(),
+                description: "Encode text to, or decode it from, base64, hex or URL encoding. Use this instead of encoding or decoding yourself.".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "operation": {
+                            "type": "string",
+                            "enum": ["encode", "decode"],
+                            "description": "Whether to encode or decode the input."
+                        },
+                        "encoding": {
+                            "type": "string",
+                            "enum": ["base64", "hex", "url"],
+                            "description": "The encoding to use."
+                        },
+                        "input": {
+                            "type": "string",
+                            "description": "The text to encode, or the encoded data to decode."
+                        }
+                    },
+                    "required": ["operation", "encoding", "input"]
+                }),
+            },
+        }
+    }
 
-                    // Add tool message
-                    messages.push(ChatMessage {
-                        role: "tool".to_string(),
-                        content: tool_output,
-                        tool_calls: None,
-                    });
+    fn create_render_template_tool() -> Tool {
+        Tool {
+            tool_type: "function".to_string(),
+            function: crate::llm::ollama::ToolFunction {
+                name: "render_template".to_string(),
+                description: "Render a Jinja-style template (e.g. \"{% for item in items %}- {{ item.name }}\\n{% endfor %}\") with a JSON context. Use this to produce formatted text or reports from data.".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "template": {
+                            "type": "string",
+                            "description": "The template source."
+                        },
+                        "context": {
+                            "type": "object",
+                            "description": "Variables available to the template."
+                        }
+                    },
+                    "required": ["template"]
+                }),
+            },
+        }
+    }
 
-                    // Continue the loop to process the tool response
-                    continue;
-                }
-                Ok(None) => {
-                    // No more tool calls, use the final message content
-                    info!("Final response recieved from the model.");
-                    response = chat_response.message.content;
-                    break;
-                }
-                Err(e) => {
-                    error!("Tool processing error: {}", e);
-                    return Ok(HttpResponse::InternalServerError().json(ChatApiResponse {
-                        response: format!("Error: {}", e),
-                    }));
-                }
-            }
+    fn create_precise_math_tool() -> Tool {
+        Tool {
+            tool_type: "function".to_string(),
+            function: crate::llm::ollama::ToolFunction {
+                name: "precise_math".to_string(),
+                description: "Evaluate an arithmetic expression exactly, with arbitrary precision integers and fractions (e.g. \"2^256\" or \"1/3 + 1/6\"). Supports + - * / ^ and parentheses. Use this instead of python_invoker when exact results matter.".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "expression": {
+                            "type": "string",
+                            "description": "The expression to evaluate."
+                        },
+                        "form": {
+                            "type": "string",
+                            "enum": ["decimal", "fraction", "integer"],
+                            "description": "How to write the result. Defaults to decimal."
+                        },
+                        "precision": {
+                            "type": "number",
+                            "description": "Digits after the decimal point for the decimal form. Defaults to 30."
+                        }
+                    },
+                    "required": ["expression"]
+                }),
+            },
         }
+    }
 
-        Ok(HttpResponse::Ok().json(ChatApiResponse {
-            response,
-        }))
+    fn create_datetime_tool() -> Tool {
+        Tool {
+            tool_type: "function".to_string(),
+            function: crate::llm::ollama::ToolFunction {
+                name: "datetime".to_string(),
+                description: "Date and time calculations: current time in a timezone, converting between timezones, adding or subtracting durations (including business days) and the difference between two dates. Use this instead of doing date arithmetic yourself.".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "operation": {
+                            "type": "string",
+                            "enum": ["now", "convert", "add", "diff"],
+                            "description": "now: current time in timezone. convert: datetime from timezone to to_timezone. add: datetime plus amount of unit. diff: time from datetime to other."
+                        },
+                        "datetime": {
+                            "type": "string",
+                            "description": "ISO 8601 date or date and time, e.g. \"2024-03-15\" or \"2024-03-15 15:00\", or \"now\" / \"today\". Defaults to now."
+                        },
+                        "timezone": {
+                            "type": "string",
+                            "description": "IANA timezone (e.g. \"America/Los_Angeles\") or abbreviation (e.g. \"PST\") that datetime is in. Defaults to UTC."
+                        },
+                        "to_timezone": {
+                            "type": "string",
+                            "description": "Target timezone for convert."
+                        },
+                        "amount": {
+                            "type": "integer",
+                            "description": "For add: how many units to add, negative to subtract."
+                        },
+                        "unit": {
+                            "type": "string",
+                            "enum": ["seconds", "minutes", "hours", "days", "weeks", "business_days", "months", "years"],
+                            "description": "For add: the unit of amount."
+                        },
+                        "other": {
+                            "type": "string",
+                            "description": "For diff: the second date, in the same timezone as datetime."
+                        }
+                    },
+                    "required": ["operation"]
+                }),
+            },
+        }
+    }
+
+    /// Runs a `datetime` tool call; errors are meant to be shown to the model.
+    fn run_datetime(args: &Value) -> Result<String, datetime::DateTimeError> {
+        let arg = |name: &str| args.get(name).and_then(|v| v.as_str());
+        let operation = datetime::Operation::parse(arg("operation").unwrap_or_default())?;
+        let tz = datetime::parse_timezone(arg("timezone").unwrap_or("UTC"))?;
+        let now = chrono::Utc::now();
+        let start = datetime::parse_datetime(arg("datetime").unwrap_or("now"), tz, now)?;
+
+        match operation {
+            datetime::Operation::Now => Ok(datetime::describe(&start)),
+            datetime::Operation::Convert => {
+                let to = arg("to_timezone").ok_or(datetime::DateTimeError::MissingArgument("to_timezone"))?;
+                let converted = start.with_timezone(&datetime::parse_timezone(to)?);
+                Ok(format!("{} is {}", datetime::describe(&start), datetime::describe(&converted)))
+            }
+            datetime::Operation::Add => {
+                let amount = args.get("amount")
+                    .and_then(|a| a.as_i64())
+                    .ok_or(datetime::DateTimeError::MissingArgument("amount"))?;
+                let unit = arg("unit").ok_or(datetime::DateTimeError::MissingArgument("unit"))?;
+                Ok(datetime::describe(&datetime::add(start, amount, unit)?))
+            }
+            datetime::Operation::Diff => {
+                let other = arg("other").ok_or(datetime::DateTimeError::MissingArgument("other"))?;
+                let end = datetime::parse_datetime(other, tz, now)?;
+                let difference = datetime::difference(start, end);
+                Ok(serde_json::to_string_pretty(&difference).unwrap_or_default())
+            }
+        }
+    }
+
+    fn create_extract_tool() -> Tool {
+        Tool {
+            tool_type: "function".to_string(),
+            function: crate::llm::ollama::ToolFunction {
+                name: "extract".to_string(),
+                description: "Extract every match of a regular expression from a text, such as emails, URLs or numbers. Returns the matches and their capture groups as JSON.".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "text": {
+                            "type": "string",
+                            "description": "The text to search."
+                        },
+                        "pattern": {
+                            "type": "string",
+                            "description": "Regular expression in Rust regex syntax, e.g. \"[\\w.+-]+@[\\w-]+\\.[\\w.]+\" for emails. Use (?P<name>...) for named groups. Lookaround and backreferences are not supported."
+                        },
+                        "case_insensitive": {
+                            "type": "boolean",
+                            "description": "Match regardless of case. Defaults to false."
+                        }
+                    },
+                    "required": ["text", "pattern"]
+                }),
+            },
+        }
+    }
+
+    fn create_generate_qr_tool() -> Tool {
+        Tool {
+            tool_type: "function".to_string(),
+            function: crate::llm::ollama::ToolFunction {
+                name: "generate_qr".to_string(),
+                description: "Generate a QR code for a link, text or payment string. Returns a PNG image attached to the result, or the code drawn as ASCII art.".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "data": {
+                            "type": "string",
+                            "description": "The text or URL to encode."
+                        },
+                        "format": {
+                            "type": "string",
+                            "enum": ["png", "ascii"],
+                            "description": "png for an image, ascii for text-only clients. Defaults to png."
+                        }
+                    },
+                    "required": ["data"]
+                }),
+            },
+        }
+    }
+
+    /// With `files`, `a` and `b` may name files in the data directory instead of holding the text.
+    fn create_diff_tool(files: bool) -> Tool {
+        let mut parameters = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "a": {
+                    "type": "string",
+                    "description": "The original text."
+                },
+                "b": {
+                    "type": "string",
+                    "description": "The changed text."
+                },
+                "context_lines": {
+                    "type": "integer",
+                    "description": "Unchanged lines shown around each change. Defaults to 3."
+                }
+            },
+            "required": ["a", "b"]
+        });
+        if files {
+            parameters["properties"]["from_files"] = serde_json::json!({
+                "type": "boolean",
+                "description": "Treat a and b as paths of files in the data directory and diff their contents."
+            });
+        }
+        Tool {
+            tool_type: "function".to_string(),
+            function: crate::llm::ollama::ToolFunction {
+                name: "diff".to_string(),
+                description: "Compare two texts or files and return a unified diff of their lines. Use this instead of comparing text yourself.".to_string(),
+                parameters,
+            },
+        }
+    }
+
+    fn create_json_query_tool(files: bool) -> Tool {
+        let mut parameters = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "document": {
+                    "type": "string",
+                    "description": "The JSON document to query, e.g. an API response."
+                },
+                "expression": {
+                    "type": "string",
+                    "description": "JSONPath expression, e.g. \"$.items[0].name\", \"$..price\" or \"$.items[?@.price < 10].name\"."
+                }
+            },
+            "required": ["document", "expression"]
+        });
+        if files {
+            parameters["properties"]["path"] = serde_json::json!({
+                "type": "string",
+                "description": "Path of a JSON file in the data directory to query instead of document."
+            });
+            parameters["required"] = serde_json::json!(["expression"]);
+        }
+        Tool {
+            tool_type: "function".to_string(),
+            function: crate::llm::ollama::ToolFunction {
+                name: "json_query".to_string(),
+                description: "Extract values from a JSON document with a JSONPath expression. Returns the matched values as a JSON array. Use this to pick nested fields out of API responses.".to_string(),
+                parameters,
+            },
+        }
+    }
+
+    /// With `files`, `input` may name a file in the data directory instead of holding the text.
+    fn create_hash_tool(files: bool) -> Tool {
+        let mut parameters = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "input": {
+                    "type": "string",
+                    "description": "The text to hash, as UTF-8."
+                },
+                "algorithm": {
+                    "type": "string",
+                    "enum": ["md5", "sha1", "sha256", "sha512"],
+                    "description": "Hash algorithm. Defaults to sha256."
+                }
+            },
+            "required": ["input"]
+        });
+        if files {
+            parameters["properties"]["from_file"] = serde_json::json!({
+                "type": "boolean",
+                "description": "Treat input as the path of a file in the data directory and hash its contents, e.g. to verify a download against a published checksum."
+            });
+        }
+        Tool {
+            tool_type: "function".to_string(),
+            function: crate::llm::ollama::ToolFunction {
+                name: "hash".to_string(),
+                description: "Compute the MD5, SHA-1, SHA-256 or SHA-512 hash of text or a file. Returns the lowercase hex digest. Use this instead of computing hashes yourself or with Python.".to_string(),
+                parameters,
+            },
+        }
+    }
+
+    fn create_download_file_tool() -> Tool {
+        Tool {
+            tool_type: "function".to_string(),
+            function: crate::llm::ollama::ToolFunction {
+                name: "download_file".to_string(),
+                description: "Download a file from a URL into the data directory, e.g. a dataset to analyze with load_data. Returns the saved path and its size.".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "url": {
+                            "type": "string",
+                            "description": "The URL to download."
+                        },
+                        "dest": {
+                            "type": "string",
+                            "description": "Where to save the file, relative to the data directory, e.g. \"data/sales.csv\". The directory must exist."
+                        }
+                    },
+                    "required": ["url", "dest"]
+                }),
+            },
+        }
+    }
+
+    fn create_load_data_tool() -> Tool {
+        Tool {
+            tool_type: "function".to_string(),
+            function: crate::llm::ollama::ToolFunction {
+                name: "load_data".to_string(),
+                description: "Load a CSV or JSON data file and return its columns, row count and a sample of the first and last rows.".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path of the data file, relative to the data directory."
+                        },
+                        "sample_rows": {
+                            "type": "number",
+                            "description": "Optional number of rows to return from the start and end of the data (default 5)."
+                        }
+                    },
+                    "required": ["path"]
+                }),
+            },
+        }
+    }
+
+    /**
+        * Runs a single tool by name with the given arguments.
+        * If the tool call is for the websearch tool, it performs a web search using the WebSearchClient.
+        * The search results are formatted and returned as a string.
+        * The function returns a Result containing the tool output (name, text content and any images).
+        * If the tool is unknown, it returns a message listing the available tools so the model can pick a real one.
+        * If the tool's required arguments are missing, it returns None.
+        * If the tool call lacks an argument its schema lists as required, it returns a message asking the model to re-issue the call.
+        * If there is an error while running the tool, it returns an error string.
+        * Cancelling `cancel` kills a running Python script.
+     */
+    pub async fn run_tool(&self, tool_name: &str, args: &Value, cancel: &Arc<CancelToken>) -> Result<Option<ToolOutput>, String> {
+        // Held for the whole call, so a reload can't swap backends out from under it
+        let registry = self.registry();
+        let definitions = Self::tool_definitions(&registry);
+        let Some(definition) = definitions.iter().find(|tool| tool.function.name == tool_name) else {
+            let available: Vec<&str> = definitions.iter().map(|tool| tool.function.name.as_str()).collect();
+            error!("Model called unknown tool {} with arguments: {}", tool_name, args);
+            return Ok(Some(ToolOutput::text(
+                tool_name,
+                format!(
+                    "Tool '{}' is not available. Available tools are: {}.",
+                    tool_name,
+                    available.join(", ")
+                ),
+            )));
+        };
+
+        let errors = validate_arguments(&definition.function.parameters, args);
+        if !errors.is_empty() {
+            error!("Invalid call to tool {}: {}", tool_name, args);
+            return Ok(Some(ToolOutput::text(
+                tool_name,
+                format!(
+                    "Invalid call to tool '{}': {}. Re-issue the call with valid arguments as a JSON object.",
+                    tool_name,
+                    errors.join("; ")
+                ),
+            )));
+        }
+        // Tools whose parameters are all optional may be called with null arguments
+        let empty_args = Value::Object(Default::default());
+        let args = if args.is_null() { &empty_args } else { args };
+
+        match tool_name {
+            "websearch" => {
+                if let Some(query) = args.get("query").and_then(|q| q.as_str()) {
+                    let count = args.get("count")
+                        .and_then(|c| c.as_u64())
+                        .map(|c| c as usize)
+                        .unwrap_or_else(|| registry.search_client.default_count());
+                    let recent_only = args.get("recent_only").and_then(|r| r.as_bool()).unwrap_or(false);
+                    let string_arg = |name: &str| args.get(name).and_then(|v| v.as_str()).filter(|v| !v.trim().is_empty());
+                    // Bad filter values are reported back so the model can correct them
+                    let safe_search = match string_arg("safe_search").map(str::parse::<SafeSearch>).transpose() {
+                        Ok(safe_search) => safe_search,
+                        Err(e) => return Ok(Some(ToolOutput::text(tool_name, format!("Error: {}", e)))),
+                    };
+                    let filters = SearchFilters {
+                        site: string_arg("site").map(str::to_string),
+                        region: string_arg("region").map(str::to_string),
+                        safe_search,
+                    };
+
+                    let started = std::time::Instant::now();
+                    match registry.search_client.search(query.to_string(), count, recent_only, &filters).await {
+                        Ok(mut results) => {
+                            truncate_contents(&mut results, registry.search_max_content_chars);
+                            // The tool passes snippets on without fetching pages, so scraping is the only step
+                            return Ok(Some(ToolOutput {
+                                sources: search_sources(&results),
+                                phases: vec![PhaseTiming::new("scrape", started.elapsed())],
+                                ..ToolOutput::text(tool_name, format_search_results(&results))
+                            }));
+                        }
+                        Err(e) if e.is_invalid_input() => {
+                            return Ok(Some(ToolOutput::text(tool_name, format!("Error: {}", e))));
+                        }
+                        Err(e) => {
+                            error!("Web search error: {}", e);
+                            return Err(format!("Web search failed: {}", e));
+                        }
+                    }
+                }
+            }
+            "python_invoker" => {
+                if let Some(script) = args.get("script").and_then(|s| s.as_str()) {
+                    let script_args: Vec<String> = args.get("args")
+                        .and_then(|a| a.as_array())
+                        .map(|arr| arr.iter().filter_map(|v| v.as_str()).map(|s| s.to_string()).collect())
+                        .unwrap_or_else(Vec::new);
+
+                    // run_script blocks until the child exits, so keep it off the async workers
+                    let invoker = registry.python_invoker.clone();
+                    let script = script.to_string();
+                    let session_id = args.get("session_id").and_then(|s| s.as_str()).map(|s| s.to_string());
+                    let binary_output = args.get("binary_output").and_then(|b| b.as_bool()).unwrap_or(false);
+                    let cancel = cancel.clone();
+                    let run = tokio::task::spawn_blocking(move || match session_id {
+                        Some(session_id) => invoker.run_in_session(&session_id, &script, cancel.flag()),
+                        None => {
+                            let script_args: Vec<&str> = script_args.iter().map(|s| s.as_str()).collect();
+                            invoker.run_script(&script, &script_args, binary_output, cancel.flag())
+                        }
+                    })
+                    .await
+                    .map_err(|e| format!("Python script task failed: {}", e))?;
+
+                    match run {
+                        Ok(result) => {
+                            let mut response = format!("Exit Code: {:?}\nStdout: {}\nStderr: {}", result.exit_code, result.stdout, result.stderr);
+                            if let Some(encoded) = &result.stdout_base64 {
+                                response.push_str(&format!("\nStdout (base64): {}", encoded));
+                            }
+                            if result.stderr_is_binary {
+                                response.push_str("\nNote: stderr contained bytes that are not valid UTF-8; they were replaced.");
+                            }
+                            if !result.artifacts.is_empty() {
+                                let listing = result.artifacts.iter()
+                                    .map(|a| format!("{} ({} bytes)", a.name, a.size))
+                                    .collect::<Vec<_>>()
+                                    .join(", ");
+                                response.push_str(&format!("\nArtifacts: {}", listing));
+                            }
+                            let images = result.artifacts.into_iter()
+                                .filter(|a| a.is_image())
+                                .map(|a| a.data)
+                                .collect();
+                            return Ok(Some(ToolOutput {
+                                images,
+                                ..ToolOutput::text(tool_name, response)
+                            }));
+                        }
+                        Err(e) => {
+                            error!("Python invoker error: {}", e);
+                            return Err(format!("Python script execution failed: {}", e));
+                        }
+                    }
+                }
+            }
+            "read_feed" => {
+                if let Some(url) = args.get("url").and_then(|u| u.as_str()) {
+                    let max_items = args.get("max_items")
+                        .and_then(|m| m.as_u64())
+                        .map(|m| m as usize)
+                        .unwrap_or(feed::DEFAULT_FEED_ITEMS);
+
+                    // Unreachable or malformed feeds are reported back so the model can try another
+                    let summary = match registry.feed_client.read(url, max_items).await {
+                        Ok(summary) => summary,
+                        Err(e) => {
+                            error!("Feed error for {}: {}", url, e);
+                            return Ok(Some(ToolOutput::text(tool_name, format!("Error: {}", e))));
+                        }
+                    };
+                    let content = serde_json::to_string_pretty(&summary)
+                        .unwrap_or_else(|e| format!("Failed to serialize feed: {}", e));
+                    return Ok(Some(ToolOutput {
+                        sources: summary.items.iter()
+                            .filter_map(|item| item.link.as_ref().map(|link| Source { title: item.title.clone(), url: link.clone() }))
+                            .collect(),
+                        ..ToolOutput::text(tool_name, content)
+                    }));
+                }
+            }
+            "arxiv_search" => {
+                if let Some(query) = args.get("query").and_then(|q| q.as_str()) {
+                    let max_results = args.get("max_results")
+                        .and_then(|m| m.as_u64())
+                        .unwrap_or(5)
+                        .min(50) as usize;
+
+                    match registry.arxiv_client.search(query, max_results).await {
+                        Ok(entries) => {
+                            let results_text = if entries.is_empty() {
+                                format!("No arXiv papers found for query: {}", query)
+                            } else {
+                                entries.iter()
+                                    .map(|e| format!("Title: {}\nAuthors: {}\nPublished: {}\nPDF: {}\nAbstract: {}\n---",
+                                        e.title, e.authors.join(", "), e.published, e.pdf_url, e.summary))
+                                    .collect::<Vec<_>>()
+                                    .join("\n")
+                            };
+
+                            return Ok(Some(ToolOutput {
+                                sources: entries.iter()
+                                    .map(|e| Source { title: e.title.clone(), url: e.pdf_url.clone() })
+                                    .collect(),
+                                ..ToolOutput::text(tool_name, results_text)
+                            }));
+                        }
+                        Err(e) => {
+                            error!("arXiv search error: {}", e);
+                            return Err(format!("arXiv search failed: {}", e));
+                        }
+                    }
+                }
+            }
+            "convert" => {
+                if let (Some(value), Some(from), Some(to)) = (
+                    args.get("value").and_then(|v| v.as_f64()),
+                    args.get("from").and_then(|f| f.as_str()),
+                    args.get("to").and_then(|t| t.as_str()),
+                ) {
+                    match registry.unit_converter.convert(value, from, to).await {
+                        Ok(conversion) => {
+                            let content = serde_json::to_string(&conversion)
+                                .unwrap_or_else(|e| format!("Failed to serialize conversion: {}", e));
+                            return Ok(Some(ToolOutput::text(tool_name, content)));
+                        }
+                        Err(e) => {
+                            error!("Conversion error: {}", e);
+                            return Err(format!("Conversion failed: {}", e));
+                        }
+                    }
+                }
+            }
+            "get_weather" => {
+                if let Some(location) = args.get("location").and_then(|l| l.as_str()) {
+                    let units = args.get("units").and_then(|u| u.as_str()).unwrap_or("metric");
+                    let result = match WeatherUnits::parse(units) {
+                        Ok(units) => registry.weather_client.get_weather(location, units).await,
+                        Err(e) => Err(e),
+                    };
+
+                    match result {
+                        Ok(report) => {
+                            let content = serde_json::to_string_pretty(&report)
+                                .unwrap_or_else(|e| format!("Failed to serialize weather report: {}", e));
+                            return Ok(Some(ToolOutput::text(tool_name, content)));
+                        }
+                        // Bad input the model can fix, so report it back instead of failing the chat
+                        Err(e @ (WeatherError::LocationNotFound(_) | WeatherError::InvalidUnits(_))) => {
+                            return Ok(Some(ToolOutput::text(tool_name, e.to_string())));
+                        }
+                        Err(e) => {
+                            error!("Weather error: {}", e);
+                            return Err(format!("Weather lookup failed: {}", e));
+                        }
+                    }
+                }
+            }
+            "encode_decode" => {
+                if let (Some(operation), Some(encoding), Some(input)) = (
+                    args.get("operation").and_then(|o| o.as_str()),
+                    args.get("encoding").and_then(|e| e.as_str()),
+                    args.get("input").and_then(|i| i.as_str()),
+                ) {
+                    // Invalid input is reported back so the model can correct it
+                    let content = codec::encode_decode(operation, encoding, input)
+                        .unwrap_or_else(|e| format!("Error: {}", e));
+                    return Ok(Some(ToolOutput::text(tool_name, content)));
+                }
+            }
+            "render_template" => {
+                if let Some(template_source) = args.get("template").and_then(|t| t.as_str()) {
+                    let context = args.get("context").cloned().unwrap_or(Value::Null);
+                    // Template mistakes are reported back so the model can fix them
+                    let content = template::render_template(template_source, &context)
+                        .unwrap_or_else(|e| format!("Error: {}", e));
+                    return Ok(Some(ToolOutput::text(tool_name, content)));
+                }
+            }
+            "precise_math" => {
+                if let Some(expression) = args.get("expression").and_then(|e| e.as_str()) {
+                    let form = args.get("form").and_then(|f| f.as_str()).unwrap_or("decimal");
+                    let precision = args.get("precision").and_then(|p| p.as_u64()).map(|p| p as usize);
+                    // Malformed expressions are reported back so the model can correct them
+                    let content = precise_math::OutputForm::parse(form, precision)
+                        .and_then(|form| precise_math::evaluate(expression, form))
+                        .unwrap_or_else(|e| format!("Error: {}", e));
+                    return Ok(Some(ToolOutput::text(tool_name, content)));
+                }
+            }
+            "datetime" if args.get("operation").and_then(|o| o.as_str()).is_some() => {
+                // Bad dates and timezones are reported back so the model can correct them
+                let content = Self::run_datetime(args).unwrap_or_else(|e| format!("Error: {}", e));
+                return Ok(Some(ToolOutput::text(tool_name, content)));
+            }
+            "extract" => {
+                if let (Some(text), Some(pattern)) = (
+                    args.get("text").and_then(|t| t.as_str()),
+                    args.get("pattern").and_then(|p| p.as_str()),
+                ) {
+                    let case_insensitive = args.get("case_insensitive").and_then(|c| c.as_bool()).unwrap_or(false);
+                    // Invalid patterns are reported back so the model can fix them
+                    let content = match extract::extract(text, pattern, case_insensitive) {
+                        Ok(extraction) => serde_json::to_string_pretty(&extraction)
+                            .unwrap_or_else(|e| format!("Failed to serialize matches: {}", e)),
+                        Err(e) => format!("Error: {}", e),
+                    };
+                    return Ok(Some(ToolOutput::text(tool_name, content)));
+                }
+            }
+            "json_query" => {
+                if let Some(expression) = args.get("expression").and_then(|e| e.as_str()) {
+                    let document = match (args.get("document").and_then(|d| d.as_str()), args.get("path").and_then(|p| p.as_str())) {
+                        (Some(document), _) => Ok(document.to_string()),
+                        (None, Some(path)) => registry.file_root
+                            .as_ref()
+                            .ok_or_else(|| crate::tools::file_root::FileRootError::NotConfigured.to_string())
+                            .and_then(|root| root.resolve(path).map_err(|e| e.to_string()))
+                            .and_then(|resolved| json_query::read_document(&resolved, path).map_err(|e| e.to_string())),
+                        (None, None) => Err("Missing argument: document".to_string()),
+                    };
+                    // Bad JSON, paths and expressions are reported back so the model can fix them
+                    let content = match document.and_then(|document| json_query::query(&document, expression).map_err(|e| e.to_string())) {
+                        Ok(result) => serde_json::to_string_pretty(&result)
+                            .unwrap_or_else(|e| format!("Failed to serialize values: {}", e)),
+                        Err(e) => format!("Error: {}", e),
+                    };
+                    return Ok(Some(ToolOutput::text(tool_name, content)));
+                }
+            }
+            "hash" => {
+                if let Some(input) = args.get("input").and_then(|i| i.as_str()) {
+                    let algorithm = args.get("algorithm").and_then(|a| a.as_str()).unwrap_or("sha256");
+                    // Unknown algorithms and bad paths are reported back so the model can correct them
+                    let algorithm = match hashing::HashAlgorithm::parse(algorithm) {
+                        Ok(algorithm) => algorithm,
+                        Err(e) => return Ok(Some(ToolOutput::text(tool_name, format!("Error: {}", e)))),
+                    };
+                    if !args.get("from_file").and_then(|f| f.as_bool()).unwrap_or(false) {
+                        return Ok(Some(ToolOutput::text(tool_name, hashing::hash_text(input, algorithm))));
+                    }
+                    let Some(root) = &registry.file_root else {
+                        return Err(crate::tools::file_root::FileRootError::NotConfigured.to_string());
+                    };
+                    let resolved = match root.resolve(input) {
+                        Ok(resolved) => resolved,
+                        Err(e) => return Ok(Some(ToolOutput::text(tool_name, format!("Error: {}", e)))),
+                    };
+                    let label = input.to_string();
+                    // Large files take a while to read, so keep them off the async workers
+                    let digest = tokio::task::spawn_blocking(move || hashing::hash_file(&resolved, &label, algorithm))
+                        .await
+                        .map_err(|e| format!("Hash task failed: {}", e))?;
+                    let content = match digest {
+                        Ok(digest) => digest,
+                        Err(e) => format!("Error: {}", e),
+                    };
+                    return Ok(Some(ToolOutput::text(tool_name, content)));
+                }
+            }
+            "generate_qr" => {
+                if let Some(data) = args.get("data").and_then(|d| d.as_str()) {
+                    let format = args.get("format").and_then(|f| f.as_str()).unwrap_or("png");
+                    // Unencodable data is reported back so the model can shorten it
+                    let output = match qrcode::QrFormat::parse(format).and_then(|format| qrcode::generate_qr(data, format)) {
+                        Ok(output) => output,
+                        Err(e) => return Ok(Some(ToolOutput::text(tool_name, format!("Error: {}", e)))),
+                    };
+                    return Ok(Some(match output {
+                        qrcode::QrOutput::Ascii(art) => ToolOutput::text(tool_name, art),
+                        qrcode::QrOutput::Png(png) => ToolOutput {
+                            images: vec![png],
+                            ..ToolOutput::text(tool_name, format!("QR code for {:?} generated as a PNG image and attached.", data))
+                        },
+                    }));
+                }
+            }
+            "diff" => {
+                if let (Some(a), Some(b)) = (
+                    args.get("a").and_then(|a| a.as_str()),
+                    args.get("b").and_then(|b| b.as_str()),
+                ) {
+                    let context_lines = args.get("context_lines")
+                        .and_then(|n| n.as_u64())
+                        .map(|n| n as usize)
+                        .unwrap_or(diff::DEFAULT_CONTEXT_LINES);
+
+                    if !args.get("from_files").and_then(|f| f.as_bool()).unwrap_or(false) {
+                        return Ok(Some(ToolOutput::text(tool_name, diff::unified_diff(a, b, "a", "b", context_lines))));
+                    }
+                    let Some(root) = &registry.file_root else {
+                        return Err(crate::tools::file_root::FileRootError::NotConfigured.to_string());
+                    };
+                    let read = |path: &str| {
+                        root.resolve(path)
+                            .map_err(|e| e.to_string())
+                            .and_then(|resolved| diff::read_text(&resolved, path).map_err(|e| e.to_string()))
+                    };
+                    // Bad paths are reported back so the model can correct them
+                    let output = match read(a).and_then(|a_text| Ok((a_text, read(b)?))) {
+                        Ok((a_text, b_text)) => diff::unified_diff(&a_text, &b_text, a, b, context_lines),
+                        Err(e) => format!("Error: {}", e),
+                    };
+                    return Ok(Some(ToolOutput::text(tool_name, output)));
+                }
+            }
+            "load_data" => {
+                if let Some(path) = args.get("path").and_then(|p| p.as_str()) {
+                    let sample_rows = args.get("sample_rows")
+                        .and_then(|n| n.as_u64())
+                        .unwrap_or(5)
+                        .min(50) as usize;
+
+                    let result = registry.file_root
+                        .as_ref()
+                        .ok_or_else(|| crate::tools::file_root::FileRootError::NotConfigured.to_string())
+                        .and_then(|root| root.resolve(path).map_err(|e| e.to_string()))
+                        .and_then(|resolved| data_loader::load_data(&resolved, sample_rows).map_err(|e| e.to_string()));
+
+                    match result {
+                        Ok(summary) => {
+                            let content = serde_json::to_string_pretty(&summary)
+                                .unwrap_or_else(|e| format!("Failed to serialize data summary: {}", e));
+                            return Ok(Some(ToolOutput::text(tool_name, content)));
+                        }
+                        Err(e) => {
+                            error!("Load data error: {}", e);
+                            return Err(format!("Loading data failed: {}", e));
+                        }
+                    }
+                }
+            }
+            "download_file" => {
+                if let (Some(url), Some(dest)) = (
+                    args.get("url").and_then(|u| u.as_str()),
+                    args.get("dest").and_then(|d| d.as_str()),
+                ) {
+                    let (Some(downloader), Some(root)) = (&registry.downloader, &registry.file_root) else {
+                        return Err("Downloads are disabled".to_string());
+                    };
+                    let path = root.resolve_new(dest).map_err(|e| format!("Download failed: {}", e))?;
+                    match downloader.download(url, &path).await {
+                        Ok(bytes) => {
+                            return Ok(Some(ToolOutput::text(tool_name, format!("Saved {} bytes to {}", bytes, dest))));
+                        }
+                        Err(e) => {
+                            error!("Download error: {}", e);
+                            return Err(format!("Download failed: {}", e));
+                        }
+                    }
+                }
+            }
+            _ => {
+                // Unknown tools are answered above
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Runs every tool call in the chat response in order and returns one output per call that ran,
+    /// each tagged with its call's id. Calls missing required arguments produce none. Calls to tools
+    /// outside `allowed`, the tools offered for this request, are refused with an error the model
+    /// can read.
+    async fn process_tool_calls(&self, chat_response: &ChatResponse, allowed: &[String], cancel: &Arc<CancelToken>, progress: &Progress) -> Result<Vec<ToolOutput>, String> {
+        let mut outputs = Vec::new();
+        if let Some(tool_calls) = &chat_response.message.tool_calls {
+            for tool_call in tool_calls {
+                let name = &tool_call.function.name;
+                let arguments = &tool_call.function.arguments;
+                if !allowed.contains(name) {
+                    info!("Refusing call to {}, which is not offered in this request", name);
+                    self.audit_log.record(AuditEntry::new(name, arguments, Err("tool not allowed in this request")));
+                    outputs.push(ToolOutput {
+                        call_id: tool_call.id.clone(),
+                        ..ToolOutput::text(name, format!(
+                            "Error: The tool '{}' is not available. Available tools: {}.",
+                            name,
+                            allowed.join(", ")
+                        ))
+                    });
+                    continue;
+                }
+                progress.emit(ChatEvent::running_tool(name, arguments));
+                let started = std::time::Instant::now();
+                let mut result = self.run_tool(name, arguments, cancel).await;
+                let elapsed = started.elapsed();
+                info!("Tool {} finished in {} ms", name, elapsed.as_millis());
+                if let Ok(Some(output)) = &mut result {
+                    output.elapsed = elapsed;
+                    output.call_id = tool_call.id.clone();
+                    progress.emit(ChatEvent::ToolFinished {
+                        tool: name.clone(),
+                        sources: output.sources.len(),
+                        chars: output.content.chars().count(),
+                        ms: elapsed.as_millis() as u64,
+                    });
+                }
+                match &result {
+                    Ok(Some(output)) => self.audit_log.record(AuditEntry::new(name, arguments, Ok(&output.content))),
+                    Ok(None) => self.audit_log.record(AuditEntry::new(name, arguments, Err("missing required arguments"))),
+                    Err(e) => self.audit_log.record(AuditEntry::new(name, arguments, Err(e))),
+                }
+                if let Some(mut output) = result? {
+                    if let Some(hook) = &self.after_tool {
+                        hook(&mut output.content);
+                    }
+                    outputs.push(output);
+                }
+            }
+        }
+
+        Ok(outputs)
+    }
+
+    /// Renders queue and concurrency gauges in the Prometheus text format.
+    pub fn handle_metrics(&self) -> Result<HttpResponse, Error> {
+        let mut body = String::new();
+        body.push_str("# HELP ollama_queue_depth Chat calls waiting for an Ollama inference slot.\n");
+        body.push_str("# TYPE ollama_queue_depth gauge\n");
+        body.push_str(&format!("ollama_queue_depth {}\n", self.ollama_client.queue_depth()));
+        if let Some(in_flight) = self.ollama_client.in_flight() {
+            body.push_str("# HELP ollama_in_flight Chat calls currently running on Ollama.\n");
+            body.push_str("# TYPE ollama_in_flight gauge\n");
+            body.push_str(&format!("ollama_in_flight {}\n", in_flight));
+        }
+        if let Some((sessions, evictions)) = self.registry().python_invoker.session_stats() {
+            body.push_str("# HELP python_sessions Live persistent Python sessions.\n");
+            body.push_str("# TYPE python_sessions gauge\n");
+            body.push_str(&format!("python_sessions {}\n", sessions));
+            body.push_str("# HELP python_session_evictions_total Python sessions evicted to make room for new ones.\n");
+            body.push_str("# TYPE python_session_evictions_total counter\n");
+            body.push_str(&format!("python_session_evictions_total {}\n", evictions));
+        }
+        Ok(HttpResponse::Ok()
+            .content_type("text/plain; version=0.0.4")
+            .body(body))
+    }
+
+    /// Lists the models installed in Ollama.
+    pub async fn handle_models(&self) -> Result<HttpResponse, Error> {
+        match self.ollama_client.list_models().await {
+            Ok(models) => Ok(HttpResponse::Ok().json(models)),
+            Err(e) => {
+                error!("Failed to list Ollama models: {}", e);
+                Ok(HttpResponse::BadGateway().json(ChatApiResponse {
+                    response: format!("Error: {}", e),
+                    ..Default::default()
+                }))
+            }
+        }
+    }
+
+    /// Reloads the system prompt from disk.
+    pub fn handle_reload_system_prompt(&self) -> Result<HttpResponse, Error> {
+        match self.reload_system_prompt() {
+            Ok(()) => Ok(HttpResponse::Ok().json(ChatApiResponse {
+                response: "System prompt reloaded".to_string(),
+                ..Default::default()
+            })),
+            Err(e) => Ok(HttpResponse::InternalServerError().json(ChatApiResponse {
+                response: format!("Error: Failed to reload system prompt: {}", e),
+                ..Default::default()
+            })),
+        }
+    }
+
+    /// Re-reads `.env` and the environment and rebuilds the tool backends from them.
+    pub fn handle_reload_tools(&self) -> Result<HttpResponse, Error> {
+        let config = match Config::reload() {
+            Ok(config) => config,
+            Err(e) => {
+                error!("Failed to reload .env, keeping the current tools: {}", e);
+                return Ok(HttpResponse::InternalServerError().json(ChatApiResponse {
+                    response: format!("Error: Failed to reload .env: {}", e),
+                    ..Default::default()
+                }));
+            }
+        };
+        self.reload_tools(&config);
+        Ok(HttpResponse::Ok().json(ChatApiResponse {
+            response: format!("Tools reloaded: {}", self.tool_names().join(", ")),
+            ..Default::default()
+        }))
+    }
+
+    /// Estimates the tokens `req.text` takes for the requested model. Nothing is sent to Ollama.
+    pub fn handle_tokenize(&self, req: &TokenizeRequest) -> Result<HttpResponse, Error> {
+        let model = req.model
+            .clone()
+            .filter(|m| !m.trim().is_empty())
+            .or_else(|| self.default_model.clone());
+        let estimate = estimate_tokens(&req.text, model.as_deref().unwrap_or_default());
+        Ok(HttpResponse::Ok().json(serde_json::json!({
+            "model": model,
+            "chars": req.text.chars().count(),
+            "tokens": estimate.tokens,
+            "estimate": estimate.estimate,
+            "method": estimate.method,
+        })))
+    }
+
+    /// Cancels a running `/chat` request started with the given `request_id`.
+    pub fn handle_cancel(&self, req: &CancelRequest) -> Result<HttpResponse, Error> {
+        if self.active_requests.cancel(&req.request_id) {
+            info!("Cancelling chat request {}", req.request_id);
+            Ok(HttpResponse::Ok().json(ChatApiResponse {
+                response: format!("Cancelled request '{}'", req.request_id),
+                ..Default::default()
+            }))
+        } else {
+            Ok(HttpResponse::NotFound().json(ChatApiResponse {
+                response: format!("Error: No running request with id '{}'", req.request_id),
+                ..Default::default()
+            }))
+        }
+    }
+
+    /// Runs a registered tool directly with the given arguments, bypassing the model.
+    pub async fn invoke_tool(&self, name: &str, args: Value) -> Result<HttpResponse, Error> {
+        if !self.tools().iter().any(|tool| tool.function.name == name) {
+            return Ok(HttpResponse::NotFound().json(ChatApiResponse {
+                response: format!("Error: Unknown tool '{}'", name),
+                ..Default::default()
+            }));
+        }
+
+        info!("Invoking tool {} directly with arguments: {}", name, args);
+        let result = self.run_tool(name, &args, &Arc::new(CancelToken::default())).await;
+        match &result {
+            Ok(Some(output)) => self.audit_log.record(AuditEntry::direct(name, &args, Ok(&output.content))),
+            Ok(None) => self.audit_log.record(AuditEntry::direct(name, &args, Err("missing required arguments"))),
+            Err(e) => self.audit_log.record(AuditEntry::direct(name, &args, Err(e))),
+        }
+        match result {
+            Ok(Some(output)) => Ok(HttpResponse::Ok().json(output)),
+            Ok(None) => Ok(HttpResponse::BadRequest().json(ChatApiResponse {
+                response: format!("Error: Missing required arguments for tool '{}'", name),
+                ..Default::default()
+            })),
+            Err(e) => Ok(HttpResponse::InternalServerError().json(ChatApiResponse {
+                response: format!("Error: {}", e),
+                ..Default::default()
+            })),
+        }
+    }
+
+    /// Checks `args` against the named tool's parameter schema without running the tool.
+    pub fn validate_tool(&self, name: &str, args: &Value) -> Result<HttpResponse, Error> {
+        let Some(tool) = self.tools().into_iter().find(|tool| tool.function.name == name) else {
+            return Ok(HttpResponse::NotFound().json(ChatApiResponse {
+                response: format!("Error: Unknown tool '{}'", name),
+                ..Default::default()
+            }));
+        };
+
+        let errors = validate_arguments(&tool.function.parameters, args);
+        Ok(HttpResponse::Ok().json(serde_json::json!({
+            "valid": errors.is_empty(),
+            "errors": errors,
+            "parameters": tool.function.parameters,
+        })))
+    }
+
+    /// Names of the tools offered to the model.
+    pub fn tool_names(&self) -> Vec<String> {
+        self.tools().into_iter().map(|tool| tool.function.name).collect()
+    }
+
+    /// Returns every tool offered to the model. File tools are only offered when FILE_ROOT is configured.
+    fn tools(&self) -> Vec<Tool> {
+        Self::tool_definitions(&self.registry())
+    }
+
+    fn tool_definitions(registry: &ToolRegistry) -> Vec<Tool> {
+        let mut tools = vec![Self::create_websearch_tool(), Self::create_python_invoker_tool(registry.python_invoker.sessions_enabled()), Self::create_arxiv_tool(), Self::create_read_feed_tool(), Self::create_convert_tool(), Self::create_weather_tool(), Self::create_encode_decode_tool(), Self::create_render_template_tool(), Self::create_precise_math_tool(), Self::create_generate_qr_tool(), Self::create_datetime_tool(), Self::create_extract_tool(), Self::create_diff_tool(registry.file_root.is_some()), Self::create_json_query_tool(registry.file_root.is_some()), Self::create_hash_tool(registry.file_root.is_some())];
+        if registry.file_root.is_some() {
+            tools.push(Self::create_load_data_tool());
+        }
+        if registry.downloader.is_some() {
+            tools.push(Self::create_download_file_tool());
+        }
+        tools
+    }
+
+    /// Whether `model` should be sent tools. Models listed in NO_TOOL_MODELS never are; otherwise
+    /// support is looked up with `/api/show` when DETECT_TOOL_SUPPORT is on, and assumed if unknown.
+    async fn supports_tools(&self, model: &str) -> bool {
+        if matches_model_list(&self.no_tool_models, model) {
+            return false;
+        }
+        if let Some(&known) = self.tool_support.lock().unwrap().get(model) {
+            return known;
+        }
+        if !self.detect_tool_support {
+            return true;
+        }
+
+        let supported = match self.ollama_client.show_model(model).await {
+            // Older Ollama versions don't report capabilities
+            Ok(details) => details.capabilities.is_empty() || details.capabilities.iter().any(|c| c == "tools"),
+            Err(e) => {
+                error!("Failed to look up tool support for {}: {}", model, e);
+                return true;
+            }
+        };
+        info!("Model {} tool support: {}", model, supported);
+        self.tool_support.lock().unwrap().insert(model.to_string(), supported);
+        supported
+    }
+
+    fn mark_tools_unsupported(&self, model: &str) {
+        info!("Model {} does not support tools, continuing without them", model);
+        self.tool_support.lock().unwrap().insert(model.to_string(), false);
+    }
+
+    /// The tools to send for `req`: none if the model can't use them, only those in
+    /// `allowed_tools` when given, and no websearch when the request answers from cached search results.
+    fn request_tools(&self, offer_tools: bool, req: &ChatRequest) -> Vec<Tool> {
+        if !offer_tools {
+            return Vec::new();
+        }
+        self.tools()
+            .into_iter()
+            .filter(|tool| !(req.use_search_id.is_some() && tool.function.name == "websearch"))
+            .filter(|tool| {
+                req.allowed_tools
+                    .as_ref()
+                    .map(|allowed| allowed.contains(&tool.function.name))
+                    .unwrap_or(true)
+            })
+            .collect()
+    }
+
+    /// Caches `/search` results and returns the id a chat request can use them by.
+    pub fn store_search_results(&self, results: Vec<SearchResult>) -> String {
+        self.search_cache.insert(results)
+    }
+
+    /// The context message and sources for `use_search_id`, or a 404 response for an unknown or
+    /// expired id.
+    fn search_context(&self, req: &ChatRequest) -> Result<Option<(ChatMessage, Vec<Source>)>, HttpResponse> {
+        let Some(search_id) = &req.use_search_id else {
+            return Ok(None);
+        };
+        let mut results = self.search_cache.get(search_id).ok_or_else(|| {
+            HttpResponse::NotFound().json(ChatApiResponse {
+                response: format!("Error: Unknown or expired search_id '{}'", search_id),
+                ..Default::default()
+            })
+        })?;
+        truncate_contents(&mut results, self.registry().search_max_content_chars);
+        let message = ChatMessage::system(format!(
+            "Answer using only these search results; do not search again.\n{}",
+            format_search_results(&results)
+        ));
+        Ok(Some((message, search_sources(&results))))
+    }
+
+    /// Builds the initial conversation: the system prompt with the current time, followed by either
+    /// the client-supplied history or the single user message.
+    /// Returns a 400 response when the supplied history is invalid.
+    fn initial_messages(&self, req: &ChatRequest) -> Result<Vec<ChatMessage>, HttpResponse> {
+        let now = Local::now();
+        let formatted_datetime = now.to_rfc3339();
+        let system_prompt = format!("{} Current date and time: {}", self.system_prompt.read().unwrap(), formatted_datetime);
+
+        let mut messages = vec![ChatMessage::system(system_prompt)];
+
+        let user_message = ChatMessage::user(req.message.clone());
+
+        match &req.messages {
+            Some(history) => {
+                let mut history = history.clone();
+                if !req.message.trim().is_empty() {
+                    history.push(user_message);
+                }
+                validate_history(&history).map_err(|e| {
+                    HttpResponse::BadRequest().json(ChatApiResponse {
+                        response: format!("Error: Invalid messages: {}", e),
+                        ..Default::default()
+                    })
+                })?;
+                messages.extend(history);
+            }
+            None => {
+                if req.message.trim().is_empty() {
+                    return Err(HttpResponse::BadRequest().json(ChatApiResponse {
+                        response: "Error: Either \"message\" or \"messages\" must be provided.".to_string(),
+                        ..Default::default()
+                    }));
+                }
+                messages.push(user_message);
+            }
+        }
+
+        Ok(messages)
+    }
+
+    /// Picks the model named in the request, falling back to the configured default.
+    /// Returns a 400 response when neither is set, and 403 when ALLOWED_MODELS excludes it.
+    fn resolve_model(&self, req: &ChatRequest) -> Result<String, HttpResponse> {
+        let model = req.model
+            .clone()
+            .filter(|m| !m.trim().is_empty())
+            .or_else(|| self.default_model.clone())
+            .ok_or_else(|| {
+                HttpResponse::BadRequest().json(ChatApiResponse {
+                    response: "Error: No model specified. Set \"model\" in the request or configure DEFAULT_MODEL.".to_string(),
+                    ..Default::default()
+                })
+            })?;
+
+        if !self.allowed_models.is_empty() && !matches_model_list(&self.allowed_models, &model) {
+            info!("Rejected request for model {} not in ALLOWED_MODELS", model);
+            return Err(HttpResponse::Forbidden().json(ChatApiResponse {
+                response: format!(
+                    "Error: Model '{}' is not allowed on this server. Allowed models: {}.",
+                    model,
+                    self.allowed_models.join(", ")
+                ),
+                ..Default::default()
+            }));
+        }
+        Ok(model)
+    }
+
+    /// Collects the Ollama settings for a request, applying per-request overrides to configured defaults.
+    /// Returns a 400 response when the request's model options are invalid.
+    fn chat_params(&self, req: &ChatRequest) -> Result<ChatParams, HttpResponse> {
+        if let Some(options) = &req.options {
+            validate_options(options).map_err(|e| {
+                HttpResponse::BadRequest().json(ChatApiResponse {
+                    response: format!("Error: {}", e),
+                    ..Default::default()
+                })
+            })?;
+        }
+
+        let mut options = req.options.clone();
+        if let Some(stop) = &req.stop {
+            options
+                .get_or_insert_with(serde_json::Map::new)
+                .insert("stop".to_string(), serde_json::json!(stop));
+        }
+
+        Ok(ChatParams {
+            keep_alive: req.keep_alive.clone().or_else(|| self.keep_alive.clone()),
+            options,
+            format: req.format.clone(),
+        })
+    }
+
+    /// Sends a one-token chat so Ollama loads `model` before real traffic arrives. Returns how long
+    /// it took, which is mostly load time.
+    pub async fn warm_up(&self, model: &str) -> Result<std::time::Duration, OllamaError> {
+        let started = std::time::Instant::now();
+        let messages = vec![ChatMessage::user("Hi")];
+        let mut options = serde_json::Map::new();
+        options.insert("num_predict".to_string(), serde_json::json!(1));
+        let params = ChatParams { keep_alive: self.keep_alive.clone(), options: Some(options), format: None };
+        self.ollama_client.chat(messages, model.to_string(), Vec::new(), &params).await?;
+        Ok(started.elapsed())
+    }
+
+    /// Calls the model exactly once and returns its raw response, including any tool calls,
+    /// without executing tools. Lets clients run their own tool orchestration.
+    pub async fn handle_chat_once(&self, req: web::Json<ChatRequest>) -> Result<HttpResponse, Error> {
+        let model = match self.resolve_model(&req) {
+            Ok(model) => model,
+            Err(response) => return Ok(response),
+        };
+        info!("Processing single-turn chat request for model: {}", model);
+
+        let mut messages = match self.initial_messages(&req) {
+            Ok(messages) => messages,
+            Err(response) => return Ok(response),
+        };
+        let params = match self.chat_params(&req) {
+            Ok(params) => params,
+            Err(response) => return Ok(response),
+        };
+        match self.search_context(&req) {
+            Ok(Some((message, _))) => messages.insert(1, message),
+            Ok(None) => {}
+            Err(response) => return Ok(response),
+        }
+        self.run_before_model(&mut messages);
+        let request_log = RequestLog::new(req.request_id.as_deref(), req.debug.unwrap_or(false));
+        request_log.detail("messages sent to Ollama", &messages);
+        let offer_tools = self.supports_tools(&model).await;
+        let mut next_call_id = count_tool_calls(&messages);
+        let mut result = self.ollama_client.chat(messages.clone(), model.clone(), self.request_tools(offer_tools, &req), &params).await;
+        if offer_tools && matches!(result, Err(OllamaError::ToolsNotSupported(_))) {
+            self.mark_tools_unsupported(&model);
+            result = self.ollama_client.chat(messages, model, Vec::new(), &params).await;
+        }
+        match result {
+            Ok(mut chat_response) => {
+                if let Some(calls) = chat_response.message.tool_calls.as_mut() {
+                    assign_call_ids(calls, &mut next_call_id);
+                }
+                request_log.detail("Ollama response", &chat_response);
+                Ok(HttpResponse::Ok().json(chat_response))
+            }
+            Err(e) => {
+                error!("Ollama chat error: {}", e);
+                Ok(ollama_error_response(&e).json(ChatApiResponse {
+                    response: format!("Error: {}", e),
+                    ..Default::default()
+                }))
+            }
+        }
+    }
+
+    fn cancelled_response(trace: LoopTrace, include_trace: bool) -> HttpResponse {
+        info!("Chat request was cancelled by the client");
+        HttpResponse::build(status_cancelled()).json(ChatApiResponse {
+            response: "Request cancelled".to_string(),
+            trace: trace.finish(TerminationReason::Cancelled, include_trace),
+            ..Default::default()
+        })
+    }
+
+    /// Handles chat requests by processing the message and interacting with the Ollama client.
+    pub async fn handle_chat(&self, req: web::Json<ChatRequest>) -> Result<HttpResponse, Error> {
+        self.handle_chat_with_progress(req, &Progress::default()).await
+    }
+
+    /// `handle_chat`, reporting each model call and tool run to `progress` as it happens. A chat
+    /// with a `request_id` is also recorded for `/session/{id}/poll`, ending with its answer or error.
+    pub async fn handle_chat_with_progress(&self, req: web::Json<ChatRequest>, progress: &Progress) -> Result<HttpResponse, Error> {
+        // A running chat with the same id keeps its session; `run_chat` answers 409. Dropping
+        // `running` finishes the session, also when this future is dropped before the chat ends
+        let Some(running) = req.request_id.as_deref().and_then(|id| self.sessions.start(id)) else {
+            return self.run_chat(req, progress).await;
+        };
+        let result = self.run_chat(req, &progress.with_session(running.session())).await;
+
+        let response = match result {
+            Ok(response) => response,
+            Err(e) => {
+                running.finish(serde_json::json!({ "stage": "error", "response": format!("Error: {}", e) }));
+                return Err(e);
+            }
+        };
+        let status = response.status();
+        let (response, body) = response.into_parts();
+        let Ok(bytes) = body.try_into_bytes() else {
+            running.finish(serde_json::json!({ "stage": "error", "status": status.as_u16() }));
+            return Ok(HttpResponse::InternalServerError().json(ChatApiResponse {
+                response: "Error: Unexpected streaming response".to_string(),
+                ..Default::default()
+            }));
+        };
+        let mut event = serde_json::from_slice::<Value>(&bytes).unwrap_or_default();
+        if let Some(fields) = event.as_object_mut() {
+            fields.insert("stage".to_string(), Value::from(if status.is_success() { "answer" } else { "error" }));
+            fields.insert("status".to_string(), Value::from(status.as_u16()));
+        }
+        running.finish(event);
+        Ok(response.set_body(BoxBody::new(bytes)))
+    }
+
+    /// Waits up to `timeout` (capped at `POLL_TIMEOUT_SECS`) for events of the chat started with
+    /// `request_id` after index `after`, returning at once if there already are some.
+    pub async fn handle_poll(&self, request_id: &str, after: usize, timeout: Option<u64>) -> Result<HttpResponse, Error> {
+        let Some(session) = self.sessions.get(request_id) else {
+            return Ok(HttpResponse::NotFound().json(ChatApiResponse {
+                response: format!("Error: No chat session with id '{}'", request_id),
+                ..Default::default()
+            }));
+        };
+        let timeout = timeout.map_or(self.poll_timeout, |secs| std::time::Duration::from_secs(secs).min(self.poll_timeout));
+        Ok(HttpResponse::Ok().json(session.poll(after, timeout).await))
+    }
+
+    async fn run_chat(&self, req: web::Json<ChatRequest>, progress: &Progress) -> Result<HttpResponse, Error> {
+        let model = match self.resolve_model(&req) {
+            Ok(model) => model,
+            Err(response) => return Ok(response),
+        };
+        info!("Processing chat request for model: {}", model);
+
+        let mut messages = match self.initial_messages(&req) {
+            Ok(messages) => messages,
+            Err(response) => return Ok(response),
+        };
+        let params = match self.chat_params(&req) {
+            Ok(params) => params,
+            Err(response) => return Ok(response),
+        };
+
+        // Requests with an id can be cancelled; the id is released when this function returns
+        let active_request = match &req.request_id {
+            Some(request_id) => match self.active_requests.register(request_id) {
+                Some(active) => Some(active),
+                None => {
+                    return Ok(HttpResponse::Conflict().json(ChatApiResponse {
+                        response: format!("Error: A request with id '{}' is already running", request_id),
+                        ..Default::default()
+                    }));
+                }
+            },
+            None => None,
+        };
+        let cancel = active_request
+            .as_ref()
+            .map(|active| active.token.clone())
+            .unwrap_or_default();
+        let request_log = RequestLog::new(req.request_id.as_deref(), req.debug.unwrap_or(false));
+
+        let include_trace = req.include_trace.unwrap_or(false);
+        let mut trace = LoopTrace::new();
+        let mut sources: Vec<Source> = Vec::new();
+
+        match self.search_context(&req) {
+            Ok(Some((message, search_sources))) => {
+                messages.insert(1, message);
+                sources = search_sources;
+            }
+            Ok(None) => {}
+            Err(response) => return Ok(response),
+        }
+
+        if req.allowed_tools.as_ref().is_some_and(|allowed| allowed.is_empty()) {
+            return Ok(self.run_pure_chat(&req, model, messages, params, &cancel, &request_log, progress, trace, sources).await);
+        }
+        if req.react.unwrap_or(self.react_mode) {
+            return Ok(self.run_react(&req, model, messages, params, &cancel, &request_log, progress, trace, sources).await);
+        }
+
+        let response;
+        let thinking;
+        let mut incomplete = false;
+        let mut images: Vec<ToolImage> = Vec::new();
+        let mut nudged_for_empty_response = false;
+        let mut tools_ran = false;
+        let mut reprompted_for_ignored_tools = false;
+        let mut json_repairs_left = self.json_repair_attempts;
+        let mut fell_back = false;
+        
+        let mut offer_tools = self.supports_tools(&model).await;
+        // Taken once, so a tool reload mid-request doesn't change what the model is offered
+        let tools = self.request_tools(true, &req);
+        let offered_names: Vec<String> = tools.iter().map(|tool| tool.function.name.clone()).collect();
+        let mut next_call_id = count_tool_calls(&messages);
+
+        loop {
+            if cancel.is_cancelled() {
+                return Ok(Self::cancelled_response(trace, include_trace));
+            }
+
+            if !fell_back && trace.iterations >= self.max_tool_iterations {
+                if !self.tool_limit_fallback {
+                    error!("Chat loop reached {} iterations without a final answer.", self.max_tool_iterations);
+                    return Ok(HttpResponse::BadGateway().json(ChatApiResponse {
+                        response: format!("Error: The model gave no final answer within {} iterations.", self.max_tool_iterations),
+                        trace: trace.finish(TerminationReason::StepLimit, include_trace),
+                        sources,
+                        ..Default::default()
+                    }));
+                }
+                info!("Chat loop reached {} iterations, falling back to one call without tools.", self.max_tool_iterations);
+                offer_tools = false;
+                fell_back = true;
+                messages.push(ChatMessage::user(TOOL_LIMIT_NUDGE));
+            }
+
+            self.run_before_model(&mut messages);
+            request_log.detail("messages sent to Ollama", &messages);
+            progress.emit(ChatEvent::CallingModel { iteration: trace.iterations + 1 });
+
+            // Call Ollama with the messages and websearch tool
+            let offered = if offer_tools { tools.clone() } else { Vec::new() };
+            let chat = self.ollama_client.chat(messages.clone(), model.clone(), offered, &params);
+            let chat_result = tokio::select! {
+                result = chat => result,
+                _ = cancel.cancelled() => return Ok(Self::cancelled_response(trace, include_trace)),
+            };
+            let mut chat_response = match chat_result {
+                Ok(response) => response,
+                Err(OllamaError::ToolsNotSupported(_)) if offer_tools => {
+                    // Degrade to a plain chat instead of failing the request
+                    self.mark_tools_unsupported(&model);
+                    offer_tools = false;
+                    continue;
+                }
+                Err(e) => {
+                    error!("Ollama chat error: {}", e);
+                    return Ok(ollama_error_response(&e).json(ChatApiResponse {
+                        response: format!("Error: {}", e),
+                        trace: trace.finish(TerminationReason::ModelError, include_trace),
+                        ..Default::default()
+                    }));
+                }
+            };
+            
+            request_log.detail("Ollama response", &chat_response);
+
+            if fell_back {
+                // No tools were offered, so running a call now would only restart the thrashing
+                chat_response.message.tool_calls = None;
+            }
+            let has_native_calls = chat_response.message.tool_calls.as_ref().map(|c| !c.is_empty()).unwrap_or(false);
+            if !has_native_calls && !fell_back {
+                // Some models write the call into the content instead of using the tool API
+                let embedded = parse_embedded_tool_calls(&chat_response.message.content, &offered_names);
+                if !embedded.is_empty() {
+                    info!("Recovered {} tool call(s) from the message content", embedded.len());
+                    chat_response.message.tool_calls = Some(embedded);
+                }
+            }
+
+            if let Some(calls) = chat_response.message.tool_calls.as_mut() {
+                assign_call_ids(calls, &mut next_call_id);
+            }
+            info!("Tool calls: {:?}", chat_response.message.tool_calls);
+            trace.record_iteration(
+                chat_response.message.tool_calls.iter()
+                    .flatten()
+                    .map(|call| call.function.name.clone())
+                    .collect(),
+            );
+            // Process any tool calls in the response
+            let tool_result = tokio::select! {
+                result = self.process_tool_calls(&chat_response, &offered_names, &cancel, progress) => result,
+                _ = cancel.cancelled() => return Ok(Self::cancelled_response(trace, include_trace)),
+            };
+            match tool_result {
+                Ok(tool_outputs) if !tool_outputs.is_empty() => {
+                    request_log.detail("tool calls", &chat_response.message.tool_calls);
+                    tools_ran = true;
+
+                    // Add assistant message; the reasoning trace is not fed back to the model
+                    messages.push(ChatMessage::assistant_tool_call(
+                        &chat_response.message.content,
+                        chat_response.message.tool_calls.clone().unwrap_or_default(),
+                    ));
+
+                    // Add one tool message per call, tagged with the id of the call it answers
+                    for tool_output in tool_outputs {
+                        trace.record_tool(&tool_output.name, tool_output.elapsed, tool_output.phases);
+                        request_log.detail(&format!("{} result", tool_output.name), &tool_output.content);
+                        images.extend(tool_output.images.iter().map(|data| ToolImage {
+                            tool: tool_output.name.clone(),
+                            data: data.clone(),
+                        }));
+                        for source in &tool_output.sources {
+                            if !sources.iter().any(|s| s.url == source.url) {
+                                sources.push(source.clone());
+                            }
+                        }
+                        messages.push(ChatMessage::tool(
+                            &tool_output.name,
+                            apply_tool_result_template(
+                                self.tool_result_template.as_deref(),
+                                &tool_output.name,
+                                truncate_tool_output(tool_output.content, self.max_tool_output_chars),
+                            ),
+                            tool_output.call_id,
+                            tool_output.images,
+                        ));
+                    }
+
+                    // Continue the loop to process the tool responses
+                    continue;
+                }
+                Ok(_) => {
+                    self.clean_answer(&model, &mut chat_response.message.content);
+                    if chat_response.message.content.trim().is_empty() {
+                        if !nudged_for_empty_response {
+                            // Smaller models sometimes stall after a tool call, ask once more for an answer
+                            info!("Model returned an empty final response, retrying with a nudge.");
+                            nudged_for_empty_response = true;
+                            messages.push(ChatMessage::user(EMPTY_RESPONSE_NUDGE));
+                            continue;
+                        }
+
+                        error!("Model returned an empty final response after retry.");
+                        return Ok(HttpResponse::BadGateway().json(ChatApiResponse {
+                            response: "Error: The model produced no content for its final answer.".to_string(),
+                            trace: trace.finish(TerminationReason::EmptyResponse, include_trace),
+                            ..Default::default()
+                        }));
+                    }
+
+                    if self.reprompt_ignored_tools
+                        && tools_ran
+                        && !reprompted_for_ignored_tools
+                        && self.ignores_tool_results(&chat_response.message.content)
+                    {
+                        info!("Final response ignores the tool results, re-prompting once.");
+                        reprompted_for_ignored_tools = true;
+                        messages.push(ChatMessage::assistant(chat_response.message.content.clone()));
+                        messages.push(ChatMessage::user(IGNORED_TOOLS_NUDGE));
+                        continue;
+                    }
+
+                    if req.format.is_some() {
+                        match parse_lenient(&chat_response.message.content) {
+                            Ok(value) => chat_response.message.content = value.to_string(),
+                            Err(e) if json_repairs_left > 0 => {
+                                info!("Final response is not valid JSON ({}), asking the model to fix it.", e);
+                                json_repairs_left -= 1;
+                                messages.push(ChatMessage::assistant(chat_response.message.content.clone()));
+                                messages.push(ChatMessage::user(format!("{} Parse error: {}", INVALID_JSON_NUDGE, e)));
+                                continue;
+                            }
+                            Err(e) => error!("Final response is still not valid JSON, returning it as is: {}", e),
+                        }
+                    }
+
+                    // No more tool calls, use the final message content
+                    info!("Final response recieved from the model.");
+                    if !chat_response.done {
+                        error!("Returning an incomplete final response after Ollama disconnected.");
+                        incomplete = true;
+                    }
+                    response = chat_response.message.content;
+                    thinking = chat_response.message.thinking;
+                    break;
+                }
+                Err(e) => {
+                    error!("Tool processing error: {}", e);
+                    return Ok(HttpResponse::InternalServerError().json(ChatApiResponse {
+                        response: format!("Error: {}", e),
+                        trace: trace.finish(TerminationReason::ToolError, include_trace),
+                        ..Default::default()
+                    }));
+                }
+            }
+        }
+
+        let termination = if fell_back { TerminationReason::Fallback } else { TerminationReason::FinalAnswer };
+        Ok(HttpResponse::Ok().json(ChatApiResponse {
+            response,
+            trace: trace.finish(termination, include_trace),
+            sources,
+            thinking: thinking.filter(|_| req.include_thinking.unwrap_or(false)),
+            system_prompt: requested_system_prompt(&req, &messages),
+            incomplete,
+            images,
+        }))
+    }
+
+    /// Answers with a single model call and no tools, for `allowed_tools: []`. Tool calls the
+    /// model writes anyway are not run, and `format` answers are parsed leniently but not repaired.
+    #[allow(clippy::too_many_arguments)]
+    async fn run_pure_chat(
+        &self,
+        req: &ChatRequest,
+        model: String,
+        mut messages: Vec<ChatMessage>,
+        params: ChatParams,
+        cancel: &Arc<CancelToken>,
+        request_log: &RequestLog,
+        progress: &Progress,
+        mut trace: LoopTrace,
+        sources: Vec<Source>,
+    ) -> HttpResponse {
+        let include_trace = req.include_trace.unwrap_or(false);
+        self.run_before_model(&mut messages);
+        request_log.detail("messages sent to Ollama", &messages);
+        progress.emit(ChatEvent::CallingModel { iteration: 1 });
+
+        let chat = self.ollama_client.chat(messages.clone(), model.clone(), Vec::new(), &params);
+        let chat_result = tokio::select! {
+            result = chat => result,
+            _ = cancel.cancelled() => return Self::cancelled_response(trace, include_trace),
+        };
+        let mut chat_response = match chat_result {
+            Ok(response) => response,
+            Err(e) => {
+                error!("Ollama chat error: {}", e);
+                return ollama_error_response(&e).json(ChatApiResponse {
+                    response: format!("Error: {}", e),
+                    trace: trace.finish(TerminationReason::ModelError, include_trace),
+                    ..Default::default()
+                });
+            }
+        };
+        request_log.detail("Ollama response", &chat_response);
+        trace.record_iteration(Vec::new());
+        self.clean_answer(&model, &mut chat_response.message.content);
+
+        if req.format.is_some() {
+            match parse_lenient(&chat_response.message.content) {
+                Ok(value) => chat_response.message.content = value.to_string(),
+                Err(e) => error!("Response is not valid JSON, returning it as is: {}", e),
+            }
+        }
+        if !chat_response.done {
+            error!("Returning an incomplete response after Ollama disconnected.");
+        }
+
+        HttpResponse::Ok().json(ChatApiResponse {
+            response: chat_response.message.content,
+            trace: trace.finish(TerminationReason::FinalAnswer, include_trace),
+            sources,
+            thinking: chat_response.message.thinking.filter(|_| req.include_thinking.unwrap_or(false)),
+            system_prompt: requested_system_prompt(req, &messages),
+            incomplete: !chat_response.done,
+            images: Vec::new(),
+        })
+    }
+
+    /// Runs the conversation as a ReAct loop on `/api/generate` for models without native tool
+    /// calling: the model writes `Action:`/`Action Input:` lines, the tool runs through the usual
+    /// path and its output is appended as an `Observation:` until the model gives a `Final Answer:`.
+    /// Tool images and `format` don't apply in this mode.
+    #[allow(clippy::too_many_arguments)]
+    async fn run_react(
+        &self,
+        req: &ChatRequest,
+        model: String,
+        mut messages: Vec<ChatMessage>,
+        mut params: ChatParams,
+        cancel: &Arc<CancelToken>,
+        request_log: &RequestLog,
+        progress: &Progress,
+        mut trace: LoopTrace,
+        mut sources: Vec<Source>,
+    ) -> HttpResponse {
+        let include_trace = req.include_trace.unwrap_or(false);
+        let tools = self.request_tools(true, req);
+        let tool_names: Vec<String> = tools.iter().map(|t| t.function.name.clone()).collect();
+
+        let options = params.options.get_or_insert_with(serde_json::Map::new);
+        let mut stop: Vec<Value> = options.get("stop").and_then(|s| s.as_array()).cloned().unwrap_or_default();
+        stop.push(Value::String(OBSERVATION_STOP.to_string()));
+        options.insert("stop".to_string(), Value::Array(stop));
+
+        let mut scratchpad = String::new();
+        for _ in 0..REACT_MAX_STEPS {
+            if cancel.is_cancelled() {
+                return Self::cancelled_response(trace, include_trace);
+            }
+
+            self.run_before_model(&mut messages);
+            let prompt = build_prompt(&messages, &tools, &scratchpad);
+            progress.emit(ChatEvent::CallingModel { iteration: trace.iterations + 1 });
+            request_log.detail("prompt sent to Ollama", &prompt);
+            let generate = self.ollama_client.generate(prompt, model.clone(), &params);
+            let generated = tokio::select! {
+                result = generate => result,
+                _ = cancel.cancelled() => return Self::cancelled_response(trace, include_trace),
+            };
+            let generated = match generated {
+                Ok(generated) => generated,
+                Err(e) => {
+                    error!("Ollama generate error: {}", e);
+                    return ollama_error_response(&e).json(ChatApiResponse {
+                        response: format!("Error: {}", e),
+                        trace: trace.finish(TerminationReason::ModelError, include_trace),
+                        ..Default::default()
+                    });
+                }
+            };
+
+            request_log.detail("Ollama response", &generated.response);
+
+            let (name, input, text) = match parse_step(&generated.response) {
+                ReactStep::FinalAnswer(mut answer) => {
+                    trace.record_iteration(Vec::new());
+                    self.clean_answer(&model, &mut answer);
+                    if answer.is_empty() {
+                        error!("Model returned an empty final answer in ReAct mode.");
+                        return HttpResponse::BadGateway().json(ChatApiResponse {
+                            response: "Error: The model produced no content for its final answer.".to_string(),
+                            trace: trace.finish(TerminationReason::EmptyResponse, include_trace),
+                            ..Default::default()
+                        });
+                    }
+                    return HttpResponse::Ok().json(ChatApiResponse {
+                        response: answer,
+                        trace: trace.finish(TerminationReason::FinalAnswer, include_trace),
+                        sources,
+                        thinking: None,
+                        system_prompt: requested_system_prompt(req, &messages),
+                        incomplete: false,
+                        images: Vec::new(),
+                    });
+                }
+                ReactStep::Action { name, input, text } => (name, input, text),
+            };
+            info!("ReAct action: {} {}", name, input);
+            trace.record_iteration(vec![name.clone()]);
+
+            // Run through the same path as native calls, so validation, auditing and hooks apply
+            let call = ChatResponse {
+                model: model.clone(),
+                message: ChatMessage::assistant_tool_call(&text, vec![ToolCall {
+                    id: None,
+                    function: FunctionCall { name: name.clone(), arguments: input },
+                }]),
+                done: true,
+            };
+            let tool_result = tokio::select! {
+                result = self.process_tool_calls(&call, &tool_names, cancel, progress) => result,
+                _ = cancel.cancelled() => return Self::cancelled_response(trace, include_trace),
+            };
+            // A ReAct step makes a single call, so there is at most one output
+            let observation = match tool_result.map(|outputs| outputs.into_iter().next()) {
+                Ok(Some(tool_output)) => {
+                    trace.record_tool(&tool_output.name, tool_output.elapsed, tool_output.phases);
+                    for source in &tool_output.sources {
+                        if !sources.iter().any(|s| s.url == source.url) {
+                            sources.push(source.clone());
+                        }
+                    }
+                    truncate_tool_output(tool_output.content, self.max_tool_output_chars)
+                }
+                Ok(None) => format!(
+                    "Invalid call to tool '{}'. Action must be one of [{}] and Action Input a JSON object with its arguments.",
+                    name,
+                    tool_names.join(", ")
+                ),
+                Err(e) => {
+                    error!("Tool processing error: {}", e);
+                    return HttpResponse::InternalServerError().json(ChatApiResponse {
+                        response: format!("Error: {}", e),
+                        trace: trace.finish(TerminationReason::ToolError, include_trace),
+                        ..Default::default()
+                    });
+                }
+            };
+            request_log.detail(&format!("{} result", name), &observation);
+            scratchpad.push_str(&format!(" {}\n{} {}\nThought:", text, OBSERVATION_STOP, observation.trim()));
+        }
+
+        error!("ReAct loop reached {} steps without a final answer.", REACT_MAX_STEPS);
+        HttpResponse::BadGateway().json(ChatApiResponse {
+            response: format!("Error: The model gave no final answer within {} steps.", REACT_MAX_STEPS),
+            trace: trace.finish(TerminationReason::StepLimit, include_trace),
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{App, HttpServer};
+    use std::collections::VecDeque;
+
+    /// Requests received and replies still to give by a fake Ollama `/api/chat`.
+    #[derive(Default)]
+    struct FakeOllama {
+        requests: Mutex<Vec<Value>>,
+        replies: Mutex<VecDeque<Value>>,
+    }
+
+    async fn fake_chat(body: web::Bytes, fake: web::Data<FakeOllama>) -> HttpResponse {
+        fake.requests.lock().unwrap().push(serde_json::from_slice(&body).unwrap());
+        let reply = fake.replies.lock().unwrap().pop_front().expect("more model calls than expected");
+        HttpResponse::Ok().content_type("application/x-ndjson").body(format!("{}\n", reply))
+    }
+
+    /// Starts a fake Ollama answering chat calls with `replies` in order, and a handler using it.
+    fn handler_with_replies(replies: Vec<Value>) -> (QueryHandler, web::Data<FakeOllama>) {
+        let fake = web::Data::new(FakeOllama { replies: Mutex::new(replies.into()), ..Default::default() });
+        let app_fake = fake.clone();
+        let server = HttpServer::new(move || App::new().app_data(app_fake.clone()).route("/api/chat", web::post().to(fake_chat)))
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .unwrap();
+        let address = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+
+        let mut config = Config::from_env();
+        config.ollama_hosts = vec![format!("http://{}", address)];
+        (QueryHandler::new(&config), fake)
+    }
+
+    fn answer(content: &str) -> Value {
+        serde_json::json!({ "model": "m", "message": { "role": "assistant", "content": content }, "done": true })
+    }
+
+    fn chat_request(message: &str) -> web::Json<ChatRequest> {
+        web::Json(serde_json::from_value(serde_json::json!({ "message": message, "model": "m" })).unwrap())
+    }
+
+    async fn response_json(response: HttpResponse) -> Value {
+        let bytes = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn empty_final_answer_is_retried_with_a_nudge() {
+        let (handler, fake) = handler_with_replies(vec![answer("  \n"), answer("Paris")]);
+        let response = handler.handle_chat(chat_request("Capital of France?")).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response_json(response).await["response"], "Paris");
+        let requests = fake.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        let last = requests[1]["messages"].as_array().unwrap().last().unwrap().clone();
+        assert_eq!(last["role"], "user");
+        assert_eq!(last["content"], EMPTY_RESPONSE_NUDGE);
+    }
+
+    #[actix_web::test]
+    async fn empty_final_answer_after_the_nudge_is_a_bad_gateway() {
+        let (handler, fake) = handler_with_replies(vec![answer(""), answer(" ")]);
+        let response = handler.handle_chat(chat_request("Capital of France?")).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(response_json(response).await["response"], "Error: The model produced no content for its final answer.");
+        assert_eq!(fake.requests.lock().unwrap().len(), 2);
+    }
+
+    fn chat_request_with(fields: Value) -> web::Json<ChatRequest> {
+        let mut request = serde_json::json!({ "message": "What is 2 + 2?", "model": "m" });
+        request.as_object_mut().unwrap().extend(fields.as_object().unwrap().clone());
+        web::Json(serde_json::from_value(request).unwrap())
+    }
+
+    fn thinking_replies() -> Vec<Value> {
+        vec![
+            serde_json::json!({
+                "model": "m",
+                "message": {
+                    "role": "assistant",
+                    "content": "",
+                    "thinking": "I should call a tool.",
+                    "tool_calls": [{ "function": { "name": "no_such_tool", "arguments": {} } }]
+                },
+                "done": true
+            }),
+            serde_json::json!({
+                "model": "m",
+                "message": { "role": "assistant", "content": "4", "thinking": "2 + 2 is 4." },
+                "done": true
+            }),
+        ]
+    }
+
+    #[actix_web::test]
+    async fn thinking_is_returned_only_when_asked_for() {
+        let (handler, _) = handler_with_replies(thinking_replies());
+        let body = response_json(handler.handle_chat(chat_request_with(serde_json::json!({ "include_thinking": true }))).await.unwrap()).await;
+        assert_eq!(body["response"], "4");
+        assert_eq!(body["thinking"], "2 + 2 is 4.");
+
+        let (handler, _) = handler_with_replies(thinking_replies());
+        let body = response_json(handler.handle_chat(chat_request_with(serde_json::json!({}))).await.unwrap()).await;
+        assert_eq!(body["response"], "4");
+        assert!(body.get("thinking").is_none());
+    }
+
+    #[actix_web::test]
+    async fn thinking_is_not_fed_back_to_the_model() {
+        let (handler, fake) = handler_with_replies(thinking_replies());
+        handler.handle_chat(chat_request_with(serde_json::json!({ "include_thinking": true }))).await.unwrap();
+
+        let requests = fake.requests.lock().unwrap();
+        let messages = requests[1]["messages"].as_array().unwrap();
+        let assistant = messages.iter().find(|m| m["role"] == "assistant").unwrap();
+        assert!(assistant.get("thinking").is_none());
+        assert!(messages.iter().all(|m| !m["content"].as_str().unwrap_or_default().contains("I should call a tool.")));
+    }
+
+    #[actix_web::test]
+    async fn before_model_hook_rewrites_the_messages_sent() {
+        let (handler, fake) = handler_with_replies(vec![answer("4")]);
+        let handler = handler.with_before_model(|messages| messages.push(ChatMessage::user("Answer in digits.")));
+        handler.handle_chat(chat_request("What is 2 + 2?")).await.unwrap();
+
+        let requests = fake.requests.lock().unwrap();
+        let last = requests[0]["messages"].as_array().unwrap().last().unwrap().clone();
+        assert_eq!(last["content"], "Answer in digits.");
+    }
+
+    #[actix_web::test]
+    async fn after_tool_hook_rewrites_tool_output() {
+        let call = serde_json::json!({
+            "model": "m",
+            "message": {
+                "role": "assistant",
+                "content": "",
+                "tool_calls": [{ "function": { "name": "websearch", "arguments": {} } }]
+            },
+            "done": true
+        });
+        let (handler, fake) = handler_with_replies(vec![call, answer("4")]);
+        let handler = handler.with_after_tool(|output| *output = output.to_uppercase());
+        handler.handle_chat(chat_request("What is 2 + 2?")).await.unwrap();
+
+        let requests = fake.requests.lock().unwrap();
+        let messages = requests[1]["messages"].as_array().unwrap();
+        let tool = messages.iter().find(|m| m["role"] == "tool").unwrap();
+        assert!(tool["content"].as_str().unwrap().contains("INVALID CALL TO TOOL 'WEBSEARCH'"));
+    }
+
+    /// Writes `contents` to a file in the temp directory unique to this test run.
+    fn temp_prompt(name: &str, contents: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("system_prompt_{}_{}.txt", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[actix_web::test]
+    async fn invalid_prompt_files_keep_the_current_prompt() {
+        let (handler, _) = handler_with_replies(Vec::new());
+        let valid = temp_prompt("valid", b"You are terse.");
+        handler.reload_system_prompt_from(&valid).unwrap();
+
+        for (name, contents) in [("empty", &b"  \n"[..]), ("binary", &b"\xff\xfe prompt"[..])] {
+            let path = temp_prompt(name, contents);
+            assert!(handler.reload_system_prompt_from(&path).is_err());
+            assert_eq!(*handler.system_prompt.read().unwrap(), "You are terse.");
+            fs::remove_file(path).unwrap();
+        }
+        assert!(handler.reload_system_prompt_from(Path::new("/nonexistent/system_prompt.txt")).is_err());
+        assert_eq!(*handler.system_prompt.read().unwrap(), "You are terse.");
+        fs::remove_file(valid).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn readers_never_see_a_torn_prompt_during_reloads() {
+        let (handler, _) = handler_with_replies(Vec::new());
+        let first = "A".repeat(100_000);
+        let second = "B".repeat(100_000);
+        let paths = [temp_prompt("first", first.as_bytes()), temp_prompt("second", second.as_bytes())];
+        handler.reload_system_prompt_from(&paths[0]).unwrap();
+
+        let reloading = std::sync::atomic::AtomicBool::new(true);
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    while reloading.load(std::sync::atomic::Ordering::Relaxed) {
+                        let prompt = handler.system_prompt.read().unwrap().clone();
+                        assert!(prompt == first || prompt == second, "torn prompt of {} bytes", prompt.len());
+                    }
+                });
+            }
+            for i in 0..200 {
+                handler.reload_system_prompt_from(&paths[i % 2]).unwrap();
+            }
+            reloading.store(false, std::sync::atomic::Ordering::Relaxed);
+        });
+
+        for path in paths {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn null_arguments_are_missing_every_required_argument() {
+        let response: ChatResponse = serde_json::from_str(
+            r#"{"model":"m","message":{"role":"assistant","content":"","tool_calls":[{"function":{"name":"websearch","arguments":null}}]},"done":true}"#,
+        ).unwrap();
+        let call = &response.message.tool_calls.unwrap()[0];
+        assert!(call.function.arguments.is_null());
+
+        let tool = QueryHandler::create_websearch_tool();
+        assert_eq!(
+            validate_arguments(&tool.function.parameters, &call.function.arguments),
+            vec!["arguments is missing required field \"query\""]
+        );
+    }
+
+    #[test]
+    fn omitted_arguments_read_as_null() {
+        let call: ToolCall = serde_json::from_str(r#"{"function":{"name":"websearch"}}"#).unwrap();
+        assert!(call.function.arguments.is_null());
+    }
+
+    #[test]
+    fn empty_arguments_are_accepted_when_nothing_is_required() {
+        let parameters = serde_json::json!({
+            "type": "object",
+            "properties": { "timezone": { "type": "string" } }
+        });
+        assert!(validate_arguments(&parameters, &serde_json::json!({})).is_empty());
+        assert!(validate_arguments(&parameters, &Value::Null).is_empty());
+    }
+
+    #[test]
+    fn only_absent_or_null_required_arguments_are_missing() {
+        let tool = QueryHandler::create_convert_tool();
+        let args = serde_json::json!({ "value": 3, "from": null });
+        assert_eq!(
+            validate_arguments(&tool.function.parameters, &args),
+            vec!["arguments is missing required field \"from\"", "arguments is missing required field \"to\""]
+        );
+    }
+
+    #[test]
+    fn wrong_types_are_invalid_and_unknown_arguments_ignored() {
+        let tool = QueryHandler::create_convert_tool();
+        let args = serde_json::json!({ "value": "3", "from": "km", "to": "mi", "precision": 2 });
+        assert_eq!(
+            validate_arguments(&tool.function.parameters, &args),
+            vec!["arguments.value must be of type number, got \"3\""]
+        );
     }
 }
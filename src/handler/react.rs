@@ -0,0 +1,116 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::Value;
+
+use super::json_repair::parse_lenient;
+use crate::llm::ollama::{ChatMessage, Tool};
+
+/// Generation is stopped here so the model can't invent the tool's result.
+pub const OBSERVATION_STOP: &str = "Observation:";
+
+static ACTION: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?m)^\s*Action\s*:\s*(.+?)\s*$").unwrap());
+static ACTION_INPUT: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?s)Action\s+Input\s*:\s*(.*)").unwrap());
+static FINAL_ANSWER: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?s)Final\s+Answer\s*:\s*(.*)").unwrap());
+
+/// One parsed model turn in ReAct mode.
+#[derive(Debug, PartialEq)]
+pub enum ReactStep {
+    /// Run `name` with `input`. `text` is the turn as it goes back into the scratchpad.
+    Action { name: String, input: Value, text: String },
+    FinalAnswer(String),
+}
+
+fn tool_list(tools: &[Tool]) -> String {
+    tools
+        .iter()
+        .map(|tool| format!(
+            "- {}: {} Arguments (JSON schema): {}",
+            tool.function.name, tool.function.description, tool.function.parameters
+        ))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Renders the conversation, the available tools and the steps taken so far as a single
+/// `/api/generate` prompt ending where the model's next `Thought:` goes. System messages come
+/// first, the last user message is the question and earlier turns are shown as a transcript.
+pub fn build_prompt(messages: &[ChatMessage], tools: &[Tool], scratchpad: &str) -> String {
+    let system = messages
+        .iter()
+        .filter(|m| m.role == "system")
+        .map(|m| m.content.trim())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let question_index = messages.iter().rposition(|m| m.role == "user");
+    let question = question_index.map(|i| messages[i].content.trim()).unwrap_or_default();
+
+    let transcript = messages
+        .iter()
+        .enumerate()
+        .filter(|(i, m)| m.role != "system" && Some(*i) != question_index)
+        .map(|(_, m)| match m.role.as_str() {
+            "user" => format!("User: {}", m.content.trim()),
+            "tool" => format!("Tool result: {}", m.content.trim()),
+            _ => format!("Assistant: {}", m.content.trim()),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let tool_names = tools.iter().map(|t| t.function.name.as_str()).collect::<Vec<_>>().join(", ");
+    let mut prompt = format!(
+        "{}\n\nYou can use these tools:\n{}\n\n\
+         Answer using this format:\n\n\
+         Question: the question you must answer\n\
+         Thought: what to do next\n\
+         Action: the tool to use, one of [{}]\n\
+         Action Input: the tool's arguments as a JSON object\n\
+         Observation: the tool's result\n\
+         ... (Thought, Action, Action Input and Observation can repeat)\n\
+         Thought: I now know the final answer\n\
+         Final Answer: the answer to the question\n\n",
+        system,
+        tool_list(tools),
+        tool_names,
+    );
+    if !transcript.is_empty() {
+        prompt.push_str(&format!("Conversation so far:\n{}\n\n", transcript));
+    }
+    prompt.push_str(&format!("Question: {}\n{}Thought:", question, scratchpad));
+    prompt
+}
+
+/// Parses the model's continuation of the prompt. An `Action:` with its `Action Input:` is a tool
+/// call unless a `Final Answer:` comes first; text that follows neither format is taken as the
+/// answer, since small models often just reply. Anything from a made-up `Observation:` on is
+/// ignored.
+pub fn parse_step(text: &str) -> ReactStep {
+    let text = text.split(OBSERVATION_STOP).next().unwrap_or(text).trim_end();
+
+    let final_answer = FINAL_ANSWER.captures(text);
+    let action = ACTION.captures(text);
+    let final_first = match (&final_answer, &action) {
+        (Some(f), Some(a)) => f.get(0).unwrap().start() < a.get(0).unwrap().start(),
+        (Some(_), None) => true,
+        _ => false,
+    };
+    if final_first {
+        let answer = final_answer.unwrap().get(1).map(|m| m.as_str().trim()).unwrap_or_default();
+        return ReactStep::FinalAnswer(answer.to_string());
+    }
+
+    let Some(action) = action else {
+        return ReactStep::FinalAnswer(text.trim().to_string());
+    };
+    let name = action[1]
+        .trim_matches(|c: char| c == '`' || c == '[' || c == ']' || c == '"' || c.is_whitespace())
+        .to_string();
+    let input = ACTION_INPUT
+        .captures(&text[action.get(0).unwrap().end()..])
+        .and_then(|c| parse_lenient(c[1].trim()).ok())
+        .unwrap_or(Value::Null);
+
+    ReactStep::Action { name, input, text: text.trim().to_string() }
+}
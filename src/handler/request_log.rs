@@ -0,0 +1,33 @@
+use log::{log, log_enabled, Level};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static NEXT_REQUEST: AtomicU64 = AtomicU64::new(1);
+
+/// Detailed logging for one chat request: the messages sent to Ollama, its raw responses and tool
+/// arguments and results. These go to the debug level, except for requests sent with `debug`,
+/// which log them at info so one request can be inspected without raising the global level.
+pub struct RequestLog {
+    /// Prefixed to every line so a request's entries can be picked out of concurrent ones.
+    tag: String,
+    level: Level,
+}
+
+impl RequestLog {
+    pub fn new(request_id: Option<&str>, debug: bool) -> Self {
+        let tag = match request_id {
+            Some(id) => id.to_string(),
+            None => format!("#{}", NEXT_REQUEST.fetch_add(1, Ordering::Relaxed)),
+        };
+        Self { tag, level: if debug { Level::Info } else { Level::Debug } }
+    }
+
+    /// Logs `value` as JSON under `label`. Nothing is serialized when the level is filtered out.
+    pub fn detail(&self, label: &str, value: &impl Serialize) {
+        if !log_enabled!(self.level) {
+            return;
+        }
+        let json = serde_json::to_string(value).unwrap_or_else(|e| format!("<unserializable: {}>", e));
+        log!(self.level, "[request {}] {}: {}", self.tag, label, json);
+    }
+}
@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::tools::websearch::SearchResult;
+
+/// How long `/search` results stay available to `use_search_id`.
+const SEARCH_CACHE_TTL: Duration = Duration::from_secs(30 * 60);
+/// Oldest entries are evicted beyond this many cached searches.
+const SEARCH_CACHE_MAX_ENTRIES: usize = 100;
+
+/// Results of recent `/search` calls, kept so a later `/chat` can answer from exactly those results.
+#[derive(Default)]
+pub struct SearchCache {
+    entries: Mutex<HashMap<String, (Instant, Vec<SearchResult>)>>,
+}
+
+/// 128 random bits in hex, so one client can't guess the id of another's search.
+fn random_id() -> String {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).expect("the OS random number generator is unavailable");
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl SearchCache {
+    /// Stores `results` and returns the id to retrieve them with.
+    pub fn insert(&self, results: Vec<SearchResult>) -> String {
+        let id = random_id();
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (stored_at, _)| stored_at.elapsed() < SEARCH_CACHE_TTL);
+        while entries.len() >= SEARCH_CACHE_MAX_ENTRIES {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, (stored_at, _))| *stored_at)
+                .map(|(id, _)| id.clone());
+            match oldest {
+                Some(oldest) => entries.remove(&oldest),
+                None => break,
+            };
+        }
+        entries.insert(id.clone(), (Instant::now(), results));
+        id
+    }
+
+    /// The results stored under `id`, unless they have expired.
+    pub fn get(&self, id: &str) -> Option<Vec<SearchResult>> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(id)
+            .filter(|(stored_at, _)| stored_at.elapsed() < SEARCH_CACHE_TTL)
+            .map(|(_, results)| results.clone())
+    }
+}
@@ -0,0 +1,132 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// How long a finished session's events stay available to `/session/{id}/poll`.
+const SESSION_TTL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Default)]
+struct SessionState {
+    events: Vec<Value>,
+    /// When the chat ended, or None while it is running.
+    finished_at: Option<Instant>,
+}
+
+/// The events of one chat started with a `request_id`, in the order they happened.
+#[derive(Default)]
+pub struct Session {
+    state: Mutex<SessionState>,
+    changed: Notify,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PollResult {
+    /// Events after the `after` index the poll asked for.
+    pub events: Vec<Value>,
+    /// Pass as `after` in the next poll.
+    pub next: usize,
+    /// The chat has ended; its answer or error is the last event.
+    pub done: bool,
+}
+
+impl Session {
+    pub fn push(&self, event: Value) {
+        self.state.lock().unwrap().events.push(event);
+        self.changed.notify_waiters();
+    }
+
+    /// Records the final event and wakes every poll, whether or not it has new events. Only the
+    /// first call has an effect.
+    fn finish(&self, event: Value) {
+        let mut state = self.state.lock().unwrap();
+        if state.finished_at.is_some() {
+            return;
+        }
+        state.events.push(event);
+        state.finished_at = Some(Instant::now());
+        drop(state);
+        self.changed.notify_waiters();
+    }
+
+    fn is_live(&self) -> bool {
+        self.state.lock().unwrap().finished_at.is_none_or(|finished| finished.elapsed() < SESSION_TTL)
+    }
+
+    /// Returns the events after index `after` as soon as there are any, or once the session ends,
+    /// or with none when `timeout` passes first.
+    pub async fn poll(&self, after: usize, timeout: Duration) -> PollResult {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            // Registered before checking, so an event pushed in between still wakes this poll
+            let changed = self.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+
+            {
+                let state = self.state.lock().unwrap();
+                let done = state.finished_at.is_some();
+                if state.events.len() > after || done || tokio::time::Instant::now() >= deadline {
+                    let events = state.events.get(after..).map(<[Value]>::to_vec).unwrap_or_default();
+                    return PollResult { next: after + events.len(), events, done };
+                }
+            }
+
+            let _ = tokio::time::timeout_at(deadline, changed).await;
+        }
+    }
+}
+
+/// The session of a running chat. Dropping it without `finish`, e.g. when the client disconnects
+/// and the handler is dropped mid-chat, ends the session with an error event so polls see `done`
+/// and it expires like any other.
+pub struct RunningSession {
+    session: Arc<Session>,
+}
+
+impl RunningSession {
+    pub fn session(&self) -> Arc<Session> {
+        self.session.clone()
+    }
+
+    /// Ends the session with the chat's answer or error.
+    pub fn finish(self, event: Value) {
+        self.session.finish(event);
+    }
+}
+
+impl Drop for RunningSession {
+    fn drop(&mut self) {
+        self.session.finish(serde_json::json!({
+            "stage": "error",
+            "response": "Error: The chat ended before it completed"
+        }));
+    }
+}
+
+/// Chats started with a `request_id`, kept for polling while they run and for `SESSION_TTL` after.
+#[derive(Default)]
+pub struct SessionEvents {
+    sessions: Mutex<HashMap<String, Arc<Session>>>,
+}
+
+impl SessionEvents {
+    /// Starts recording a chat under `id`, replacing a finished session with the same id.
+    /// Returns None when a chat with that id is still running.
+    pub fn start(&self, id: &str) -> Option<RunningSession> {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, session| session.is_live());
+        if sessions.get(id).is_some_and(|session| session.state.lock().unwrap().finished_at.is_none()) {
+            return None;
+        }
+        let session = Arc::new(Session::default());
+        sessions.insert(id.to_string(), session.clone());
+        Some(RunningSession { session })
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<Session>> {
+        self.sessions.lock().unwrap().get(id).filter(|session| session.is_live()).cloned()
+    }
+}
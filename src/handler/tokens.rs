@@ -0,0 +1,63 @@
+use serde::Serialize;
+
+/// Average characters per token for a model family, from its tokenizer's vocabulary size.
+/// Larger vocabularies pack more text into each token.
+struct Family {
+    name: &'static str,
+    /// Substrings of the model name that identify the family, checked in order.
+    prefixes: &'static [&'static str],
+    chars_per_token: f64,
+}
+
+/// Checked in order, so more specific names come before the ones they contain
+/// (`llama3` before `llama`, `codellama` before both).
+const FAMILIES: &[Family] = &[
+    Family { name: "codellama", prefixes: &["codellama"], chars_per_token: 3.2 },
+    Family { name: "llama3", prefixes: &["llama3", "llama-3"], chars_per_token: 4.0 },
+    Family { name: "llama2", prefixes: &["llama2", "llama-2", "llama"], chars_per_token: 3.5 },
+    Family { name: "mistral", prefixes: &["mistral", "mixtral", "codestral"], chars_per_token: 3.5 },
+    Family { name: "qwen", prefixes: &["qwen"], chars_per_token: 3.8 },
+    Family { name: "gemma", prefixes: &["gemma"], chars_per_token: 4.0 },
+    Family { name: "phi", prefixes: &["phi"], chars_per_token: 3.5 },
+    Family { name: "deepseek", prefixes: &["deepseek"], chars_per_token: 3.8 },
+    Family { name: "gpt-oss", prefixes: &["gpt-oss"], chars_per_token: 4.0 },
+];
+
+/// Used for models not matching any family.
+const DEFAULT_CHARS_PER_TOKEN: f64 = 4.0;
+
+#[derive(Debug, Serialize)]
+pub struct TokenEstimate {
+    pub tokens: usize,
+    /// Always true: no tokenizer is run, see `method`.
+    pub estimate: bool,
+    /// How the count was reached, e.g. `chars/3.5 (mistral)`.
+    pub method: String,
+}
+
+/// CJK and other wide-script characters usually take a token each, whatever the vocabulary.
+fn is_wide(c: char) -> bool {
+    c as u32 >= 0x2E80
+}
+
+/// Estimates how many tokens `text` takes for `model` without running its tokenizer: wide-script
+/// characters count one token each, and the rest are divided by the model family's average
+/// characters per token. Expect it to be within about 20% for prose; code and unusual text vary more.
+pub fn estimate_tokens(text: &str, model: &str) -> TokenEstimate {
+    let lower = model.to_lowercase();
+    let family = FAMILIES
+        .iter()
+        .find(|family| family.prefixes.iter().any(|prefix| lower.contains(prefix)));
+    let chars_per_token = family.map_or(DEFAULT_CHARS_PER_TOKEN, |family| family.chars_per_token);
+
+    let (wide, narrow) = text.chars().fold((0usize, 0usize), |(wide, narrow), c| {
+        if is_wide(c) { (wide + 1, narrow) } else { (wide, narrow + 1) }
+    });
+    let tokens = wide + (narrow as f64 / chars_per_token).ceil() as usize;
+
+    TokenEstimate {
+        tokens,
+        estimate: true,
+        method: format!("chars/{} ({})", chars_per_token, family.map_or("default", |family| family.name)),
+    }
+}
@@ -0,0 +1,41 @@
+use log::error;
+
+use crate::config::Config;
+use crate::tools::{ArxivClient, Downloader, FeedClient, FileRoot, PythonInvoker, UnitConverter, WeatherClient, WebSearchClient};
+
+/// The tool backends built from configuration. Rebuilt whole by `POST /reload-tools` and swapped
+/// in as one value, so a tool call never mixes backends from two configurations.
+pub struct ToolRegistry {
+    pub search_client: WebSearchClient,
+    pub python_invoker: PythonInvoker,
+    pub arxiv_client: ArxivClient,
+    pub unit_converter: UnitConverter,
+    pub weather_client: WeatherClient,
+    pub feed_client: FeedClient,
+    pub file_root: Option<FileRoot>,
+    /// Set only when downloads are allowed and FILE_ROOT is configured.
+    pub downloader: Option<Downloader>,
+    /// Characters of each search result passed to the model.
+    pub search_max_content_chars: usize,
+}
+
+impl ToolRegistry {
+    pub fn from_config(config: &Config) -> Self {
+        let file_root = config.file_root.clone().and_then(|root| {
+            FileRoot::new(root)
+                .map_err(|e| error!("Failed to open FILE_ROOT, file tools disabled: {}", e))
+                .ok()
+        });
+        Self {
+            search_client: WebSearchClient::from_config(config),
+            python_invoker: PythonInvoker::from_config(config),
+            arxiv_client: ArxivClient::from_config(config),
+            unit_converter: UnitConverter::from_config(config),
+            weather_client: WeatherClient::from_config(config),
+            feed_client: FeedClient::from_config(config),
+            downloader: (config.allow_downloads && file_root.is_some()).then(|| Downloader::from_config(config)),
+            file_root,
+            search_max_content_chars: config.search_tool_max_content_chars,
+        }
+    }
+}
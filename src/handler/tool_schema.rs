@@ -0,0 +1,69 @@
+use serde_json::Value;
+
+fn type_matches(expected: &str, value: &Value) -> bool {
+    match expected {
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "null" => value.is_null(),
+        // Types we don't know about aren't ours to reject
+        _ => true,
+    }
+}
+
+/// Checks `value` at `path` against a JSON schema fragment, collecting every problem found.
+/// Covers the subset our tool schemas use: `type`, `enum`, `required`, `properties` and `items`.
+fn validate_value(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    if let Some(expected) = schema.get("type").and_then(|t| t.as_str()) {
+        if !type_matches(expected, value) {
+            errors.push(format!("{} must be of type {}, got {}", path, expected, value));
+            return;
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(|e| e.as_array()) {
+        if !allowed.contains(value) {
+            let allowed: Vec<String> = allowed.iter().map(|v| v.to_string()).collect();
+            errors.push(format!("{} must be one of {}, got {}", path, allowed.join(", "), value));
+        }
+    }
+
+    if let Some(fields) = value.as_object() {
+        if let Some(required) = schema.get("required").and_then(|r| r.as_array()) {
+            for name in required.iter().filter_map(|n| n.as_str()) {
+                if fields.get(name).is_none_or(Value::is_null) {
+                    errors.push(format!("{} is missing required field \"{}\"", path, name));
+                }
+            }
+        }
+        if let Some(properties) = schema.get("properties").and_then(|p| p.as_object()) {
+            // Null fields count as omitted, and fields the schema doesn't list are ignored by the tools
+            for (name, field) in fields.iter().filter(|(_, field)| !field.is_null()) {
+                if let Some(field_schema) = properties.get(name) {
+                    validate_value(field_schema, field, &format!("{}.{}", path, name), errors);
+                }
+            }
+        }
+    }
+
+    if let (Some(items), Some(schema)) = (value.as_array(), schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            validate_value(schema, item, &format!("{}[{}]", path, i), errors);
+        }
+    }
+}
+
+/// Validates tool call `arguments` against the tool's `parameters` schema. Returns every
+/// validation error, each naming the offending argument, or an empty list when the call is valid.
+/// Null arguments are read as an empty object, so they are only invalid for tools with required
+/// parameters.
+pub fn validate_arguments(parameters: &Value, arguments: &Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    let empty = Value::Object(Default::default());
+    let arguments = if arguments.is_null() { &empty } else { arguments };
+    validate_value(parameters, arguments, "arguments", &mut errors);
+    errors
+}
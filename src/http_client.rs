@@ -0,0 +1,53 @@
+use log::{error, info};
+use once_cell::sync::OnceCell;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::egress::EgressPolicy;
+
+/// The extra root certificate from `CA_CERT`, loaded once and shared by every client.
+static EXTRA_ROOT_CERT: OnceCell<Option<reqwest::Certificate>> = OnceCell::new();
+
+fn load_certificate(path: &Path) -> Option<reqwest::Certificate> {
+    let pem = match std::fs::read(path) {
+        Ok(pem) => pem,
+        Err(e) => {
+            error!("Failed to read CA_CERT {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    match reqwest::Certificate::from_pem(&pem) {
+        Ok(certificate) => {
+            info!("Trusting extra root certificate from {}", path.display());
+            Some(certificate)
+        }
+        Err(e) => {
+            error!("Failed to parse CA_CERT {} as PEM: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// A client builder that trusts the configured `CA_CERT` in addition to the system roots and
+/// checks every redirect hop against the egress policy.
+pub fn builder(config: &Config) -> reqwest::ClientBuilder {
+    let certificate = EXTRA_ROOT_CERT.get_or_init(|| config.ca_cert.as_deref().and_then(load_certificate));
+    let builder = reqwest::Client::builder().redirect(EgressPolicy::from_config(config).redirect_policy());
+    match certificate {
+        Some(certificate) => builder.add_root_certificate(certificate.clone()),
+        None => builder,
+    }
+}
+
+/// Builds `builder`, falling back to a default client if the TLS backend can't be initialised.
+pub fn build(builder: reqwest::ClientBuilder) -> reqwest::Client {
+    builder.build().unwrap_or_else(|e| {
+        error!("Failed to build HTTP client, using defaults: {}", e);
+        reqwest::Client::new()
+    })
+}
+
+/// A client for outbound requests that honours `CA_CERT` and the egress policy on redirects.
+pub fn client(config: &Config) -> reqwest::Client {
+    build(builder(config))
+}
@@ -1,3 +1 @@
 pub mod ollama;
-
-pub use ollama::OllamaError;
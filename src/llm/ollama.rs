@@ -1,37 +1,116 @@
 use serde::{Deserialize, Serialize};
 use log::{info, error};
-use serde_json::Value;
+use serde_json::{Map, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 
-const OLLAMA_CHAT_API_URL: &str = "http://localhost:11434/api/chat";
+use crate::config::Config;
+use crate::http_client;
+
+pub const DEFAULT_OLLAMA_HOST: &str = "http://localhost:11434";
+const OLLAMA_CHAT_API_PATH: &str = "/api/chat";
+const OLLAMA_GENERATE_API_PATH: &str = "/api/generate";
+const OLLAMA_TAGS_API_PATH: &str = "/api/tags";
+const OLLAMA_SHOW_API_PATH: &str = "/api/show";
+
+/// Default cap on a chat response body. Generous, since long answers are legitimate.
+pub const DEFAULT_OLLAMA_MAX_RESPONSE_BYTES: usize = 64 * 1024 * 1024;
+
+/// How long an unreachable backend is skipped before it is tried again.
+const UNHEALTHY_BACKOFF: Duration = Duration::from_secs(30);
 
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ChatMessage {
     pub role: String,
+    /// Always serialized, as an empty string for tool-call-only assistant turns; Ollama may omit it.
+    #[serde(default)]
     pub content: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_calls: Option<Vec<ToolCall>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub images: Option<Vec<String>>,
+    /// Reasoning trace returned separately from `content` by reasoning models.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<String>,
+    /// On `tool` messages, the tool that produced the result.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_name: Option<String>,
+    /// On `tool` messages, the `id` of the call this result answers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl ChatMessage {
+    fn with_role(role: &str, content: impl Into<String>) -> Self {
+        Self {
+            role: role.to_string(),
+            content: content.into(),
+            tool_calls: None,
+            images: None,
+            thinking: None,
+            tool_name: None,
+            tool_call_id: None,
+        }
+    }
+
+    pub fn system(content: impl Into<String>) -> Self {
+        Self::with_role("system", content)
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self::with_role("user", content)
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self::with_role("assistant", content)
+    }
+
+    /// The result of a tool call, tagged with the tool and the `id` of the call it answers.
+    /// Images are only sent when there are any.
+    pub fn tool(name: &str, content: impl Into<String>, call_id: Option<String>, images: Vec<String>) -> Self {
+        Self {
+            images: if images.is_empty() { None } else { Some(images) },
+            tool_name: Some(name.to_string()),
+            tool_call_id: call_id,
+            ..Self::with_role("tool", content)
+        }
+    }
+
+    /// The assistant turn that requested `tool_calls`, in the shape Ollama expects to get back:
+    /// whitespace-only content is sent as `""` and the reasoning trace is dropped.
+    pub fn assistant_tool_call(content: &str, tool_calls: Vec<ToolCall>) -> Self {
+        let content = if content.trim().is_empty() { String::new() } else { content.to_string() };
+        Self { tool_calls: Some(tool_calls), ..Self::assistant(content) }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ToolCall {
+    /// Set by Ollama on recent versions, otherwise assigned before the call runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     pub function: FunctionCall,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FunctionCall {
     pub name: String,
+    /// Null when the model omits the arguments entirely.
+    #[serde(default)]
     pub arguments: Value,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Clone)]
 pub struct Tool {
     #[serde(rename = "type")]
     pub tool_type: String,
     pub function: ToolFunction,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Clone)]
 pub struct ToolFunction {
     pub name: String,
     pub description: String,
@@ -44,6 +123,10 @@ pub struct OllamaRequest {
     pub prompt: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<Map<String, Value>>,
 }
 
 #[derive(Serialize)]
@@ -51,7 +134,25 @@ pub struct ChatRequest {
     pub model: String,
     pub messages: Vec<ChatMessage>,
     pub stream: bool,
+    /// Left out when empty; some Ollama versions handle an empty array differently from none.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     pub tools: Vec<Tool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<Map<String, Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<Value>,
+}
+
+/// Optional per-request settings passed through to Ollama. `None` fields use Ollama's defaults.
+#[derive(Debug, Clone, Default)]
+pub struct ChatParams {
+    pub keep_alive: Option<Value>,
+    /// Model options such as `temperature` or `num_ctx`.
+    pub options: Option<Map<String, Value>>,
+    /// Output format: `"json"` or a JSON schema the answer must follow.
+    pub format: Option<Value>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -63,64 +164,681 @@ pub struct OllamaResponse {
     pub done: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ChatResponse {
     #[allow(dead_code)]
     pub model: String,
     pub message: ChatMessage,
-    #[allow(dead_code)]
+    /// False when the reply was cut off before Ollama finished it.
+    #[serde(default)]
     pub done: bool,
 }
 
+/// A locally installed model, as listed by Ollama's `/api/tags`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ModelInfo {
+    pub name: String,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub modified_at: String,
+}
+
+/// The parts of Ollama's `/api/show` response this server uses.
+#[derive(Debug, Deserialize)]
+pub struct ModelDetails {
+    /// Features such as `completion`, `tools` or `vision`. Empty on Ollama versions that don't report them.
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TagsResponse {
+    models: Vec<ModelInfo>,
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum OllamaError {
     #[error("Failed to send request to Ollama: {0}")]
     RequestError(#[from] reqwest::Error),
     #[error("Ollama API error: {0}")]
     ApiError(String),
+    #[error("No Ollama backend is reachable")]
+    NoBackendAvailable,
+    #[error("Not enough memory to load the model: {0}. Try a smaller or more quantized model, or free GPU/system memory.")]
+    OutOfMemory(String),
+    #[error("Model not found: {0}. Pull it with `ollama pull <model>` or pick an installed model from /models.")]
+    ModelNotFound(String),
+    #[error("Context length exceeded: {0}. Shorten the conversation or raise num_ctx in options.")]
+    ContextLengthExceeded(String),
+    #[error("Model does not support tools: {0}")]
+    ToolsNotSupported(String),
+    #[error("Ollama is busy; timed out after {0:?} waiting for a free inference slot")]
+    Busy(Duration),
+    #[error("Ollama response exceeded the {0} byte limit")]
+    ResponseTooLarge(usize),
+}
+
+impl OllamaError {
+    /// Maps an Ollama error response to a specific variant by recognising common error signatures.
+    pub fn from_api_error(status: reqwest::StatusCode, message: String) -> Self {
+        let lower = message.to_lowercase();
+        if lower.contains("requires more system memory")
+            || lower.contains("out of memory")
+            || lower.contains("insufficient memory")
+            || lower.contains("cudamalloc failed")
+        {
+            OllamaError::OutOfMemory(message)
+        } else if lower.contains("does not support tools") {
+            OllamaError::ToolsNotSupported(message)
+        } else if (lower.contains("model") && lower.contains("not found")) || status == reqwest::StatusCode::NOT_FOUND {
+            OllamaError::ModelNotFound(message)
+        } else if lower.contains("context length")
+            || lower.contains("context window")
+            || lower.contains("exceeds the context")
+            || lower.contains("too many tokens")
+        {
+            OllamaError::ContextLengthExceeded(message)
+        } else {
+            OllamaError::ApiError(message)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    error: String,
 }
 
+/// Longest error text passed on when the body isn't Ollama's JSON error, e.g. a proxy's HTML page.
+const MAX_RAW_ERROR_CHARS: usize = 500;
+
+/// The clean message from an error response body: the `error` field of Ollama's
+/// `{"error": "..."}`, or otherwise the body's text with any HTML tags removed.
+fn error_message(status: reqwest::StatusCode, body: &str) -> String {
+    if let Ok(parsed) = serde_json::from_str::<ErrorBody>(body) {
+        return parsed.error;
+    }
+
+    let mut text = String::with_capacity(body.len());
+    let mut in_tag = false;
+    for c in body.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    let text: String = text
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(MAX_RAW_ERROR_CHARS)
+        .collect();
+    if text.is_empty() {
+        format!("HTTP {}", status)
+    } else {
+        text
+    }
+}
+
+/// Parses a successful response body as `T`. Some Ollama-compatible servers answer errors with
+/// status 200, so a body that doesn't fit is checked for an `{"error": "..."}` shape before
+/// giving up with the parse error and the start of the body.
+fn parse_success_body<T: serde::de::DeserializeOwned>(body: &[u8], what: &str) -> Result<T, OllamaError> {
+    serde_json::from_slice(body).map_err(|e| {
+        if let Ok(parsed) = serde_json::from_slice::<ErrorBody>(body) {
+            error!("Ollama returned an error with a success status: {}", parsed.error);
+            return OllamaError::from_api_error(reqwest::StatusCode::OK, parsed.error);
+        }
+        let text = String::from_utf8_lossy(body);
+        let excerpt: String = text.chars().take(MAX_RAW_ERROR_CHARS).collect();
+        OllamaError::ApiError(format!("Invalid {} response: {}. Body: {}", what, e, excerpt.trim()))
+    })
+}
+
+/// Adds one line of a streamed chat reply to the response so far: content and thinking are
+/// appended, tool calls collected and `done` taken from the latest line.
+fn merge_chat_chunk(merged: &mut Option<ChatResponse>, line: &[u8]) -> Result<(), OllamaError> {
+    if line.iter().all(u8::is_ascii_whitespace) {
+        return Ok(());
+    }
+    let chunk: ChatResponse = parse_success_body(line, "chat")?;
+    let Some(response) = merged else {
+        *merged = Some(chunk);
+        return Ok(());
+    };
+    response.message.content.push_str(&chunk.message.content);
+    if let Some(thinking) = chunk.message.thinking {
+        response.message.thinking.get_or_insert_with(String::new).push_str(&thinking);
+    }
+    if let Some(tool_calls) = chunk.message.tool_calls {
+        response.message.tool_calls.get_or_insert_with(Vec::new).extend(tool_calls);
+    }
+    response.done = chunk.done;
+    Ok(())
+}
+
+/// Merges the lines of a streamed chat reply as its bytes arrive, whatever the chunk boundaries.
+struct ChatStreamReader {
+    merged: Option<ChatResponse>,
+    /// Bytes after the last complete line.
+    pending: Vec<u8>,
+    total: usize,
+    max_bytes: usize,
+}
+
+impl ChatStreamReader {
+    fn new(max_bytes: usize) -> Self {
+        Self { merged: None, pending: Vec::new(), total: 0, max_bytes }
+    }
+
+    fn push(&mut self, chunk: &[u8]) -> Result<(), OllamaError> {
+        self.total += chunk.len();
+        if self.total > self.max_bytes {
+            error!("Ollama response exceeded {} bytes, aborting", self.max_bytes);
+            return Err(OllamaError::ResponseTooLarge(self.max_bytes));
+        }
+        self.pending.extend_from_slice(chunk);
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            merge_chat_chunk(&mut self.merged, &line)?;
+        }
+        Ok(())
+    }
+
+    /// The reply once the stream has ended, including a last line without a newline.
+    fn finish(mut self) -> Result<ChatResponse, OllamaError> {
+        merge_chat_chunk(&mut self.merged, &self.pending)?;
+        self.merged.ok_or_else(|| OllamaError::ApiError("Empty chat response".to_string()))
+    }
+
+    /// The partial reply after the connection dropped, marked not done, or None when no content
+    /// had arrived. An unfinished last line is dropped.
+    fn disconnected(self) -> Option<ChatResponse> {
+        let partial = self.merged.filter(|partial| !partial.message.content.is_empty())?;
+        info!("Returning the {} characters received before the disconnect", partial.message.content.chars().count());
+        Some(ChatResponse { done: false, ..partial })
+    }
+}
+
+/// Reads a failed response into the matching error variant.
+async fn error_from_response(response: reqwest::Response) -> OllamaError {
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    let message = error_message(status, &body);
+    error!("Ollama API error: {}", message);
+    OllamaError::from_api_error(status, message)
+}
+
+/// One Ollama server in the pool.
+struct Backend {
+    base_url: String,
+    /// Set after a connection failure; the backend is skipped until then.
+    unhealthy_until: Mutex<Option<Instant>>,
+}
+
+impl Backend {
+    fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            unhealthy_until: Mutex::new(None),
+        }
+    }
+
+    fn is_healthy(&self) -> bool {
+        match *self.unhealthy_until.lock().unwrap() {
+            Some(until) => Instant::now() >= until,
+            None => true,
+        }
+    }
+
+    fn mark_unhealthy(&self) {
+        *self.unhealthy_until.lock().unwrap() = Some(Instant::now() + UNHEALTHY_BACKOFF);
+    }
+
+    fn mark_healthy(&self) {
+        *self.unhealthy_until.lock().unwrap() = None;
+    }
+}
+
+/// Client for one or more Ollama servers. Requests are spread round-robin across backends
+/// and fail over to the next backend when one is unreachable.
 pub struct OllamaClient {
-    client: reqwest::Client
+    client: reqwest::Client,
+    backends: Vec<Backend>,
+    next_backend: AtomicUsize,
+    /// Limits concurrent chat calls when OLLAMA_MAX_CONCURRENT is set; excess calls queue here.
+    inference_slots: Option<Arc<Semaphore>>,
+    inference_limit: usize,
+    queue_timeout: Duration,
+    /// Chat calls currently waiting for a slot.
+    queued: AtomicUsize,
+    /// Chat responses larger than this are abandoned rather than buffered.
+    max_response_bytes: usize,
+}
+
+/// Counts a chat call as queued until it gets a slot or gives up.
+struct QueuedGuard<'a>(&'a AtomicUsize);
+
+impl Drop for QueuedGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
 }
 
 impl OllamaClient {
+    #[allow(dead_code)]
     pub fn new() -> Self {
+        Self::with_hosts(&[DEFAULT_OLLAMA_HOST.to_string()])
+    }
+
+    pub fn with_hosts(hosts: &[String]) -> Self {
+        let mut backends: Vec<Backend> = hosts.iter().map(|h| Backend::new(h)).collect();
+        if backends.is_empty() {
+            backends.push(Backend::new(DEFAULT_OLLAMA_HOST));
+        }
         Self {
-            client: reqwest::Client::new()
+            client: reqwest::Client::new(),
+            backends,
+            next_backend: AtomicUsize::new(0),
+            inference_slots: None,
+            inference_limit: 0,
+            queue_timeout: Duration::ZERO,
+            queued: AtomicUsize::new(0),
+            max_response_bytes: DEFAULT_OLLAMA_MAX_RESPONSE_BYTES,
         }
     }
 
-    pub async fn chat(&self, messages: Vec<ChatMessage>, model: String, tools: Vec<Tool>) -> Result<ChatResponse, OllamaError> {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            client: http_client::client(config),
+            inference_slots: config.ollama_max_concurrent.map(|n| Arc::new(Semaphore::new(n))),
+            inference_limit: config.ollama_max_concurrent.unwrap_or(0),
+            queue_timeout: Duration::from_secs(config.ollama_queue_timeout_secs),
+            max_response_bytes: config.ollama_max_response_bytes,
+            ..Self::with_hosts(&config.ollama_hosts)
+        }
+    }
+
+    /// Number of chat calls waiting for an inference slot.
+    pub fn queue_depth(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    /// Number of chat calls currently holding an inference slot, when concurrency is limited.
+    pub fn in_flight(&self) -> Option<usize> {
+        let slots = self.inference_slots.as_ref()?;
+        Some(self.inference_limit - slots.available_permits())
+    }
+
+    /// Sends a request built by `build` for a backend's base URL, starting at the next backend in
+    /// round-robin order. Backends that can't be connected to are marked unhealthy and the next one
+    /// is tried; once a request has been sent it is never repeated on another backend.
+    /// Unhealthy backends are only tried once every healthy one has failed.
+    async fn send_with_failover<F>(&self, build: F) -> Result<reqwest::Response, OllamaError>
+    where
+        F: Fn(&str) -> reqwest::RequestBuilder,
+    {
+        let count = self.backends.len();
+        let start = self.next_backend.fetch_add(1, Ordering::Relaxed) % count;
+        let order: Vec<&Backend> = (0..count).map(|i| &self.backends[(start + i) % count]).collect();
+        let (healthy, unhealthy): (Vec<&Backend>, Vec<&Backend>) = order.into_iter().partition(|b| b.is_healthy());
+
+        let mut last_error = None;
+        for backend in healthy.into_iter().chain(unhealthy) {
+            match build(&backend.base_url).send().await {
+                Ok(response) => {
+                    backend.mark_healthy();
+                    return Ok(response);
+                }
+                // Only a failed connect is safe to retry elsewhere: the request never reached the
+                // backend, whereas after a timeout it may already be generating
+                Err(e) if e.is_connect() => {
+                    error!("Ollama backend {} is unreachable: {}", backend.base_url, e);
+                    backend.mark_unhealthy();
+                    last_error = Some(e);
+                }
+                Err(e) if e.is_timeout() => {
+                    error!("Ollama backend {} timed out: {}", backend.base_url, e);
+                    backend.mark_unhealthy();
+                    return Err(OllamaError::RequestError(e));
+                }
+                Err(e) => return Err(OllamaError::RequestError(e)),
+            }
+        }
+
+        Err(last_error.map(OllamaError::RequestError).unwrap_or(OllamaError::NoBackendAvailable))
+    }
+
+    /// Reads a response body, aborting once it grows past `max_response_bytes` so a misbehaving
+    /// endpoint can't exhaust memory.
+    async fn read_capped(&self, mut response: reqwest::Response) -> Result<Vec<u8>, OllamaError> {
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if body.len() + chunk.len() > self.max_response_bytes {
+                error!("Ollama response exceeded {} bytes, aborting", self.max_response_bytes);
+                return Err(OllamaError::ResponseTooLarge(self.max_response_bytes));
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body)
+    }
+
+    /// Waits for an inference slot when concurrency is limited. The permit must be held until
+    /// the response has been read, so only N inference requests run at once.
+    async fn acquire_slot(&self) -> Result<Option<OwnedSemaphorePermit>, OllamaError> {
+        let Some(slots) = &self.inference_slots else {
+            return Ok(None);
+        };
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        let _queued = QueuedGuard(&self.queued);
+        match tokio::time::timeout(self.queue_timeout, slots.clone().acquire_owned()).await {
+            Ok(Ok(permit)) => Ok(Some(permit)),
+            // The semaphore is never closed
+            Ok(Err(_)) => Ok(None),
+            Err(_) => {
+                error!("Timed out waiting for an Ollama inference slot");
+                Err(OllamaError::Busy(self.queue_timeout))
+            }
+        }
+    }
+
+    pub async fn chat(&self, messages: Vec<ChatMessage>, model: String, tools: Vec<Tool>, params: &ChatParams) -> Result<ChatResponse, OllamaError> {
         info!("Sending chat request to Ollama with model: {}", model);
+        let _permit = self.acquire_slot().await?;
         
         let request = ChatRequest {
             model,
             messages,
-            stream: false,
+            // Streamed so that content received before a dropped connection isn't lost
+            stream: true,
             tools,
+            keep_alive: params.keep_alive.clone(),
+            options: params.options.clone(),
+            format: params.format.clone(),
         };
 
         let response = self
-            .client
-            .post(OLLAMA_CHAT_API_URL)
-            .json(&request)
-            .send()
-            .await
-            .map_err(OllamaError::RequestError)?;
+            .send_with_failover(|base_url| {
+                self.client
+                    .post(format!("{}{}", base_url, OLLAMA_CHAT_API_PATH))
+                    .json(&request)
+            })
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(error_from_response(response).await);
+        }
+
+        let chat_response = self.read_chat_stream(response).await?;
+
+        info!("Received response from Ollama chat");
+        Ok(chat_response)
+    }
+
+    /// Reads a streamed chat reply, one JSON object per line, into a single response. When the
+    /// connection drops after some content has arrived, that content is returned with `done`
+    /// unset rather than failing. Stops once `max_response_bytes` have been read.
+    async fn read_chat_stream(&self, mut response: reqwest::Response) -> Result<ChatResponse, OllamaError> {
+        let mut reader = ChatStreamReader::new(self.max_response_bytes);
+        loop {
+            match response.chunk().await {
+                Ok(Some(chunk)) => reader.push(&chunk)?,
+                Ok(None) => return reader.finish(),
+                Err(e) => {
+                    error!("Ollama connection dropped mid-response: {}", e);
+                    return reader.disconnected().ok_or(OllamaError::RequestError(e));
+                }
+            }
+        }
+    }
+
+    /// Completes a raw `prompt` with `/api/generate`, for models driven through a text protocol
+    /// rather than the chat API's tool calls. `format` in `params` is ignored.
+    pub async fn generate(&self, prompt: String, model: String, params: &ChatParams) -> Result<OllamaResponse, OllamaError> {
+        info!("Sending generate request to Ollama with model: {}", model);
+        let _permit = self.acquire_slot().await?;
+
+        let request = OllamaRequest {
+            model,
+            prompt,
+            stream: Some(false),
+            keep_alive: params.keep_alive.clone(),
+            options: params.options.clone(),
+        };
+
+        let response = self
+            .send_with_failover(|base_url| {
+                self.client
+                    .post(format!("{}{}", base_url, OLLAMA_GENERATE_API_PATH))
+                    .json(&request)
+            })
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(error_from_response(response).await);
+        }
+
+        let body = self.read_capped(response).await?;
+        parse_success_body(&body, "generate")
+    }
+
+    /// Fetches a model's metadata from `/api/show`.
+    pub async fn show_model(&self, model: &str) -> Result<ModelDetails, OllamaError> {
+        let body = serde_json::json!({ "model": model });
+        let response = self
+            .send_with_failover(|base_url| {
+                self.client
+                    .post(format!("{}{}", base_url, OLLAMA_SHOW_API_PATH))
+                    .json(&body)
+            })
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(error_from_response(response).await);
+        }
+
+        response.json().await.map_err(OllamaError::RequestError)
+    }
+
+    /// Lists the models installed in Ollama.
+    pub async fn list_models(&self) -> Result<Vec<ModelInfo>, OllamaError> {
+        let response = self
+            .send_with_failover(|base_url| self.client.get(format!("{}{}", base_url, OLLAMA_TAGS_API_PATH)))
+            .await?;
 
         if !response.status().is_success() {
-            let error_msg = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            error!("Ollama API error: {}", error_msg);
-            return Err(OllamaError::ApiError(error_msg));
+            return Err(error_from_response(response).await);
         }
 
-        let chat_response: ChatResponse = response
+        let tags: TagsResponse = response
             .json()
             .await
             .map_err(OllamaError::RequestError)?;
+        Ok(tags.models)
+    }
+}
 
-        info!("Received response from Ollama chat");
-        Ok(chat_response)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_BYTES: usize = 1024 * 1024;
+
+    fn read(chunks: &[&str]) -> ChatStreamReader {
+        let mut reader = ChatStreamReader::new(MAX_BYTES);
+        for chunk in chunks {
+            reader.push(chunk.as_bytes()).unwrap();
+        }
+        reader
+    }
+
+    #[test]
+    fn thinking_is_read_when_present() {
+        let message: ChatMessage = serde_json::from_str(r#"{"role":"assistant","content":"4","thinking":"2 + 2 is 4."}"#).unwrap();
+        assert_eq!(message.content, "4");
+        assert_eq!(message.thinking.as_deref(), Some("2 + 2 is 4."));
+
+        let message: ChatMessage = serde_json::from_str(r#"{"role":"assistant","content":"4"}"#).unwrap();
+        assert_eq!(message.thinking, None);
+    }
+
+    #[test]
+    fn absent_thinking_is_not_serialized() {
+        let message: ChatMessage = serde_json::from_str(r#"{"role":"user","content":"hi"}"#).unwrap();
+        let json = serde_json::to_value(&message).unwrap();
+        assert!(json.get("thinking").is_none());
+    }
+
+    fn tool_calls() -> Vec<ToolCall> {
+        serde_json::from_str(r#"[{"id":"call_0","function":{"name":"websearch","arguments":{"query":"rust"}}}]"#).unwrap()
+    }
+
+    #[test]
+    fn tool_call_turn_with_empty_content_serializes_an_empty_string() {
+        for content in ["", "  \n "] {
+            let json = serde_json::to_value(ChatMessage::assistant_tool_call(content, tool_calls())).unwrap();
+            assert_eq!(
+                json,
+                serde_json::json!({
+                    "role": "assistant",
+                    "content": "",
+                    "tool_calls": [{ "id": "call_0", "function": { "name": "websearch", "arguments": { "query": "rust" } } }]
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn tool_call_turn_keeps_real_content() {
+        let json = serde_json::to_value(ChatMessage::assistant_tool_call("Searching.", tool_calls())).unwrap();
+        assert_eq!(json["content"], "Searching.");
+    }
+
+    #[test]
+    fn tool_call_reply_without_content_round_trips_with_an_empty_string() {
+        let message: ChatMessage = serde_json::from_str(
+            r#"{"role":"assistant","tool_calls":[{"function":{"name":"websearch","arguments":{"query":"rust"}}}]}"#,
+        )
+        .unwrap();
+        assert_eq!(serde_json::to_value(&message).unwrap()["content"], "");
+    }
+
+    #[test]
+    fn success_body_without_message_is_a_clean_error() {
+        let result = parse_success_body::<ChatResponse>(br#"{"model":"m","done":true}"#, "chat");
+        match result {
+            Err(OllamaError::ApiError(message)) => {
+                assert!(message.starts_with("Invalid chat response: missing field `message`"), "{}", message);
+                assert!(message.ends_with(r#"Body: {"model":"m","done":true}"#), "{}", message);
+            }
+            other => panic!("expected an ApiError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn error_shaped_success_body_reports_the_error() {
+        let result = parse_success_body::<ChatResponse>(br#"{"error":"model 'llama9' not found"}"#, "chat");
+        assert!(matches!(result, Err(OllamaError::ModelNotFound(message)) if message == "model 'llama9' not found"));
+
+        let result = parse_success_body::<ChatResponse>(br#"{"error":"server overloaded"}"#, "chat");
+        assert!(matches!(result, Err(OllamaError::ApiError(message)) if message == "server overloaded"));
+    }
+
+    #[test]
+    fn truncated_success_body_is_a_clean_error() {
+        let body = br#"{"model":"m","message":{"role":"assistant","content":"Hel"#;
+        match parse_success_body::<ChatResponse>(body, "chat") {
+            Err(OllamaError::ApiError(message)) => {
+                assert!(message.starts_with("Invalid chat response: EOF while parsing"), "{}", message);
+            }
+            other => panic!("expected an ApiError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn long_bodies_are_cut_in_the_error() {
+        let body = format!("<html>{}</html>", "x".repeat(MAX_RAW_ERROR_CHARS * 2));
+        let Err(OllamaError::ApiError(message)) = parse_success_body::<ChatResponse>(body.as_bytes(), "chat") else {
+            panic!("expected an ApiError");
+        };
+        assert!(message.len() < body.len());
+    }
+
+    #[test]
+    fn merges_content_and_thinking_across_lines() {
+        let stream = r#"{"model":"m","message":{"role":"assistant","content":"","thinking":"Let me "},"done":false}
+{"model":"m","message":{"role":"assistant","content":"","thinking":"think."},"done":false}
+{"model":"m","message":{"role":"assistant","content":"Hello"},"done":false}
+{"model":"m","message":{"role":"assistant","content":", world"},"done":true}
+"#;
+        let response = read(&[stream]).finish().unwrap();
+        assert_eq!(response.message.content, "Hello, world");
+        assert_eq!(response.message.thinking.as_deref(), Some("Let me think."));
+        assert!(response.done);
+    }
+
+    #[test]
+    fn collects_tool_calls_from_every_line() {
+        let stream = r#"{"model":"m","message":{"role":"assistant","content":"","tool_calls":[{"function":{"name":"websearch","arguments":{"query":"rust"}}}]},"done":false}
+{"model":"m","message":{"role":"assistant","content":"","tool_calls":[{"function":{"name":"get_weather","arguments":{"location":"Oslo"}}}]},"done":true}
+"#;
+        let response = read(&[stream]).finish().unwrap();
+        let names: Vec<String> = response.message.tool_calls.unwrap().into_iter().map(|call| call.function.name).collect();
+        assert_eq!(names, vec!["websearch", "get_weather"]);
+    }
+
+    #[test]
+    fn lines_split_across_chunks_are_reassembled() {
+        let stream = r#"{"model":"m","message":{"role":"assistant","content":"Hi"},"done":false}
+{"model":"m","message":{"role":"assistant","content":"!"},"done":true}"#;
+        let (first, rest) = stream.split_at(30);
+        let (second, third) = rest.split_at(60);
+        let response = read(&[first, second, third]).finish().unwrap();
+        assert_eq!(response.message.content, "Hi!");
+        assert!(response.done);
+    }
+
+    #[test]
+    fn stream_ending_without_a_done_line_is_not_done() {
+        let stream = r#"{"model":"m","message":{"role":"assistant","content":"Partial"},"done":false}
+"#;
+        let response = read(&[stream]).finish().unwrap();
+        assert_eq!(response.message.content, "Partial");
+        assert!(!response.done);
+    }
+
+    #[test]
+    fn disconnect_returns_the_content_so_far() {
+        let stream = r#"{"model":"m","message":{"role":"assistant","content":"The answer is"},"done":false}
+{"model":"m","message":{"role":"assistant","content":" 4"},"do"#;
+        let partial = read(&[stream]).disconnected().unwrap();
+        assert_eq!(partial.message.content, "The answer is");
+        assert!(!partial.done);
+    }
+
+    #[test]
+    fn disconnect_before_any_content_is_an_error() {
+        assert!(read(&[]).disconnected().is_none());
+        let thinking_only = r#"{"model":"m","message":{"role":"assistant","content":"","thinking":"Hmm"},"done":false}
+"#;
+        assert!(read(&[thinking_only]).disconnected().is_none());
+    }
+
+    #[test]
+    fn empty_stream_is_an_error() {
+        assert!(matches!(read(&[]).finish(), Err(OllamaError::ApiError(_))));
+    }
+
+    #[test]
+    fn stream_over_the_limit_is_aborted() {
+        let mut reader = ChatStreamReader::new(8);
+        assert!(matches!(reader.push(b"0123456789"), Err(OllamaError::ResponseTooLarge(8))));
     }
 }
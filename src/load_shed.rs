@@ -0,0 +1,99 @@
+use actix_web::body::{BodySize, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header;
+use actix_web::middleware::Next;
+use actix_web::web::Bytes;
+use actix_web::{web, Error, HttpResponse};
+use log::info;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use crate::config::Config;
+
+/// Sent as `Retry-After` on a rejected request.
+const RETRY_AFTER_SECS: u64 = 1;
+
+/// Routes that are never shed, so the server stays observable while overloaded.
+const EXEMPT_PATHS: &[&str] = &["/metrics"];
+
+/// Caps the requests being handled at once across all workers (`MAX_IN_FLIGHT_REQUESTS`).
+/// Unlike `OLLAMA_MAX_CONCURRENT`, which queues model calls, this rejects whole requests
+/// up front, including the tool work they would do.
+pub struct InFlightLimit {
+    max: Option<usize>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+/// Frees its request's place when dropped, however the request ends.
+struct Slot(Arc<AtomicUsize>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A response body that holds its request's slot until the body is finished or dropped, so
+/// streamed responses such as SSE `/chat` count for as long as they run.
+struct SlotBody {
+    body: BoxBody,
+    _slot: Slot,
+}
+
+impl MessageBody for SlotBody {
+    type Error = <BoxBody as MessageBody>::Error;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        Pin::new(&mut self.get_mut().body).poll_next(cx)
+    }
+}
+
+impl InFlightLimit {
+    pub fn from_config(config: &Config) -> Self {
+        Self { max: config.max_in_flight_requests, in_flight: Arc::new(AtomicUsize::new(0)) }
+    }
+
+    fn try_acquire(&self) -> Option<Slot> {
+        let previous = self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let slot = Slot(self.in_flight.clone());
+        match self.max {
+            Some(max) if previous >= max => None,
+            _ => Some(slot),
+        }
+    }
+}
+
+/// Rejects a request with 503 and `Retry-After` when `MAX_IN_FLIGHT_REQUESTS` are already being
+/// handled. A request holds its place until its response body has been sent or dropped.
+pub async fn limit_in_flight(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let limit = req.app_data::<web::Data<InFlightLimit>>().cloned();
+    let slot = match limit {
+        Some(limit) if !EXEMPT_PATHS.contains(&req.path()) => match limit.try_acquire() {
+            Some(slot) => Some(slot),
+            None => {
+                info!("Rejecting {} {}: too many requests in flight", req.method(), req.path());
+                let response = HttpResponse::ServiceUnavailable()
+                    .insert_header((header::RETRY_AFTER, RETRY_AFTER_SECS.to_string()))
+                    .json(serde_json::json!({
+                        "error": "Server is at capacity, try again shortly"
+                    }));
+                return Ok(req.into_response(response));
+            }
+        },
+        _ => None,
+    };
+    let response = next.call(req).await?.map_into_boxed_body();
+    Ok(match slot {
+        Some(slot) => response.map_body(|_, body| BoxBody::new(SlotBody { body, _slot: slot })),
+        None => response,
+    })
+}
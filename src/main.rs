@@ -1,67 +1,498 @@
-use actix_web::{web, App, HttpServer, HttpResponse, error::ErrorInternalServerError};
+use actix_web::{body::MessageBody, web, App, HttpMessage, HttpRequest, HttpServer, HttpResponse, error::{ErrorBadRequest, ErrorGatewayTimeout, ErrorInternalServerError, ErrorServiceUnavailable}};
+use actix_web::http::{header::{self, ContentEncoding}, KeepAlive};
+use actix_web::middleware::{from_fn, Compress, Condition};
+use futures::StreamExt;
 use serde::{Deserialize, Serialize};
 use log::{info, error};
+use std::sync::Arc;
+use std::time::Duration;
 
+mod audit;
+mod auth;
+mod config;
+mod egress;
+mod http_client;
 mod llm;
+mod load_shed;
+mod rpc;
 mod tools;
 mod handler;
 
-use tools::WebSearchClient;
-use handler::{QueryHandler, query_handler::ChatRequest};
+use config::Config;
+use egress::EgressPolicy;
+use load_shed::{limit_in_flight, InFlightLimit};
+use tools::websearch::{format_markdown, format_urls, truncate_contents, SafeSearch, SearchFilters, SearchResult, WebSearchError};
+use handler::{QueryHandler, progress::Progress, tool_registry::ToolRegistry, query_handler::{CancelRequest, ChatRequest, TokenizeRequest}};
+
+const SEARCH_ID_HEADER: &str = "X-Search-Id";
 
 #[derive(Deserialize)]
 struct SearchRequest {
     query: String,
     count: Option<usize>,
+    /// Replace snippets with the full text of each result page.
+    full_content: Option<bool>,
+    /// With `full_content`, also return each page's truncated raw HTML. Requires `DEBUG_RAW_HTML`.
+    debug_html: Option<bool>,
+    /// Stream results as NDJSON as they are parsed instead of returning them in one response.
+    stream: Option<bool>,
+    /// Only return results published within the last month, newest first. Not applied when streaming.
+    recent_only: Option<bool>,
+    /// Only return results from this domain or its subdomains, e.g. `docs.python.org`.
+    site: Option<String>,
+    /// DuckDuckGo region code such as `us-en`; `SEARCH_REGION` when omitted.
+    region: Option<String>,
+    /// `off`, `moderate` or `strict`; `SEARCH_SAFE_SEARCH` when omitted.
+    safe_search: Option<SafeSearch>,
+    /// Response shape; JSON when omitted. Not supported when streaming.
+    #[serde(default)]
+    format: SearchFormat,
+}
+
+#[derive(Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum SearchFormat {
+    /// The results and their search id as a JSON object.
+    #[default]
+    Json,
+    /// A numbered list of linked titles with their snippets.
+    Markdown,
+    /// One URL per line.
+    Urls,
+}
+
+#[derive(Serialize)]
+struct SearchResponse {
+    /// Pass as `use_search_id` to `/chat` to answer from these results.
+    search_id: String,
+    results: Vec<SearchResult>,
+}
+
+/// Whether the client ranks `text/plain` above JSON in its `Accept` header.
+fn prefers_plain_text(http_req: &HttpRequest) -> bool {
+    let Some(accept) = http_req.get_header::<header::Accept>() else {
+        return false;
+    };
+    accept
+        .ranked()
+        .into_iter()
+        .find(|mime| {
+            (mime.type_() == "text" && mime.subtype() == "plain")
+                || (mime.type_() == "application" && mime.subtype() == "json")
+                || mime.type_() == "*"
+        })
+        .map(|mime| mime.type_() == "text")
+        .unwrap_or(false)
+}
+
+/// Replaces a successful chat response with just its answer as `text/plain`. Errors keep their
+/// JSON body so clients can tell them apart from answers.
+fn into_plain_text(response: HttpResponse) -> HttpResponse {
+    if !response.status().is_success() {
+        return response;
+    }
+    let status = response.status();
+    let Ok(body) = response.into_body().try_into_bytes() else {
+        return HttpResponse::InternalServerError().json(serde_json::json!({"response": "Error: Unexpected streaming response"}));
+    };
+    let answer = serde_json::from_slice::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|value| value.get("response").and_then(|r| r.as_str()).map(str::to_string))
+        .unwrap_or_default();
+    HttpResponse::build(status)
+        .content_type("text/plain; charset=utf-8")
+        .body(answer)
+}
+
+/// Whether the client asks for `text/event-stream` ahead of any other type.
+fn wants_event_stream(http_req: &HttpRequest) -> bool {
+    http_req
+        .get_header::<header::Accept>()
+        .and_then(|accept| accept.ranked().into_iter().next())
+        .map(|mime| mime.type_() == "text" && mime.subtype() == "event-stream")
+        .unwrap_or(false)
+}
+
+fn sse_event(event: &str, data: &impl Serialize) -> web::Bytes {
+    let data = serde_json::to_string(data).unwrap_or_default();
+    web::Bytes::from(format!("event: {}\ndata: {}\n\n", event, data))
+}
+
+/// Runs the chat as Server-Sent Events: a `status` event per model call and tool run while the
+/// loop works, then the usual JSON response body as an `answer` event, or `error` if it failed.
+fn stream_chat(req: web::Json<ChatRequest>, handler: web::Data<QueryHandler>) -> HttpResponse {
+    let (tx, rx) = futures::channel::mpsc::unbounded::<Result<web::Bytes, std::convert::Infallible>>();
+
+    actix_web::rt::spawn(async move {
+        let (progress, events) = Progress::channel();
+        let forward = events.for_each(|event| {
+            let _ = tx.unbounded_send(Ok(sse_event("status", &event)));
+            futures::future::ready(())
+        });
+        let run = async move {
+            let result = handler.handle_chat_with_progress(req, &progress).await;
+            // Ends the event stream so forwarding finishes
+            drop(progress);
+            result
+        };
+        let (result, ()) = futures::join!(run, forward);
+
+        let (event, body) = match result {
+            Ok(response) => {
+                let event = if response.status().is_success() { "answer" } else { "error" };
+                let body = response
+                    .into_body()
+                    .try_into_bytes()
+                    .ok()
+                    .and_then(|bytes| serde_json::from_slice::<serde_json::Value>(&bytes).ok())
+                    .unwrap_or_default();
+                (event, body)
+            }
+            Err(e) => ("error", serde_json::json!({ "response": format!("Error: {}", e) })),
+        };
+        let _ = tx.unbounded_send(Ok(sse_event(event, &body)));
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        // Compression would buffer events until the encoder flushes
+        .insert_header(ContentEncoding::Identity)
+        .streaming(rx)
 }
 
 async fn handle_chat(
+    http_req: HttpRequest,
     req: web::Json<ChatRequest>,
     handler: web::Data<QueryHandler>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    handler.handle_chat(req).await
+    if wants_event_stream(&http_req) {
+        return Ok(stream_chat(req, handler));
+    }
+    let response = handler.handle_chat(req).await?;
+    if prefers_plain_text(&http_req) {
+        return Ok(into_plain_text(response));
+    }
+    Ok(response)
 }
 
+async fn handle_chat_once(
+    req: web::Json<ChatRequest>,
+    handler: web::Data<QueryHandler>,
+) -> Result<HttpResponse, actix_web::Error> {
+    handler.handle_chat_once(req).await
+}
+
+async fn cancel_chat(
+    http_req: HttpRequest,
+    req: web::Json<CancelRequest>,
+    handler: web::Data<QueryHandler>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // Request ids are chosen by clients and may be guessed, so they alone don't grant cancellation
+    if let Err(response) = auth::require_admin(&http_req, &config) {
+        return Ok(response);
+    }
+    handler.handle_cancel(&req)
+}
+
+#[derive(Deserialize)]
+struct PollQuery {
+    /// Events already seen; only later ones are returned.
+    #[serde(default)]
+    after: usize,
+    /// Seconds to wait for new events, at most `POLL_TIMEOUT_SECS`.
+    timeout: Option<u64>,
+}
+
+/// Long-polls the events of a chat started with a `request_id`, for clients that can't use SSE.
+async fn poll_session(
+    http_req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<PollQuery>,
+    handler: web::Data<QueryHandler>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // Sessions carry the full answer and are found by a client-chosen id, so the id alone is not enough
+    if let Err(response) = auth::require_admin(&http_req, &config) {
+        return Ok(response);
+    }
+    handler.handle_poll(&path.into_inner(), query.after, query.timeout).await
+}
+
+async fn tokenize(
+    req: web::Json<TokenizeRequest>,
+    handler: web::Data<QueryHandler>,
+) -> Result<HttpResponse, actix_web::Error> {
+    handler.handle_tokenize(&req)
+}
+
+async fn metrics(
+    handler: web::Data<QueryHandler>,
+) -> Result<HttpResponse, actix_web::Error> {
+    handler.handle_metrics()
+}
+
+async fn list_models(
+    handler: web::Data<QueryHandler>,
+) -> Result<HttpResponse, actix_web::Error> {
+    handler.handle_models().await
+}
+
+async fn invoke_tool(
+    http_req: HttpRequest,
+    path: web::Path<String>,
+    args: web::Json<serde_json::Value>,
+    handler: web::Data<QueryHandler>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Err(response) = auth::require_admin(&http_req, &config) {
+        return Ok(response);
+    }
+    handler.invoke_tool(&path.into_inner(), args.into_inner()).await
+}
+
+/// Checks tool arguments against the tool's schema. Unlike invoking, this runs nothing, so it
+/// needs no admin key.
+async fn validate_tool(
+    path: web::Path<String>,
+    args: web::Json<serde_json::Value>,
+    handler: web::Data<QueryHandler>,
+) -> Result<HttpResponse, actix_web::Error> {
+    handler.validate_tool(&path.into_inner(), &args)
+}
+
+async fn reload_system_prompt(
+    http_req: HttpRequest,
+    handler: web::Data<QueryHandler>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Err(response) = auth::require_admin(&http_req, &config) {
+        return Ok(response);
+    }
+    handler.handle_reload_system_prompt()
+}
+
+async fn reload_tools(
+    http_req: HttpRequest,
+    handler: web::Data<QueryHandler>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Err(response) = auth::require_admin(&http_req, &config) {
+        return Ok(response);
+    }
+    handler.handle_reload_tools()
+}
+
+/// Returns the effective configuration with secrets redacted, plus the tools offered to the model.
+async fn get_config(
+    config: web::Data<Config>,
+    handler: web::Data<QueryHandler>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut effective = serde_json::to_value(config.get_ref()).map_err(ErrorInternalServerError)?;
+    if let Some(fields) = effective.as_object_mut() {
+        fields.insert("enabled_tools".to_string(), serde_json::json!(handler.tool_names()));
+    }
+    Ok(HttpResponse::Ok().json(effective))
+}
+
+/// Streams search results as NDJSON, one `SearchResult` per line as each is parsed.
+/// A failure after the response has started is reported as a final `{"error": ...}` line.
+fn stream_search(
+    query: String,
+    count: usize,
+    filters: SearchFilters,
+    max_content_chars: usize,
+    registry: Arc<ToolRegistry>,
+) -> HttpResponse {
+    let (tx, rx) = futures::channel::mpsc::unbounded::<Result<web::Bytes, std::convert::Infallible>>();
+
+    actix_web::rt::spawn(async move {
+        let result = registry
+            .search_client
+            .search_each(&query, count, &filters, |mut result| {
+                truncate_contents(std::slice::from_mut(&mut result), max_content_chars);
+                if let Ok(mut line) = serde_json::to_vec(&result) {
+                    line.push(b'\n');
+                    let _ = tx.unbounded_send(Ok(web::Bytes::from(line)));
+                }
+            })
+            .await;
+
+        match result {
+            Ok(emitted) => info!("Streamed {} search results", emitted),
+            Err(e) => {
+                error!("Web search error: {:?}", e);
+                let mut line = serde_json::to_vec(&serde_json::json!({ "error": e.to_string() })).unwrap_or_default();
+                line.push(b'\n');
+                let _ = tx.unbounded_send(Ok(web::Bytes::from(line)));
+            }
+        }
+    });
+
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        // Compression would buffer lines until the encoder flushes, defeating streaming
+        .insert_header(ContentEncoding::Identity)
+        .streaming(rx)
+}
+
+/// Returns results as `{"search_id", "results"}`, or as Markdown or a URL list. The search id,
+/// also sent as the `X-Search-Id` header, identifies them for a later `/chat` with `use_search_id`.
 async fn search(
     request: web::Json<SearchRequest>,
-    web_search_client: web::Data<WebSearchClient>,
+    handler: web::Data<QueryHandler>,
+    config: web::Data<Config>,
 ) -> Result<HttpResponse, actix_web::Error> {
+    // The tool's client, so `/tools/reload` applies to this endpoint too
+    let registry = handler.registry();
+    let web_search_client = &registry.search_client;
     info!("Received search request with query: {}", request.query);
     
-    let count = request.count.unwrap_or(5);
-    let results = web_search_client
-        .search(request.query.clone(), count)
+    let count = request.count.unwrap_or_else(|| web_search_client.default_count());
+    let filters = SearchFilters {
+        site: request.site.clone(),
+        region: request.region.clone(),
+        safe_search: request.safe_search,
+    };
+
+    if request.stream.unwrap_or(false) {
+        if request.format != SearchFormat::Json {
+            return Err(ErrorBadRequest("format is not supported with stream"));
+        }
+        return Ok(stream_search(
+            request.into_inner().query,
+            count,
+            filters,
+            config.search_api_max_content_chars,
+            registry.clone(),
+        ));
+    }
+
+    let mut results = web_search_client
+        .search(request.query.clone(), count, request.recent_only.unwrap_or(false), &filters)
         .await
         .map_err(|e| {
             error!("Web search error: {:?}", e);
-            ErrorInternalServerError(e.to_string())
+            match e {
+                WebSearchError::RateLimited(_) => ErrorServiceUnavailable(e.to_string()),
+                WebSearchError::Timeout(_) => ErrorGatewayTimeout(e.to_string()),
+                _ if e.is_invalid_input() => ErrorBadRequest(e.to_string()),
+                _ => ErrorInternalServerError(e.to_string()),
+            }
         })?;
+
+    if request.full_content.unwrap_or(false) {
+        web_search_client
+            .fetch_full_contents(&mut results, request.debug_html.unwrap_or(false))
+            .await;
+    }
+    truncate_contents(&mut results, config.search_api_max_content_chars);
     
     info!("Found {} search results", results.len());
-    Ok(HttpResponse::Ok().json(results))
+    let search_id = handler.store_search_results(results.clone());
+    let mut response = HttpResponse::Ok();
+    response.insert_header((SEARCH_ID_HEADER, search_id.as_str()));
+    Ok(match request.format {
+        SearchFormat::Json => response.json(SearchResponse { search_id, results }),
+        SearchFormat::Markdown => response
+            .content_type("text/markdown; charset=utf-8")
+            .body(format_markdown(&results)),
+        SearchFormat::Urls => response
+            .content_type("text/plain; charset=utf-8")
+            .body(format_urls(&results)),
+    })
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    // Load .env before anything reads the environment; variables already set take precedence
+    let dotenv_result = config::load_dotenv();
+
     // Initialize logger with default (info) level
     env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+
+    match dotenv_result {
+        Ok(path) => info!("Loaded environment from {}", path.display()),
+        Err(e) if e.not_found() => {}
+        Err(e) => error!("Failed to load .env file: {}", e),
+    }
     
     info!("Starting chat server...");
     
+    let config = Config::from_env();
+
+    let egress = EgressPolicy::from_config(&config);
+    for host in &config.ollama_hosts {
+        if let Err(e) = egress.check_url(host) {
+            error!("Ollama host {} is blocked by the egress policy: {}", host, e);
+        }
+    }
+
     // Create handlers
-    let query_handler = web::Data::new(QueryHandler::new());
-    let web_search_client = web::Data::new(WebSearchClient::new());
+    let query_handler = web::Data::new(QueryHandler::new(&config));
+    let in_flight_limit = web::Data::new(InFlightLimit::from_config(&config));
+    let warmup = config.warmup_model.clone().map(|model| (model, query_handler.clone()));
+    let compress_responses = config.compress_responses;
+    let workers = config.workers;
+    let keep_alive_secs = config.keep_alive_secs;
+    let client_request_timeout_ms = config.client_request_timeout_ms;
+    let max_connections = config.max_connections;
+    let config = web::Data::new(config);
     
     info!("Server will be available at http://127.0.0.1:8080");
     
-    HttpServer::new(move || {
+    let mut server = HttpServer::new(move || {
         App::new()
+            .wrap(Condition::new(compress_responses, Compress::default()))
+            .wrap(from_fn(limit_in_flight))
+            .app_data(in_flight_limit.clone())
             .app_data(query_handler.clone())
-            .app_data(web_search_client.clone())
+            .app_data(config.clone())
             .route("/chat", web::post().to(handle_chat))
+            .route("/chat/once", web::post().to(handle_chat_once))
+            .route("/chat/cancel", web::post().to(cancel_chat))
+            .route("/session/{id}/poll", web::get().to(poll_session))
             .route("/search", web::post().to(search))
-    })
-    .bind("127.0.0.1:8080")?
-    .run()
-    .await
+            .route("/models", web::get().to(list_models))
+            .route("/tokenize", web::post().to(tokenize))
+            .route("/config", web::get().to(get_config))
+            .route("/metrics", web::get().to(metrics))
+            .route("/rpc", web::post().to(rpc::handle_rpc))
+            .route("/tools/{name}/invoke", web::post().to(invoke_tool))
+            .route("/tools/{name}/validate", web::post().to(validate_tool))
+            .route("/system-prompt/reload", web::post().to(reload_system_prompt))
+            .route("/reload-tools", web::post().to(reload_tools))
+    });
+    if let Some(workers) = workers {
+        info!("Using {} worker threads", workers);
+        server = server.workers(workers);
+    }
+    if let Some(secs) = keep_alive_secs {
+        server = server.keep_alive(match secs {
+            0 => KeepAlive::Disabled,
+            secs => KeepAlive::Timeout(Duration::from_secs(secs)),
+        });
+    }
+    if let Some(ms) = client_request_timeout_ms {
+        server = server.client_request_timeout(Duration::from_millis(ms));
+    }
+    if let Some(max_connections) = max_connections {
+        server = server.max_connections(max_connections);
+    }
+
+    let server = server.bind("127.0.0.1:8080")?.run();
+
+    // Runs once the socket is bound; requests are served meanwhile and simply wait for the load
+    if let Some((model, handler)) = warmup {
+        actix_web::rt::spawn(async move {
+            info!("Warming up model {}", model);
+            match handler.warm_up(&model).await {
+                Ok(elapsed) => info!("Model {} warmed up in {:.1}s", model, elapsed.as_secs_f64()),
+                Err(e) => error!("Warm-up of model {} failed, continuing without it: {}", model, e),
+            }
+        });
+    }
+
+    server.await
 }
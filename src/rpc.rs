@@ -0,0 +1,157 @@
+use actix_web::{body::MessageBody, http::StatusCode, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use log::{info, error};
+
+use crate::config::Config;
+use crate::handler::QueryHandler;
+
+// Standard JSON-RPC 2.0 error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+/// Implementation-defined server error, used for upstream (Ollama, search) failures.
+const SERVER_ERROR: i64 = -32000;
+
+#[derive(Debug, Deserialize)]
+struct RpcRequest {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+    #[serde(default)]
+    id: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+}
+
+#[derive(Debug, Serialize)]
+struct RpcResponse {
+    jsonrpc: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+    id: Value,
+}
+
+impl RpcResponse {
+    fn result(id: Value, result: Value) -> Self {
+        Self { jsonrpc: "2.0", result: Some(result), error: None, id }
+    }
+
+    fn error(id: Value, code: i64, message: String, data: Option<Value>) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            result: None,
+            error: Some(RpcError { code, message, data }),
+            id,
+        }
+    }
+}
+
+fn error_code_for(status: StatusCode) -> i64 {
+    match status {
+        StatusCode::BAD_REQUEST => INVALID_PARAMS,
+        StatusCode::NOT_FOUND => METHOD_NOT_FOUND,
+        s if s.is_server_error() => SERVER_ERROR,
+        _ => INTERNAL_ERROR,
+    }
+}
+
+/// Converts a REST handler's response into a JSON-RPC result or error.
+fn into_rpc_response(id: Value, response: HttpResponse) -> RpcResponse {
+    let status = response.status();
+    let body = match response.into_body().try_into_bytes() {
+        Ok(bytes) => bytes,
+        Err(_) => {
+            return RpcResponse::error(id, INTERNAL_ERROR, "Unexpected streaming response".to_string(), None);
+        }
+    };
+    let value = serde_json::from_slice::<Value>(&body)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&body).into_owned()));
+
+    if status.is_success() {
+        return RpcResponse::result(id, value);
+    }
+
+    let message = value
+        .get("response")
+        .or_else(|| value.get("error"))
+        .and_then(|m| m.as_str())
+        .or_else(|| value.as_str())
+        .unwrap_or("Request failed")
+        .to_string();
+    RpcResponse::error(id, error_code_for(status), message, Some(Value::from(status.as_u16())))
+}
+
+fn parse_params<T: serde::de::DeserializeOwned>(params: Value) -> Result<T, String> {
+    serde_json::from_value(params).map_err(|e| format!("Invalid params: {}", e))
+}
+
+/// JSON-RPC 2.0 endpoint dispatching `chat`, `search` and `models` to the REST handlers.
+pub async fn handle_rpc(
+    body: web::Bytes,
+    handler: web::Data<QueryHandler>,
+    config: web::Data<Config>,
+) -> HttpResponse {
+    let request: RpcRequest = match serde_json::from_slice::<Value>(&body) {
+        Err(e) => {
+            return HttpResponse::Ok().json(RpcResponse::error(Value::Null, PARSE_ERROR, format!("Parse error: {}", e), None));
+        }
+        Ok(value) => {
+            let id = value.get("id").cloned().unwrap_or(Value::Null);
+            match serde_json::from_value(value) {
+                Ok(request) => request,
+                Err(e) => {
+                    return HttpResponse::Ok().json(RpcResponse::error(id, INVALID_REQUEST, format!("Invalid request: {}", e), None));
+                }
+            }
+        }
+    };
+
+    if request.jsonrpc != "2.0" {
+        return HttpResponse::Ok().json(RpcResponse::error(
+            request.id,
+            INVALID_REQUEST,
+            "Invalid request: \"jsonrpc\" must be \"2.0\"".to_string(),
+            None,
+        ));
+    }
+
+    info!("Received JSON-RPC request for method: {}", request.method);
+    let id = request.id;
+    let response = match request.method.as_str() {
+        "chat" => match parse_params(request.params) {
+            Ok(params) => handler.handle_chat(web::Json(params)).await,
+            Err(e) => return HttpResponse::Ok().json(RpcResponse::error(id, INVALID_PARAMS, e, None)),
+        },
+        "search" => match parse_params(request.params) {
+            Ok(params) => crate::search(web::Json(params), handler.clone(), config).await,
+            Err(e) => return HttpResponse::Ok().json(RpcResponse::error(id, INVALID_PARAMS, e, None)),
+        },
+        "models" => handler.handle_models().await,
+        other => {
+            return HttpResponse::Ok().json(RpcResponse::error(
+                id,
+                METHOD_NOT_FOUND,
+                format!("Method not found: {}", other),
+                None,
+            ));
+        }
+    };
+
+    let response = response.unwrap_or_else(|e| {
+        error!("JSON-RPC handler error: {}", e);
+        e.error_response()
+    });
+    HttpResponse::Ok().json(into_rpc_response(id, response))
+}
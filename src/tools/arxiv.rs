@@ -0,0 +1,128 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::config::Config;
+use crate::egress::{EgressError, EgressPolicy};
+use crate::http_client;
+
+const ARXIV_API_URL: &str = "http://export.arxiv.org/api/query";
+const ATOM_NS: &str = "http://www.w3.org/2005/Atom";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ArxivEntry {
+    pub id: String,
+    pub title: String,
+    pub authors: Vec<String>,
+    pub summary: String,
+    pub published: String,
+    pub pdf_url: String,
+}
+
+#[derive(Error, Debug)]
+pub enum ArxivError {
+    #[error("Network error: {0}")]
+    NetworkError(#[from] reqwest::Error),
+    #[error("Failed to parse arXiv response: {0}")]
+    ParseError(#[from] roxmltree::Error),
+    #[error("arXiv API error: {0}")]
+    ApiError(String),
+    #[error(transparent)]
+    EgressDenied(#[from] EgressError),
+}
+
+pub struct ArxivClient {
+    client: reqwest::Client,
+    egress: EgressPolicy,
+}
+
+/// Collapses the line breaks and indentation arXiv leaves inside titles and abstracts.
+fn normalize_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn child_text(node: roxmltree::Node, name: &str) -> String {
+    node.children()
+        .find(|n| n.has_tag_name((ATOM_NS, name)))
+        .and_then(|n| n.text())
+        .map(normalize_whitespace)
+        .unwrap_or_default()
+}
+
+/// Parses an arXiv Atom feed into entries. A feed without entries yields an empty list.
+pub fn parse_feed(xml: &str) -> Result<Vec<ArxivEntry>, ArxivError> {
+    let document = roxmltree::Document::parse(xml)?;
+
+    let mut entries = Vec::new();
+    for entry in document.descendants().filter(|n| n.has_tag_name((ATOM_NS, "entry"))) {
+        let id = child_text(entry, "id");
+        let summary = child_text(entry, "summary");
+
+        // arXiv reports malformed queries as a single entry pointing at its errors page
+        if id.contains("arxiv.org/api/errors") {
+            return Err(ArxivError::ApiError(summary));
+        }
+
+        let authors = entry
+            .children()
+            .filter(|n| n.has_tag_name((ATOM_NS, "author")))
+            .map(|author| child_text(author, "name"))
+            .filter(|name| !name.is_empty())
+            .collect();
+
+        let pdf_url = entry
+            .children()
+            .filter(|n| n.has_tag_name((ATOM_NS, "link")))
+            .find(|link| link.attribute("title") == Some("pdf"))
+            .and_then(|link| link.attribute("href"))
+            .map(|href| href.to_string())
+            .unwrap_or_else(|| id.replacen("/abs/", "/pdf/", 1));
+
+        entries.push(ArxivEntry {
+            title: child_text(entry, "title"),
+            authors,
+            summary,
+            published: child_text(entry, "published"),
+            pdf_url,
+            id,
+        });
+    }
+
+    Ok(entries)
+}
+
+impl ArxivClient {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            client: http_client::client(config),
+            egress: EgressPolicy::from_config(config),
+        }
+    }
+
+    pub async fn search(&self, query: &str, max_results: usize) -> Result<Vec<ArxivEntry>, ArxivError> {
+        info!("Performing arXiv search for query: {}", query);
+        self.egress.check_url(ARXIV_API_URL)?;
+
+        let search_query = format!("all:{}", query);
+        let max_results = max_results.to_string();
+        let response = self.client
+            .get(ARXIV_API_URL)
+            .query(&[
+                ("search_query", search_query.as_str()),
+                ("start", "0"),
+                ("max_results", max_results.as_str()),
+            ])
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(ArxivError::ApiError(format!("HTTP {}", response.status())));
+        }
+
+        let body = response.text().await?;
+        let entries = parse_feed(&body)?;
+
+        info!("Found {} arXiv entries", entries.len());
+        Ok(entries)
+    }
+}
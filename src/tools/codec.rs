@@ -0,0 +1,72 @@
+use base64::Engine;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum CodecError {
+    #[error("Unknown operation: {0}. Use \"encode\" or \"decode\".")]
+    UnknownOperation(String),
+    #[error("Unknown encoding: {0}. Use \"base64\", \"hex\" or \"url\".")]
+    UnknownEncoding(String),
+    #[error("Invalid base64 input: {0}")]
+    InvalidBase64(#[from] base64::DecodeError),
+    #[error("Invalid hex input: {0}")]
+    InvalidHex(String),
+    #[error("Invalid URL-encoded input: {0}")]
+    InvalidUrlEncoding(String),
+    #[error("Decoded data is not valid UTF-8 text; as hex it is {0}")]
+    NotUtf8(String),
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hex_decode(input: &str) -> Result<Vec<u8>, CodecError> {
+    let digits: String = input.chars().filter(|c| !c.is_whitespace()).collect();
+    let digits = digits.strip_prefix("0x").unwrap_or(&digits);
+    if !digits.len().is_multiple_of(2) {
+        return Err(CodecError::InvalidHex("odd number of digits".to_string()));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            digits
+                .get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| CodecError::InvalidHex(format!("not a hex digit pair at position {}", i)))
+        })
+        .collect()
+}
+
+fn bytes_to_text(bytes: Vec<u8>) -> Result<String, CodecError> {
+    String::from_utf8(bytes).map_err(|e| CodecError::NotUtf8(hex_encode(e.as_bytes())))
+}
+
+/// Encodes `input` as, or decodes it from, base64, hex or URL encoding. Pure and deterministic.
+pub fn encode_decode(operation: &str, encoding: &str, input: &str) -> Result<String, CodecError> {
+    let encoding = encoding.trim().to_lowercase();
+    match operation.trim().to_lowercase().as_str() {
+        "encode" => match encoding.as_str() {
+            "base64" => Ok(base64::engine::general_purpose::STANDARD.encode(input)),
+            "hex" => Ok(hex_encode(input.as_bytes())),
+            "url" => Ok(urlencoding::encode(input).into_owned()),
+            other => Err(CodecError::UnknownEncoding(other.to_string())),
+        },
+        "decode" => match encoding.as_str() {
+            "base64" => {
+                let compact: String = input.chars().filter(|c| !c.is_whitespace()).collect();
+                let bytes = base64::engine::general_purpose::STANDARD
+                    .decode(&compact)
+                    .or_else(|_| base64::engine::general_purpose::URL_SAFE.decode(&compact))
+                    .or_else(|_| base64::engine::general_purpose::STANDARD_NO_PAD.decode(&compact))?;
+                bytes_to_text(bytes)
+            }
+            "hex" => bytes_to_text(hex_decode(input)?),
+            "url" => urlencoding::decode(input)
+                .map(|decoded| decoded.into_owned())
+                .map_err(|e| CodecError::InvalidUrlEncoding(e.to_string())),
+            other => Err(CodecError::UnknownEncoding(other.to_string())),
+        },
+        other => Err(CodecError::UnknownOperation(other.to_string())),
+    }
+}
@@ -0,0 +1,167 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use thiserror::Error;
+use log::info;
+
+/// JSON has to be parsed in full, so refuse files beyond this size.
+const MAX_JSON_BYTES: u64 = 50 * 1024 * 1024;
+
+#[derive(Error, Debug)]
+pub enum DataLoaderError {
+    #[error("Failed to read file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to parse CSV: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("Failed to parse JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("File is too large to load as JSON ({0} bytes, limit {1})")]
+    TooLarge(u64, u64),
+}
+
+/// Compact description of a data file: its columns, size and a sample of rows.
+#[derive(Debug, Serialize)]
+pub struct DataSummary {
+    pub format: String,
+    pub columns: Vec<String>,
+    pub row_count: usize,
+    /// First rows of the data.
+    pub head: Vec<Value>,
+    /// Last rows of the data, only present when the data is larger than the sample.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tail: Vec<Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DataFormat {
+    Csv(u8),
+    Json,
+}
+
+fn detect_format(path: &Path) -> Result<DataFormat, DataLoaderError> {
+    let extension = path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+    match extension.as_deref() {
+        Some("csv") => return Ok(DataFormat::Csv(b',')),
+        Some("tsv") => return Ok(DataFormat::Csv(b'\t')),
+        Some("json") => return Ok(DataFormat::Json),
+        _ => {}
+    }
+
+    // Fall back to sniffing the first non-whitespace byte
+    let mut prefix = vec![0u8; 512];
+    let read = std::io::Read::read(&mut fs::File::open(path)?, &mut prefix)?;
+    let first = prefix[..read].iter().find(|b| !b.is_ascii_whitespace());
+    Ok(match first {
+        Some(b'[') | Some(b'{') => DataFormat::Json,
+        _ => DataFormat::Csv(b','),
+    })
+}
+
+/// Loads a CSV or JSON file and summarizes it. At most `sample_rows` rows are returned at
+/// each end of the data.
+pub fn load_data(path: &Path, sample_rows: usize) -> Result<DataSummary, DataLoaderError> {
+    info!("Loading data file {:?}", path);
+    match detect_format(path)? {
+        DataFormat::Csv(delimiter) => load_csv(path, delimiter, sample_rows),
+        DataFormat::Json => load_json(path, sample_rows),
+    }
+}
+
+fn load_csv(path: &Path, delimiter: u8, sample_rows: usize) -> Result<DataSummary, DataLoaderError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_path(path)?;
+    let columns: Vec<String> = reader.headers()?.iter().map(|h| h.to_string()).collect();
+
+    let to_row = |record: &csv::StringRecord| -> Value {
+        let mut row = Map::new();
+        for (i, field) in record.iter().enumerate() {
+            let key = columns.get(i).cloned().unwrap_or_else(|| format!("column_{}", i + 1));
+            row.insert(key, Value::String(field.to_string()));
+        }
+        Value::Object(row)
+    };
+
+    // Stream the records so large files never sit in memory all at once
+    let mut head = Vec::new();
+    let mut tail = VecDeque::new();
+    let mut row_count = 0;
+    for record in reader.records() {
+        let record = record?;
+        row_count += 1;
+        if head.len() < sample_rows {
+            head.push(to_row(&record));
+        } else {
+            tail.push_back(to_row(&record));
+            if tail.len() > sample_rows {
+                tail.pop_front();
+            }
+        }
+    }
+
+    Ok(DataSummary {
+        format: if delimiter == b'\t' { "tsv" } else { "csv" }.to_string(),
+        columns,
+        row_count,
+        head,
+        tail: tail.into_iter().collect(),
+    })
+}
+
+fn load_json(path: &Path, sample_rows: usize) -> Result<DataSummary, DataLoaderError> {
+    let size = fs::metadata(path)?.len();
+    if size > MAX_JSON_BYTES {
+        return Err(DataLoaderError::TooLarge(size, MAX_JSON_BYTES));
+    }
+
+    let value: Value = serde_json::from_slice(&fs::read(path)?)?;
+    let summary = match value {
+        Value::Array(rows) => {
+            // Union of object keys, in order of first appearance
+            let mut columns: Vec<String> = Vec::new();
+            for row in &rows {
+                if let Value::Object(obj) = row {
+                    for key in obj.keys() {
+                        if !columns.contains(key) {
+                            columns.push(key.clone());
+                        }
+                    }
+                }
+            }
+
+            let row_count = rows.len();
+            let head: Vec<Value> = rows.iter().take(sample_rows).cloned().collect();
+            let tail_start = row_count.saturating_sub(sample_rows).max(head.len());
+            let tail = rows[tail_start..].to_vec();
+
+            DataSummary {
+                format: "json".to_string(),
+                columns,
+                row_count,
+                head,
+                tail,
+            }
+        }
+        Value::Object(obj) => DataSummary {
+            format: "json".to_string(),
+            columns: obj.keys().cloned().collect(),
+            row_count: 1,
+            head: vec![Value::Object(obj)],
+            tail: Vec::new(),
+        },
+        other => DataSummary {
+            format: "json".to_string(),
+            columns: Vec::new(),
+            row_count: 1,
+            head: vec![other],
+            tail: Vec::new(),
+        },
+    };
+
+    Ok(summary)
+}
@@ -0,0 +1,211 @@
+use chrono::{DateTime, Datelike, Days, Months, NaiveDate, NaiveDateTime, TimeDelta, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::Serialize;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum DateTimeError {
+    #[error("Unknown operation: {0}. Use \"now\", \"convert\", \"add\" or \"diff\".")]
+    UnknownOperation(String),
+    #[error("Unknown timezone: {0}. Use an IANA name such as \"Europe/Berlin\" or \"America/New_York\".")]
+    UnknownTimezone(String),
+    #[error("Cannot parse date {0:?}. Use ISO 8601, e.g. \"2024-03-15\", \"2024-03-15 14:30\" or \"2024-03-15T14:30:00+01:00\".")]
+    InvalidDate(String),
+    #[error("{0} does not exist in {1}, it falls into a daylight saving gap")]
+    NonexistentLocalTime(String, String),
+    #[error("Unknown unit: {0}. Use seconds, minutes, hours, days, weeks, business_days, months or years.")]
+    UnknownUnit(String),
+    #[error("Missing argument: {0}")]
+    MissingArgument(&'static str),
+    #[error("Result is out of the supported date range")]
+    OutOfRange,
+}
+
+/// Common abbreviations mapped to a representative zone. The zone's own rules then decide
+/// whether standard or daylight time applies, so "PST" in July gives PDT.
+const ABBREVIATIONS: &[(&str, &str)] = &[
+    ("UTC", "UTC"),
+    ("GMT", "UTC"),
+    ("Z", "UTC"),
+    ("PST", "America/Los_Angeles"),
+    ("PDT", "America/Los_Angeles"),
+    ("MST", "America/Denver"),
+    ("MDT", "America/Denver"),
+    ("CST", "America/Chicago"),
+    ("CDT", "America/Chicago"),
+    ("EST", "America/New_York"),
+    ("EDT", "America/New_York"),
+    ("BST", "Europe/London"),
+    ("CET", "Europe/Paris"),
+    ("CEST", "Europe/Paris"),
+    ("EET", "Europe/Athens"),
+    ("IST", "Asia/Kolkata"),
+    ("JST", "Asia/Tokyo"),
+    ("KST", "Asia/Seoul"),
+    ("AEST", "Australia/Sydney"),
+    ("AEDT", "Australia/Sydney"),
+];
+
+/// Business days are counted one at a time; this keeps that loop short.
+const MAX_BUSINESS_DAYS: u64 = 100_000;
+
+const NAIVE_FORMATS: &[&str] = &["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Now,
+    Convert,
+    Add,
+    Diff,
+}
+
+impl Operation {
+    pub fn parse(operation: &str) -> Result<Self, DateTimeError> {
+        match operation.trim().to_lowercase().as_str() {
+            "now" => Ok(Operation::Now),
+            "convert" => Ok(Operation::Convert),
+            "add" => Ok(Operation::Add),
+            "diff" | "difference" => Ok(Operation::Diff),
+            other => Err(DateTimeError::UnknownOperation(other.to_string())),
+        }
+    }
+}
+
+pub fn parse_timezone(name: &str) -> Result<Tz, DateTimeError> {
+    let name = name.trim();
+    let canonical = ABBREVIATIONS
+        .iter()
+        .find(|(abbreviation, _)| abbreviation.eq_ignore_ascii_case(name))
+        .map(|(_, zone)| *zone)
+        .unwrap_or(name);
+    canonical
+        .parse::<Tz>()
+        .map_err(|_| DateTimeError::UnknownTimezone(name.to_string()))
+}
+
+/// Parses `text` in `tz`. Text with an explicit offset keeps its instant and is shown in `tz`;
+/// dates without a time mean midnight. "now" and "today" are relative to `now`.
+pub fn parse_datetime(text: &str, tz: Tz, now: DateTime<Utc>) -> Result<DateTime<Tz>, DateTimeError> {
+    let text = text.trim();
+    match text.to_lowercase().as_str() {
+        "now" => return Ok(now.with_timezone(&tz)),
+        "today" => return local(now.with_timezone(&tz).date_naive().and_hms_opt(0, 0, 0).unwrap(), tz),
+        _ => {}
+    }
+    if let Ok(dt) = DateTime::parse_from_rfc3339(text) {
+        return Ok(dt.with_timezone(&tz));
+    }
+    if let Some(naive) = NAIVE_FORMATS.iter().find_map(|f| NaiveDateTime::parse_from_str(text, f).ok()) {
+        return local(naive, tz);
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return local(date.and_hms_opt(0, 0, 0).unwrap(), tz);
+    }
+    Err(DateTimeError::InvalidDate(text.to_string()))
+}
+
+fn local(naive: NaiveDateTime, tz: Tz) -> Result<DateTime<Tz>, DateTimeError> {
+    tz.from_local_datetime(&naive)
+        .earliest()
+        .ok_or_else(|| DateTimeError::NonexistentLocalTime(naive.to_string(), tz.name().to_string()))
+}
+
+fn is_weekend(date: NaiveDate) -> bool {
+    matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+}
+
+/// Adds `amount` of `unit` to `start`; negative amounts subtract. Days, weeks, months and years
+/// are calendar units that keep the wall-clock time across daylight saving changes. Business
+/// days skip Saturdays and Sundays but not public holidays.
+pub fn add(start: DateTime<Tz>, amount: i64, unit: &str) -> Result<DateTime<Tz>, DateTimeError> {
+    let unit = unit.trim().to_lowercase();
+    let unit = unit.trim_end_matches('s');
+    let magnitude = amount.unsigned_abs();
+    let calendar_days = |days: u64| {
+        if amount >= 0 {
+            start.checked_add_days(Days::new(days))
+        } else {
+            start.checked_sub_days(Days::new(days))
+        }
+    };
+    let calendar_months = |months: u64| {
+        let months = Months::new(u32::try_from(months).ok()?);
+        if amount >= 0 {
+            start.checked_add_months(months)
+        } else {
+            start.checked_sub_months(months)
+        }
+    };
+    let result = match unit {
+        "second" => TimeDelta::try_seconds(amount).and_then(|d| start.checked_add_signed(d)),
+        "minute" => TimeDelta::try_minutes(amount).and_then(|d| start.checked_add_signed(d)),
+        "hour" => TimeDelta::try_hours(amount).and_then(|d| start.checked_add_signed(d)),
+        "day" => calendar_days(magnitude),
+        "week" => magnitude.checked_mul(7).and_then(calendar_days),
+        "month" => calendar_months(magnitude),
+        "year" => magnitude.checked_mul(12).and_then(calendar_months),
+        "business_day" | "business day" | "weekday" => {
+            if magnitude > MAX_BUSINESS_DAYS {
+                return Err(DateTimeError::OutOfRange);
+            }
+            let mut date = start;
+            let mut remaining = magnitude;
+            while remaining > 0 {
+                date = if amount >= 0 {
+                    date.checked_add_days(Days::new(1))
+                } else {
+                    date.checked_sub_days(Days::new(1))
+                }
+                .ok_or(DateTimeError::OutOfRange)?;
+                if !is_weekend(date.date_naive()) {
+                    remaining -= 1;
+                }
+            }
+            Some(date)
+        }
+        _ => return Err(DateTimeError::UnknownUnit(unit.to_string())),
+    };
+    result.ok_or(DateTimeError::OutOfRange)
+}
+
+/// The time from `start` to `end`; negative when `end` is earlier.
+#[derive(Debug, Serialize)]
+pub struct Difference {
+    pub days: i64,
+    pub hours: i64,
+    pub minutes: i64,
+    pub seconds: i64,
+    pub total_hours: f64,
+    /// Weekdays from `start`'s date up to but excluding `end`'s date.
+    pub business_days: i64,
+}
+
+pub fn difference(start: DateTime<Tz>, end: DateTime<Tz>) -> Difference {
+    let delta = end.signed_duration_since(start);
+    let total = delta.num_seconds();
+
+    let (from, to, sign) = if end >= start {
+        (start.date_naive(), end.date_naive(), 1)
+    } else {
+        (end.date_naive(), start.date_naive(), -1)
+    };
+    let business_days = from
+        .iter_days()
+        .take_while(|d| *d < to)
+        .filter(|d| !is_weekend(*d))
+        .count() as i64;
+
+    Difference {
+        days: total / 86_400,
+        hours: total % 86_400 / 3_600,
+        minutes: total % 3_600 / 60,
+        seconds: total % 60,
+        total_hours: total as f64 / 3_600.0,
+        business_days: business_days * sign,
+    }
+}
+
+/// RFC 3339 with the weekday and zone name, e.g. `2024-03-15T14:30:00+01:00 (Friday, Europe/Berlin)`.
+pub fn describe(dt: &DateTime<Tz>) -> String {
+    format!("{} ({}, {})", dt.to_rfc3339(), dt.format("%A"), dt.timezone().name())
+}
@@ -0,0 +1,43 @@
+use similar::TextDiff;
+use std::path::Path;
+use thiserror::Error;
+
+pub const DEFAULT_CONTEXT_LINES: usize = 3;
+const MAX_CONTEXT_LINES: usize = 100;
+/// Files larger than this are refused; diffing is quadratic in the worst case.
+const MAX_DIFF_FILE_BYTES: u64 = 1024 * 1024;
+
+#[derive(Error, Debug)]
+pub enum DiffError {
+    #[error("Failed to read {0}: {1}")]
+    Io(String, std::io::Error),
+    #[error("{0} is larger than the {1} byte limit for diffing")]
+    TooLarge(String, u64),
+    #[error("{0} is not a UTF-8 text file")]
+    NotText(String),
+}
+
+/// Reads a file to diff, refusing binary and oversized files.
+pub fn read_text(path: &Path, label: &str) -> Result<String, DiffError> {
+    let size = std::fs::metadata(path).map_err(|e| DiffError::Io(label.to_string(), e))?.len();
+    if size > MAX_DIFF_FILE_BYTES {
+        return Err(DiffError::TooLarge(label.to_string(), MAX_DIFF_FILE_BYTES));
+    }
+    let bytes = std::fs::read(path).map_err(|e| DiffError::Io(label.to_string(), e))?;
+    String::from_utf8(bytes).map_err(|_| DiffError::NotText(label.to_string()))
+}
+
+/// A line-based unified diff of `a` against `b` with `---`/`+++` headers named by the labels.
+/// `context_lines` is capped at `MAX_CONTEXT_LINES`.
+pub fn unified_diff(a: &str, b: &str, a_label: &str, b_label: &str, context_lines: usize) -> String {
+    let diff = TextDiff::from_lines(a, b)
+        .unified_diff()
+        .context_radius(context_lines.min(MAX_CONTEXT_LINES))
+        .header(a_label, b_label)
+        .to_string();
+    if diff.is_empty() {
+        "No differences.".to_string()
+    } else {
+        diff
+    }
+}
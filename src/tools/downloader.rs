@@ -0,0 +1,84 @@
+use log::info;
+use std::path::Path;
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+
+use crate::config::Config;
+use crate::egress::{EgressError, EgressPolicy};
+use crate::http_client;
+
+#[derive(Error, Debug)]
+pub enum DownloadError {
+    #[error("Network error: {0}")]
+    NetworkError(#[from] reqwest::Error),
+    #[error("Download failed: HTTP {0}")]
+    HttpStatus(reqwest::StatusCode),
+    #[error("File is larger than the {0} byte download limit")]
+    TooLarge(u64),
+    #[error("Failed to write {0}: {1}")]
+    Io(String, std::io::Error),
+    #[error(transparent)]
+    EgressDenied(#[from] EgressError),
+}
+
+pub struct Downloader {
+    client: reqwest::Client,
+    egress: EgressPolicy,
+    max_bytes: u64,
+}
+
+impl Downloader {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            client: http_client::client(config),
+            egress: EgressPolicy::from_config(config),
+            max_bytes: config.download_max_bytes,
+        }
+    }
+
+    /// Streams `url` to `dest`, returning the number of bytes written. The body is written to a
+    /// temporary file next to `dest` and only renamed into place once complete, so an aborted
+    /// download never leaves a truncated file behind.
+    pub async fn download(&self, url: &str, dest: &Path) -> Result<u64, DownloadError> {
+        // Redirect hops are checked by the client's redirect policy before they are requested
+        self.egress.check_url(url)?;
+        info!("Downloading {} to {}", url, dest.display());
+
+        let mut response = self.client.get(url).send().await?;
+        if !response.status().is_success() {
+            return Err(DownloadError::HttpStatus(response.status()));
+        }
+        if response.content_length().map(|len| len > self.max_bytes).unwrap_or(false) {
+            return Err(DownloadError::TooLarge(self.max_bytes));
+        }
+
+        let mut partial_name = dest.file_name().unwrap_or_default().to_os_string();
+        partial_name.push(".part");
+        let partial = dest.with_file_name(partial_name);
+        let io_error = |e| DownloadError::Io(dest.display().to_string(), e);
+        let mut file = tokio::fs::File::create(&partial).await.map_err(io_error)?;
+
+        let mut written: u64 = 0;
+        let result = async {
+            while let Some(chunk) = response.chunk().await? {
+                written += chunk.len() as u64;
+                if written > self.max_bytes {
+                    return Err(DownloadError::TooLarge(self.max_bytes));
+                }
+                file.write_all(&chunk).await.map_err(io_error)?;
+            }
+            file.flush().await.map_err(io_error)
+        }
+        .await;
+
+        if let Err(e) = result {
+            let _ = tokio::fs::remove_file(&partial).await;
+            return Err(e);
+        }
+        drop(file);
+        tokio::fs::rename(&partial, dest).await.map_err(io_error)?;
+
+        info!("Downloaded {} bytes to {}", written, dest.display());
+        Ok(written)
+    }
+}
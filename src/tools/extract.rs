@@ -0,0 +1,75 @@
+use regex::RegexBuilder;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+/// Compiled program size limit; rejects patterns like `(a{1000}){1000}` that explode on compile.
+/// Matching itself runs in linear time, so there is no backtracking to guard against.
+const PATTERN_SIZE_LIMIT: usize = 1024 * 1024;
+const MAX_PATTERN_CHARS: usize = 1_000;
+/// Matches returned at most; the total count is still reported.
+const MAX_MATCHES: usize = 500;
+
+#[derive(Error, Debug)]
+pub enum ExtractError {
+    #[error("Invalid pattern: {0}")]
+    InvalidPattern(#[from] regex::Error),
+    #[error("Pattern is longer than {0} characters")]
+    PatternTooLong(usize),
+}
+
+#[derive(Debug, Serialize)]
+pub struct RegexMatch {
+    #[serde(rename = "match")]
+    pub text: String,
+    /// Byte offset of the match in the input.
+    pub start: usize,
+    /// Capture groups by name, or by number for unnamed ones. Groups that didn't take part are null.
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub groups: Map<String, Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Extraction {
+    pub count: usize,
+    /// Set when more than `MAX_MATCHES` matched and only the first ones are listed.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    pub matches: Vec<RegexMatch>,
+}
+
+/// Finds every non-overlapping match of `pattern` in `text`, with its capture groups.
+pub fn extract(text: &str, pattern: &str, case_insensitive: bool) -> Result<Extraction, ExtractError> {
+    if pattern.chars().count() > MAX_PATTERN_CHARS {
+        return Err(ExtractError::PatternTooLong(MAX_PATTERN_CHARS));
+    }
+    let regex = RegexBuilder::new(pattern)
+        .case_insensitive(case_insensitive)
+        .size_limit(PATTERN_SIZE_LIMIT)
+        .dfa_size_limit(PATTERN_SIZE_LIMIT)
+        .build()?;
+    let names: Vec<Option<&str>> = regex.capture_names().collect();
+
+    let mut count = 0;
+    let mut matches = Vec::new();
+    for captures in regex.captures_iter(text) {
+        count += 1;
+        if matches.len() >= MAX_MATCHES {
+            continue;
+        }
+        let whole = captures.get(0).expect("group 0 always participates");
+        let groups = names
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(i, name)| {
+                let key = name.map(str::to_string).unwrap_or_else(|| i.to_string());
+                let value = captures.get(i).map(|m| Value::String(m.as_str().to_string())).unwrap_or(Value::Null);
+                (key, value)
+            })
+            .collect();
+        matches.push(RegexMatch { text: whole.as_str().to_string(), start: whole.start(), groups });
+    }
+
+    Ok(Extraction { count, truncated: count > matches.len(), matches })
+}
@@ -0,0 +1,125 @@
+use log::info;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::config::Config;
+use crate::egress::{EgressError, EgressPolicy};
+use crate::http_client;
+
+/// Feeds larger than this are rejected rather than parsed.
+const MAX_FEED_BYTES: usize = 5 * 1024 * 1024;
+/// Summaries are cut to this many characters.
+const MAX_SUMMARY_CHARS: usize = 500;
+pub const DEFAULT_FEED_ITEMS: usize = 10;
+pub const MAX_FEED_ITEMS: usize = 50;
+
+#[derive(Error, Debug)]
+pub enum FeedError {
+    #[error("Network error: {0}")]
+    NetworkError(#[from] reqwest::Error),
+    #[error("Fetching the feed failed: HTTP {0}")]
+    HttpStatus(reqwest::StatusCode),
+    #[error("Feed is larger than {0} bytes")]
+    TooLarge(usize),
+    #[error("Not a valid RSS or Atom feed: {0}")]
+    Malformed(#[from] feed_rs::parser::ParseFeedError),
+    #[error(transparent)]
+    EgressDenied(#[from] EgressError),
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeedItem {
+    pub title: String,
+    pub link: Option<String>,
+    /// RFC 3339; the updated date when the feed gives no published date.
+    pub published: Option<String>,
+    pub summary: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeedSummary {
+    pub title: String,
+    /// Items in the feed before limiting.
+    pub total_items: usize,
+    pub items: Vec<FeedItem>,
+}
+
+/// Collapses whitespace and strips tags, since summaries are often HTML fragments.
+fn plain_text(text: &str) -> String {
+    let fragment = scraper::Html::parse_fragment(text);
+    let text = fragment.root_element().text().collect::<Vec<_>>().join(" ");
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match text.char_indices().nth(MAX_SUMMARY_CHARS) {
+        Some((cut, _)) => format!("{}…", &text[..cut]),
+        None => text,
+    }
+}
+
+/// Parses an RSS or Atom document and keeps the `max_items` newest items, newest first.
+/// Undated items sort last, in feed order.
+pub fn parse_feed(body: &[u8], max_items: usize) -> Result<FeedSummary, FeedError> {
+    let feed = feed_rs::parser::parse(body)?;
+    let total_items = feed.entries.len();
+    let mut entries = feed.entries;
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.published.or(entry.updated)));
+
+    let items = entries
+        .into_iter()
+        .take(max_items)
+        .map(|entry| {
+            let summary = entry
+                .summary
+                .map(|summary| summary.content)
+                .or_else(|| entry.content.and_then(|content| content.body))
+                .map(|text| plain_text(&text))
+                .unwrap_or_default();
+            FeedItem {
+                title: entry.title.map(|title| plain_text(&title.content)).unwrap_or_default(),
+                link: entry.links.into_iter().next().map(|link| link.href),
+                published: entry.published.or(entry.updated).map(|date| date.to_rfc3339()),
+                summary,
+            }
+        })
+        .collect();
+
+    Ok(FeedSummary {
+        title: feed.title.map(|title| plain_text(&title.content)).unwrap_or_default(),
+        total_items,
+        items,
+    })
+}
+
+pub struct FeedClient {
+    client: reqwest::Client,
+    egress: EgressPolicy,
+}
+
+impl FeedClient {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            client: http_client::client(config),
+            egress: EgressPolicy::from_config(config),
+        }
+    }
+
+    /// Fetches the feed at `url` and returns its `max_items` newest items.
+    pub async fn read(&self, url: &str, max_items: usize) -> Result<FeedSummary, FeedError> {
+        // Redirect hops are checked by the client's redirect policy before they are requested
+        self.egress.check_url(url)?;
+        info!("Reading feed {}", url);
+
+        let mut response = self.client.get(url).send().await?;
+        if !response.status().is_success() {
+            return Err(FeedError::HttpStatus(response.status()));
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if body.len() > MAX_FEED_BYTES {
+                return Err(FeedError::TooLarge(MAX_FEED_BYTES));
+            }
+        }
+        parse_feed(&body, max_items.clamp(1, MAX_FEED_ITEMS))
+    }
+}
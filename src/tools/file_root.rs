@@ -0,0 +1,62 @@
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum FileRootError {
+    #[error("File access is disabled: FILE_ROOT is not configured")]
+    NotConfigured,
+    #[error("Path {0:?} is outside the allowed root")]
+    OutsideRoot(String),
+    #[error("Failed to access {0:?}: {1}")]
+    Io(String, io::Error),
+}
+
+/// Directory that file-based tools are confined to. Paths given by the model are resolved
+/// relative to it and rejected if they escape it (via `..` or symlinks).
+#[derive(Debug, Clone)]
+pub struct FileRoot {
+    root: PathBuf,
+}
+
+impl FileRoot {
+    pub fn new(root: PathBuf) -> Result<Self, FileRootError> {
+        let root = root
+            .canonicalize()
+            .map_err(|e| FileRootError::Io(root.display().to_string(), e))?;
+        Ok(Self { root })
+    }
+
+    /// Resolves an existing file or directory inside the root.
+    pub fn resolve(&self, relative: &str) -> Result<PathBuf, FileRootError> {
+        let joined = self.root.join(relative.trim_start_matches('/'));
+        let resolved = joined
+            .canonicalize()
+            .map_err(|e| FileRootError::Io(relative.to_string(), e))?;
+        if !resolved.starts_with(&self.root) {
+            return Err(FileRootError::OutsideRoot(relative.to_string()));
+        }
+        Ok(resolved)
+    }
+
+    /// Resolves a path that may not exist yet (e.g. a file about to be written). The parent
+    /// directory must exist inside the root and the path may not contain `..`.
+    pub fn resolve_new(&self, relative: &str) -> Result<PathBuf, FileRootError> {
+        let relative_path = Path::new(relative.trim_start_matches('/'));
+        if relative_path.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+            return Err(FileRootError::OutsideRoot(relative.to_string()));
+        }
+        let file_name = relative_path
+            .file_name()
+            .ok_or_else(|| FileRootError::OutsideRoot(relative.to_string()))?;
+
+        let parent = self.root.join(relative_path.parent().unwrap_or_else(|| Path::new("")));
+        let parent = parent
+            .canonicalize()
+            .map_err(|e| FileRootError::Io(relative.to_string(), e))?;
+        if !parent.starts_with(&self.root) {
+            return Err(FileRootError::OutsideRoot(relative.to_string()));
+        }
+        Ok(parent.join(file_name))
+    }
+}
@@ -0,0 +1,77 @@
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use thiserror::Error;
+
+/// Files are hashed in chunks of this size rather than read whole.
+const READ_CHUNK_BYTES: usize = 64 * 1024;
+
+#[derive(Error, Debug)]
+pub enum HashError {
+    #[error("Unknown algorithm: {0}. Use \"md5\", \"sha1\", \"sha256\" or \"sha512\".")]
+    UnknownAlgorithm(String),
+    #[error("Failed to read {0}: {1}")]
+    Io(String, std::io::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl HashAlgorithm {
+    pub fn parse(algorithm: &str) -> Result<Self, HashError> {
+        match algorithm.trim().to_lowercase().replace('-', "").as_str() {
+            "md5" => Ok(HashAlgorithm::Md5),
+            "sha1" => Ok(HashAlgorithm::Sha1),
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "sha512" => Ok(HashAlgorithm::Sha512),
+            other => Err(HashError::UnknownAlgorithm(other.to_string())),
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn digest_reader<D: Digest>(mut reader: impl Read, label: &str) -> Result<String, HashError> {
+    let mut hasher = D::new();
+    let mut buffer = vec![0u8; READ_CHUNK_BYTES];
+    loop {
+        let read = reader.read(&mut buffer).map_err(|e| HashError::Io(label.to_string(), e))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(to_hex(&hasher.finalize()))
+}
+
+fn digest(algorithm: HashAlgorithm, reader: impl Read, label: &str) -> Result<String, HashError> {
+    match algorithm {
+        HashAlgorithm::Md5 => digest_reader::<Md5>(reader, label),
+        HashAlgorithm::Sha1 => digest_reader::<Sha1>(reader, label),
+        HashAlgorithm::Sha256 => digest_reader::<Sha256>(reader, label),
+        HashAlgorithm::Sha512 => digest_reader::<Sha512>(reader, label),
+    }
+}
+
+/// Lowercase hex digest of the UTF-8 bytes of `text`.
+pub fn hash_text(text: &str, algorithm: HashAlgorithm) -> String {
+    // Reading from a slice cannot fail
+    digest(algorithm, text.as_bytes(), "input").unwrap_or_default()
+}
+
+/// Lowercase hex digest of the file at `path`, read in chunks so large files aren't held in memory.
+/// `label` names the file in errors.
+pub fn hash_file(path: &Path, label: &str, algorithm: HashAlgorithm) -> Result<String, HashError> {
+    let file = File::open(path).map_err(|e| HashError::Io(label.to_string(), e))?;
+    digest(algorithm, file, label)
+}
@@ -0,0 +1,51 @@
+use serde::Serialize;
+use serde_json::Value;
+use serde_json_path::JsonPath;
+use std::path::Path;
+use thiserror::Error;
+
+/// Largest document read from a file.
+const MAX_DOCUMENT_BYTES: u64 = 10 * 1024 * 1024;
+/// Values returned at most; the total count is still reported.
+const MAX_VALUES: usize = 200;
+
+#[derive(Error, Debug)]
+pub enum JsonQueryError {
+    #[error("Invalid JSON: {0}")]
+    InvalidJson(#[from] serde_json::Error),
+    #[error("Invalid JSONPath expression: {0}")]
+    InvalidExpression(#[from] serde_json_path::ParseError),
+    #[error("Failed to read {0}: {1}")]
+    Io(String, std::io::Error),
+    #[error("{0} is larger than {1} bytes")]
+    TooLarge(String, u64),
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueryResult {
+    pub count: usize,
+    /// Set when more than `MAX_VALUES` matched and only the first ones are listed.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    pub values: Vec<Value>,
+}
+
+/// Reads a JSON document from a file, refusing oversized ones.
+pub fn read_document(path: &Path, label: &str) -> Result<String, JsonQueryError> {
+    let size = std::fs::metadata(path).map_err(|e| JsonQueryError::Io(label.to_string(), e))?.len();
+    if size > MAX_DOCUMENT_BYTES {
+        return Err(JsonQueryError::TooLarge(label.to_string(), MAX_DOCUMENT_BYTES));
+    }
+    std::fs::read_to_string(path).map_err(|e| JsonQueryError::Io(label.to_string(), e))
+}
+
+/// Evaluates the JSONPath `expression` (RFC 9535, e.g. `$.items[?@.price < 10].name`) against
+/// `document` and returns the matched values in document order.
+pub fn query(document: &str, expression: &str) -> Result<QueryResult, JsonQueryError> {
+    let path = JsonPath::parse(expression)?;
+    let value: Value = serde_json::from_str(document)?;
+    let matched = path.query(&value).all();
+    let count = matched.len();
+    let values: Vec<Value> = matched.into_iter().take(MAX_VALUES).cloned().collect();
+    Ok(QueryResult { count, truncated: count > values.len(), values })
+}
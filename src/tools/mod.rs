@@ -1,5 +1,31 @@
 pub mod websearch;
 pub mod python_invoker;
+pub mod python_session;
+pub mod arxiv;
+pub mod file_root;
+pub mod data_loader;
+pub mod units;
+pub mod weather;
+pub mod codec;
+pub mod template;
+pub mod readability;
+pub mod precise_math;
+pub mod ranking;
+pub mod rate_limit;
+pub mod qrcode;
+pub mod downloader;
+pub mod diff;
+pub mod datetime;
+pub mod extract;
+pub mod json_query;
+pub mod feed;
+pub mod hashing;
 
 pub use websearch::WebSearchClient;
 pub use python_invoker::PythonInvoker;
+pub use arxiv::ArxivClient;
+pub use file_root::FileRoot;
+pub use units::UnitConverter;
+pub use weather::WeatherClient;
+pub use downloader::Downloader;
+pub use feed::FeedClient;
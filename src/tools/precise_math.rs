@@ -0,0 +1,228 @@
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Signed, Zero};
+use thiserror::Error;
+
+/// Digits after the decimal point when no precision is given.
+const DEFAULT_PRECISION: usize = 30;
+const MAX_PRECISION: usize = 1000;
+/// Exponents beyond this would produce numbers too large to be useful in a chat.
+const MAX_EXPONENT: u32 = 10_000;
+/// Largest numerator or denominator a power may produce, about 300,000 decimal digits. Checked
+/// before raising, since nested powers like `(10^10000)^10000` would otherwise exhaust memory.
+const MAX_POWER_BITS: u64 = 1_000_000;
+
+#[derive(Error, Debug)]
+pub enum MathError {
+    #[error("Invalid expression: {0}")]
+    Syntax(String),
+    #[error("Division by zero")]
+    DivisionByZero,
+    #[error("Exponent must be an integer between -{max} and {max}", max = MAX_EXPONENT)]
+    InvalidExponent,
+    #[error("Result is too large: a power would exceed {} bits", MAX_POWER_BITS)]
+    TooLarge,
+    #[error("Unknown output form: {0}. Use \"decimal\", \"fraction\" or \"integer\".")]
+    UnknownForm(String),
+    #[error("Result is not an integer: {0}")]
+    NotAnInteger(String),
+}
+
+/// How the exact result is written out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputForm {
+    /// Rounded to the given number of digits after the decimal point.
+    Decimal(usize),
+    Fraction,
+    Integer,
+}
+
+impl OutputForm {
+    pub fn parse(form: &str, precision: Option<usize>) -> Result<Self, MathError> {
+        match form.trim().to_lowercase().as_str() {
+            "decimal" => Ok(OutputForm::Decimal(precision.unwrap_or(DEFAULT_PRECISION).min(MAX_PRECISION))),
+            "fraction" => Ok(OutputForm::Fraction),
+            "integer" => Ok(OutputForm::Integer),
+            other => Err(MathError::UnknownForm(other.to_string())),
+        }
+    }
+}
+
+/// Recursive descent over `+ - * / ^` (or `**`) and parentheses, in exact rational arithmetic.
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        while self.input.get(self.pos).map(|b| b.is_ascii_whitespace()).unwrap_or(false) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.input.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.input[self.pos..].starts_with(token.as_bytes()) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expression(&mut self) -> Result<BigRational, MathError> {
+        let mut value = self.term()?;
+        loop {
+            if self.eat("+") {
+                value += self.term()?;
+            } else if self.eat("-") {
+                value -= self.term()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<BigRational, MathError> {
+        let mut value = self.unary()?;
+        loop {
+            // `**` is exponentiation, handled in `power`
+            self.skip_whitespace();
+            if self.input[self.pos..].starts_with(b"**") {
+                return Ok(value);
+            }
+            if self.eat("*") {
+                value *= self.unary()?;
+            } else if self.eat("/") {
+                let divisor = self.unary()?;
+                if divisor.is_zero() {
+                    return Err(MathError::DivisionByZero);
+                }
+                value /= divisor;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<BigRational, MathError> {
+        if self.eat("-") {
+            return Ok(-self.unary()?);
+        }
+        if self.eat("+") {
+            return self.unary();
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> Result<BigRational, MathError> {
+        let base = self.primary()?;
+        if !(self.eat("^") || self.eat("**")) {
+            return Ok(base);
+        }
+        // Right associative, and binds tighter than a unary minus on its left: -2^2 is -4
+        let exponent = self.unary()?;
+        if !exponent.is_integer() {
+            return Err(MathError::InvalidExponent);
+        }
+        let exponent = exponent.to_integer();
+        let magnitude = u32::try_from(exponent.abs()).ok().filter(|e| *e <= MAX_EXPONENT).ok_or(MathError::InvalidExponent)?;
+        let too_large = |part: &BigInt| part.bits().saturating_mul(u64::from(magnitude)) > MAX_POWER_BITS;
+        if too_large(base.numer()) || too_large(base.denom()) {
+            return Err(MathError::TooLarge);
+        }
+        let raised = BigRational::new(base.numer().pow(magnitude), base.denom().pow(magnitude));
+        if exponent.is_negative() {
+            if raised.is_zero() {
+                return Err(MathError::DivisionByZero);
+            }
+            return Ok(raised.recip());
+        }
+        Ok(raised)
+    }
+
+    fn primary(&mut self) -> Result<BigRational, MathError> {
+        match self.peek() {
+            Some(b'(') => {
+                self.pos += 1;
+                let value = self.expression()?;
+                if !self.eat(")") {
+                    return Err(MathError::Syntax(format!("expected ')' at position {}", self.pos)));
+                }
+                Ok(value)
+            }
+            Some(b) if b.is_ascii_digit() || b == b'.' => self.number(),
+            Some(b) => Err(MathError::Syntax(format!("unexpected '{}' at position {}", b as char, self.pos))),
+            None => Err(MathError::Syntax("unexpected end of expression".to_string())),
+        }
+    }
+
+    /// A decimal literal such as `42`, `0.1` or `.5`, read exactly rather than as a float.
+    fn number(&mut self) -> Result<BigRational, MathError> {
+        let start = self.pos;
+        while self.input.get(self.pos).map(|b| b.is_ascii_digit() || *b == b'_').unwrap_or(false) {
+            self.pos += 1;
+        }
+        let mut fraction_digits = 0;
+        if self.input.get(self.pos) == Some(&b'.') {
+            self.pos += 1;
+            while self.input.get(self.pos).map(|b| b.is_ascii_digit()).unwrap_or(false) {
+                self.pos += 1;
+                fraction_digits += 1;
+            }
+        }
+
+        let literal: String = std::str::from_utf8(&self.input[start..self.pos])
+            .unwrap_or_default()
+            .chars()
+            .filter(|c| c.is_ascii_digit())
+            .collect();
+        if literal.is_empty() {
+            return Err(MathError::Syntax(format!("invalid number at position {}", start)));
+        }
+        let numer: BigInt = literal
+            .parse()
+            .map_err(|_| MathError::Syntax(format!("invalid number at position {}", start)))?;
+        Ok(BigRational::new(numer, BigInt::from(10u32).pow(fraction_digits)))
+    }
+}
+
+fn format_decimal(value: &BigRational, precision: usize) -> String {
+    let scale = BigInt::from(10u32).pow(precision as u32);
+    // Rounds half away from zero
+    let scaled = (value.abs() * BigRational::from_integer(scale)).round().to_integer();
+    let digits = format!("{:0>width$}", scaled, width = precision + 1);
+    let (integer_part, fraction_part) = digits.split_at(digits.len() - precision);
+    let fraction_part = fraction_part.trim_end_matches('0');
+
+    let sign = if value.is_negative() && !scaled.is_zero() { "-" } else { "" };
+    if fraction_part.is_empty() {
+        format!("{}{}", sign, integer_part)
+    } else {
+        format!("{}{}.{}", sign, integer_part, fraction_part)
+    }
+}
+
+/// Evaluates `expression` exactly with arbitrary-precision rationals and writes the result in
+/// the requested `form`, so large integers and fractions come back without rounding errors.
+pub fn evaluate(expression: &str, form: OutputForm) -> Result<String, MathError> {
+    let mut parser = Parser { input: expression.as_bytes(), pos: 0 };
+    let value = parser.expression()?;
+    if parser.peek().is_some() {
+        return Err(MathError::Syntax(format!("unexpected '{}' at position {}", parser.input[parser.pos] as char, parser.pos)));
+    }
+
+    Ok(match form {
+        OutputForm::Decimal(precision) => format_decimal(&value, precision),
+        OutputForm::Fraction if value.denom().is_one() => value.numer().to_string(),
+        OutputForm::Fraction => format!("{}/{}", value.numer(), value.denom()),
+        OutputForm::Integer if value.is_integer() => value.to_integer().to_string(),
+        OutputForm::Integer => return Err(MathError::NotAnInteger(format!("{}/{}", value.numer(), value.denom()))),
+    })
+}
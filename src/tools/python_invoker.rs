@@ -1,50 +1,257 @@
+use base64::Engine;
 use serde::{Deserialize, Serialize};
-use std::process::Command;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 use thiserror::Error;
 use log::{info, error};
 
+use crate::config::Config;
+use crate::tools::python_session::PythonSessions;
+
+/// Environment variable through which scripts learn where to write output files.
+pub const OUTPUT_DIR_ENV: &str = "OUTPUT_DIR";
+
+static RUN_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// How often a running script checks whether it has been cancelled.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
 #[derive(Error, Debug)]
 pub enum PythonInvokerError {
     #[error("Failed to execute Python script: {0}")]
     CommandError(String),
     #[error("Script execution failed: {0}")]
     ScriptError(String),
+    #[error("Script rejected: it uses '{0}', which is not allowed. Rewrite the script without it.")]
+    Denied(String),
+    #[error("Script execution was cancelled")]
+    Cancelled,
 }
 
+/// A file written by a script to its output directory, base64 encoded.
 #[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PythonArtifact {
+    pub name: String,
+    pub size: usize,
+    pub data: String,
+}
+
+impl PythonArtifact {
+    pub fn is_image(&self) -> bool {
+        let name = self.name.to_lowercase();
+        [".png", ".jpg", ".jpeg", ".gif", ".webp", ".bmp"]
+            .iter()
+            .any(|ext| name.ends_with(ext))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct PythonScriptResult {
     pub stdout: String,
     pub stderr: String,
     pub exit_code: Option<i32>,
+    #[serde(default)]
+    pub artifacts: Vec<PythonArtifact>,
+    /// Set when stdout wasn't valid UTF-8. `stdout` then only describes it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stdout_is_binary: bool,
+    /// Length of the raw stdout in bytes.
+    #[serde(default)]
+    pub stdout_bytes: usize,
+    /// Raw stdout, base64 encoded, when it was binary and the caller asked for it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdout_base64: Option<String>,
+    /// Set when stderr wasn't valid UTF-8; invalid bytes in `stderr` are replaced.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stderr_is_binary: bool,
+}
+
+/// Decodes script output as UTF-8. Binary output is replaced by a short description rather
+/// than mojibake; the flag tells whether that happened.
+fn describe_output(bytes: &[u8]) -> (String, bool) {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => (text.to_string(), false),
+        Err(_) => (format!("<{} bytes of binary output, not valid UTF-8>", bytes.len()), true),
+    }
+}
+
+/// The directory a script runs in. A scratch directory is removed when this is dropped; a pinned
+/// `PYTHON_WORKING_DIR` is left alone.
+pub(crate) struct WorkingDir {
+    path: PathBuf,
+    scratch: bool,
+}
+
+impl WorkingDir {
+    /// `pinned` when given, otherwise a fresh empty directory under the system temp dir.
+    pub(crate) fn create(pinned: Option<&Path>) -> Result<Self, PythonInvokerError> {
+        match pinned {
+            Some(path) => {
+                fs::create_dir_all(path).map_err(|e| PythonInvokerError::CommandError(e.to_string()))?;
+                Ok(Self { path: path.to_path_buf(), scratch: false })
+            }
+            None => Ok(Self { path: PythonInvoker::create_temp_dir("python_scratch")?, scratch: true }),
+        }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for WorkingDir {
+    fn drop(&mut self) {
+        if self.scratch {
+            if let Err(e) = fs::remove_dir_all(&self.path) {
+                error!("Failed to remove working directory {:?}: {}", self.path, e);
+            }
+        }
+    }
 }
 
-pub struct PythonInvoker;
+#[derive(Clone)]
+pub struct PythonInvoker {
+    /// Substrings (e.g. `import os`, `subprocess`) that cause a script to be rejected before running.
+    denylist: Vec<String>,
+    /// Persistent interpreters for calls with a `session_id`, when `PYTHON_SESSIONS` is enabled.
+    sessions: Option<Arc<PythonSessions>>,
+    /// Shared working directory from `PYTHON_WORKING_DIR`; each run gets a scratch one when unset.
+    working_dir: Option<PathBuf>,
+}
 
 impl PythonInvoker {
+    /// No denylist, sessions or shared working directory.
+    #[cfg_attr(not(test), allow(dead_code))]
     pub fn new() -> Self {
-        Self
+        Self { denylist: Vec::new(), sessions: None, working_dir: None }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            denylist: config.python_denylist.clone(),
+            sessions: config.python_sessions.then(|| {
+                PythonSessions::start(
+                    Duration::from_secs(config.python_session_idle_secs),
+                    config.python_max_sessions,
+                    config.python_working_dir.clone(),
+                )
+            }),
+            working_dir: config.python_working_dir.clone(),
+        }
+    }
+
+    pub fn sessions_enabled(&self) -> bool {
+        self.sessions.is_some()
+    }
+
+    /// Live session count and evictions so far, when sessions are enabled.
+    pub fn session_stats(&self) -> Option<(usize, u64)> {
+        self.sessions.as_ref().map(|sessions| (sessions.count(), sessions.evictions()))
     }
 
-    pub fn run_script(&self, script: &str, args: &[&str]) -> Result<PythonScriptResult, PythonInvokerError> {
+    /// Runs `script` in the persistent interpreter named `session_id`, keeping variables from
+    /// earlier calls with the same id. Falls back to a fresh interpreter when sessions are disabled.
+    pub fn run_in_session(&self, session_id: &str, script: &str, cancelled: &AtomicBool) -> Result<PythonScriptResult, PythonInvokerError> {
+        let Some(sessions) = &self.sessions else {
+            return self.run_script(script, &[], false, cancelled);
+        };
+        info!("Executing Python script in session {}", session_id);
+        if let Err(e) = self.check_denylist(script) {
+            error!("{}", e);
+            return Err(e);
+        }
+        sessions.run(session_id, script, cancelled)
+    }
+
+    /// Rejects scripts containing a denylisted pattern. A cheap first line of defense, not a sandbox.
+    fn check_denylist(&self, script: &str) -> Result<(), PythonInvokerError> {
+        match self.denylist.iter().find(|pattern| script.contains(pattern.as_str())) {
+            Some(pattern) => Err(PythonInvokerError::Denied(pattern.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs `script` with `python3 -c`. The child is killed if `cancelled` is set while it runs.
+    /// With `include_binary`, stdout that isn't UTF-8 is also returned base64 encoded.
+    pub fn run_script(&self, script: &str, args: &[&str], include_binary: bool, cancelled: &AtomicBool) -> Result<PythonScriptResult, PythonInvokerError> {
         info!("Executing Python script with args: {:?}", args);
 
-        let output = Command::new("python3")
+        if let Err(e) = self.check_denylist(script) {
+            error!("{}", e);
+            return Err(e);
+        }
+
+        // Removed when this returns, whichever way the run ends
+        let working_dir = WorkingDir::create(self.working_dir.as_deref())?;
+        let output_dir = Self::create_output_dir()?;
+
+        let mut child = Command::new("python3")
             .arg("-c")
             .arg(script)
             .args(args)
-            .output()
-            .map_err(|e| PythonInvokerError::CommandError(e.to_string()))?;
+            .current_dir(working_dir.path())
+            .env(OUTPUT_DIR_ENV, &output_dir)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| {
+                let _ = fs::remove_dir_all(&output_dir);
+                PythonInvokerError::CommandError(e.to_string())
+            })?;
+
+        // Drain both pipes on their own threads so a chatty script can't block on a full pipe
+        let stdout_reader = Self::drain(child.stdout.take());
+        let stderr_reader = Self::drain(child.stderr.take());
+
+        let status = loop {
+            if cancelled.load(Ordering::SeqCst) {
+                info!("Cancelling running Python script");
+                let _ = child.kill();
+                let _ = child.wait();
+                let _ = fs::remove_dir_all(&output_dir);
+                return Err(PythonInvokerError::Cancelled);
+            }
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) => thread::sleep(CANCEL_POLL_INTERVAL),
+                Err(e) => {
+                    let _ = child.kill();
+                    let _ = fs::remove_dir_all(&output_dir);
+                    return Err(PythonInvokerError::CommandError(e.to_string()));
+                }
+            }
+        };
+        let stdout_bytes = stdout_reader.join().unwrap_or_default();
+        let stderr_bytes = stderr_reader.join().unwrap_or_default();
+
+        let artifacts = Self::collect_artifacts(&output_dir);
+        if let Err(e) = fs::remove_dir_all(&output_dir) {
+            error!("Failed to remove output directory {:?}: {}", output_dir, e);
+        }
 
-        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-        let exit_code = output.status.code();
+        let (stdout, stdout_is_binary) = describe_output(&stdout_bytes);
+        let stderr_is_binary = std::str::from_utf8(&stderr_bytes).is_err();
+        let stderr = String::from_utf8_lossy(&stderr_bytes).to_string();
+        let exit_code = status.code();
 
-        if output.status.success() {
-            info!("Python script executed successfully");
+        if status.success() {
+            info!("Python script executed successfully with {} artifact(s)", artifacts.len());
             Ok(PythonScriptResult {
                 stdout,
                 stderr,
                 exit_code,
+                artifacts,
+                stdout_is_binary,
+                stdout_bytes: stdout_bytes.len(),
+                stdout_base64: (stdout_is_binary && include_binary)
+                    .then(|| base64::engine::general_purpose::STANDARD.encode(&stdout_bytes)),
+                stderr_is_binary,
             })
         } else {
             error!("Python script execution failed with exit code: {:?}", exit_code);
@@ -54,4 +261,86 @@ impl PythonInvoker {
             )))
         }
     }
-} 
\ No newline at end of file
+
+    fn drain<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<Vec<u8>> {
+        thread::spawn(move || {
+            let mut buffer = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buffer);
+            }
+            buffer
+        })
+    }
+
+    pub(crate) fn create_output_dir() -> Result<PathBuf, PythonInvokerError> {
+        Self::create_temp_dir("python_invoker")
+    }
+
+    fn create_temp_dir(prefix: &str) -> Result<PathBuf, PythonInvokerError> {
+        let dir = std::env::temp_dir().join(format!(
+            "{}_{}_{}",
+            prefix,
+            std::process::id(),
+            RUN_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir).map_err(|e| PythonInvokerError::CommandError(e.to_string()))?;
+        Ok(dir)
+    }
+
+    /// Reads every regular file the script left in `dir` and base64 encodes it.
+    pub(crate) fn collect_artifacts(dir: &Path) -> Vec<PythonArtifact> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("Failed to read output directory {:?}: {}", dir, e);
+                return Vec::new();
+            }
+        };
+
+        let mut artifacts = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if !path.is_file() {
+                continue;
+            }
+            match fs::read(&path) {
+                Ok(bytes) => artifacts.push(PythonArtifact {
+                    name: entry.file_name().to_string_lossy().to_string(),
+                    size: bytes.len(),
+                    data: base64::engine::general_purpose::STANDARD.encode(&bytes),
+                }),
+                Err(e) => error!("Failed to read artifact {:?}: {}", path, e),
+            }
+        }
+        artifacts.sort_by(|a, b| a.name.cmp(&b.name));
+        artifacts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_a_script_and_collects_its_output_files() {
+        let script = "import os, sys\nprint('hello', sys.argv[1])\nopen(os.path.join(os.environ['OUTPUT_DIR'], 'out.txt'), 'w').write('done')";
+        let result = PythonInvoker::new().run_script(script, &["world"], false, &AtomicBool::new(false)).unwrap();
+        assert_eq!(result.stdout.trim(), "hello world");
+        assert_eq!(result.exit_code, Some(0));
+        assert_eq!(result.artifacts.len(), 1);
+        assert_eq!(result.artifacts[0].name, "out.txt");
+    }
+
+    #[test]
+    fn failing_script_is_an_error() {
+        let result = PythonInvoker::new().run_script("raise SystemExit(3)", &[], false, &AtomicBool::new(false));
+        assert!(matches!(result, Err(PythonInvokerError::ScriptError(message)) if message.contains("Some(3)")));
+    }
+
+    #[test]
+    fn denylisted_script_is_rejected_before_running() {
+        let invoker = PythonInvoker { denylist: vec!["import os".to_string()], ..PythonInvoker::new() };
+        let result = invoker.run_script("import os", &[], false, &AtomicBool::new(false));
+        assert!(matches!(result, Err(PythonInvokerError::Denied(pattern)) if pattern == "import os"));
+    }
+}
@@ -0,0 +1,257 @@
+use log::{error, info};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::tools::python_invoker::{PythonInvoker, PythonInvokerError, PythonScriptResult, WorkingDir, OUTPUT_DIR_ENV};
+
+/// How often a running snippet checks whether it has been cancelled.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How often idle sessions are looked for.
+const REAP_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Runs in the long-lived interpreter: reads one JSON request per line, executes its code in a
+/// namespace shared by all requests and answers with one JSON line of captured output.
+const DRIVER: &str = r#"
+import contextlib, io, json, sys, traceback
+_namespace = {"__name__": "__main__"}
+_reply = sys.stdout
+for _line in sys.stdin:
+    _code = json.loads(_line)["code"]
+    _stdout, _stderr, _ok = io.StringIO(), io.StringIO(), True
+    with contextlib.redirect_stdout(_stdout), contextlib.redirect_stderr(_stderr):
+        try:
+            exec(compile(_code, "<session>", "exec"), _namespace)
+        except SystemExit:
+            pass
+        except BaseException:
+            _ok = False
+            traceback.print_exc()
+    _reply.write(json.dumps({"stdout": _stdout.getvalue(), "stderr": _stderr.getvalue(), "ok": _ok}) + "\n")
+    _reply.flush()
+"#;
+
+#[derive(Deserialize)]
+struct Reply {
+    stdout: String,
+    stderr: String,
+    ok: bool,
+}
+
+/// One long-lived interpreter. The process is killed when the session is dropped.
+struct PythonSession {
+    child: Child,
+    stdin: ChildStdin,
+    replies: Receiver<String>,
+    output_dir: PathBuf,
+    /// Kept for the session's lifetime; a scratch directory is removed after the process is killed.
+    _working_dir: WorkingDir,
+    last_used: Instant,
+}
+
+impl PythonSession {
+    fn spawn(working_dir: Option<&Path>) -> Result<Self, PythonInvokerError> {
+        let working_dir = WorkingDir::create(working_dir)?;
+        let output_dir = PythonInvoker::create_output_dir()?;
+        let mut child = Command::new("python3")
+            .arg("-u")
+            .arg("-c")
+            .arg(DRIVER)
+            .current_dir(working_dir.path())
+            .env(OUTPUT_DIR_ENV, &output_dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| {
+                let _ = fs::remove_dir_all(&output_dir);
+                PythonInvokerError::CommandError(e.to_string())
+            })?;
+
+        let stdin = child.stdin.take().ok_or_else(|| PythonInvokerError::CommandError("no stdin".to_string()))?;
+        let stdout = child.stdout.take().ok_or_else(|| PythonInvokerError::CommandError("no stdout".to_string()))?;
+        // Replies are read on their own thread so a waiting call can still notice cancellation
+        let (sender, replies) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self { child, stdin, replies, output_dir, _working_dir: working_dir, last_used: Instant::now() })
+    }
+
+    fn execute(&mut self, code: &str, cancelled: &AtomicBool) -> Result<PythonScriptResult, PythonInvokerError> {
+        self.last_used = Instant::now();
+        let request = serde_json::json!({ "code": code }).to_string();
+        writeln!(self.stdin, "{}", request)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| PythonInvokerError::CommandError(format!("Python session is gone: {}", e)))?;
+
+        let line = loop {
+            if cancelled.load(Ordering::SeqCst) {
+                return Err(PythonInvokerError::Cancelled);
+            }
+            match self.replies.recv_timeout(CANCEL_POLL_INTERVAL) {
+                Ok(line) => break line,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(PythonInvokerError::CommandError("Python session exited".to_string()));
+                }
+            }
+        };
+        self.last_used = Instant::now();
+
+        let reply: Reply = serde_json::from_str(&line)
+            .map_err(|e| PythonInvokerError::CommandError(format!("Invalid reply from Python session: {}", e)))?;
+        let artifacts = PythonInvoker::collect_artifacts(&self.output_dir);
+        // Artifacts are returned once; later runs only report files they write themselves
+        for artifact in &artifacts {
+            let _ = fs::remove_file(self.output_dir.join(&artifact.name));
+        }
+
+        if reply.ok {
+            Ok(PythonScriptResult {
+                stdout_bytes: reply.stdout.len(),
+                stdout: reply.stdout,
+                stderr: reply.stderr,
+                artifacts,
+                ..Default::default()
+            })
+        } else {
+            Err(PythonInvokerError::ScriptError(format!("Stdout: {}\nStderr: {}", reply.stdout, reply.stderr)))
+        }
+    }
+}
+
+impl Drop for PythonSession {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        if let Err(e) = fs::remove_dir_all(&self.output_dir) {
+            error!("Failed to remove output directory {:?}: {}", self.output_dir, e);
+        }
+    }
+}
+
+/// Interpreters kept alive between `python_invoker` calls that pass the same `session_id`, so
+/// variables and imports persist. Sessions idle for longer than `idle_timeout` are torn down, and
+/// starting one beyond `max_sessions` evicts the least recently used idle session.
+pub struct PythonSessions {
+    sessions: Mutex<HashMap<String, Arc<Mutex<PythonSession>>>>,
+    idle_timeout: Duration,
+    max_sessions: usize,
+    evictions: AtomicU64,
+    working_dir: Option<PathBuf>,
+}
+
+impl PythonSessions {
+    /// Creates the session store and starts a background task that reaps idle sessions.
+    pub fn start(idle_timeout: Duration, max_sessions: usize, working_dir: Option<PathBuf>) -> Arc<Self> {
+        let sessions = Arc::new(Self {
+            sessions: Mutex::new(HashMap::new()),
+            idle_timeout,
+            max_sessions: max_sessions.max(1),
+            evictions: AtomicU64::new(0),
+            working_dir,
+        });
+        let weak: Weak<Self> = Arc::downgrade(&sessions);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(REAP_INTERVAL);
+            loop {
+                interval.tick().await;
+                match weak.upgrade() {
+                    Some(sessions) => sessions.reap_idle(),
+                    None => break,
+                }
+            }
+        });
+        sessions
+    }
+
+    fn reap_idle(&self) {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|id, session| {
+            // A session that is running code is busy, not idle
+            let idle = match session.try_lock() {
+                Ok(session) => session.last_used.elapsed() >= self.idle_timeout,
+                Err(_) => false,
+            };
+            if idle {
+                info!("Closing idle Python session {}", id);
+            }
+            !idle
+        });
+    }
+
+    /// Number of live sessions.
+    pub fn count(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    /// Sessions evicted to make room for new ones since startup.
+    pub fn evictions(&self) -> u64 {
+        self.evictions.load(Ordering::Relaxed)
+    }
+
+    /// Closes the least recently used session that isn't running code. Returns false when every
+    /// session is busy.
+    fn evict_lru(&self, sessions: &mut HashMap<String, Arc<Mutex<PythonSession>>>) -> bool {
+        let lru = sessions
+            .iter()
+            .filter_map(|(id, session)| session.try_lock().ok().map(|s| (id.clone(), s.last_used)))
+            .min_by_key(|(_, last_used)| *last_used)
+            .map(|(id, _)| id);
+        match lru {
+            Some(id) => {
+                info!("Evicting least recently used Python session {}", id);
+                sessions.remove(&id);
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Runs `code` in the session named `session_id`, starting it if needed. Blocks until the
+    /// code finishes. A cancelled or crashed session is discarded, losing its state.
+    pub fn run(&self, session_id: &str, code: &str, cancelled: &AtomicBool) -> Result<PythonScriptResult, PythonInvokerError> {
+        let session = {
+            let mut sessions = self.sessions.lock().unwrap();
+            match sessions.get(session_id) {
+                Some(session) => session.clone(),
+                None => {
+                    if sessions.len() >= self.max_sessions && !self.evict_lru(&mut sessions) {
+                        return Err(PythonInvokerError::CommandError(format!(
+                            "All {} Python sessions are busy, try again later",
+                            self.max_sessions
+                        )));
+                    }
+                    info!("Starting Python session {}", session_id);
+                    let session = Arc::new(Mutex::new(PythonSession::spawn(self.working_dir.as_deref())?));
+                    sessions.insert(session_id.to_string(), session.clone());
+                    session
+                }
+            }
+        };
+
+        let result = session.lock().unwrap().execute(code, cancelled);
+        if matches!(result, Err(PythonInvokerError::Cancelled) | Err(PythonInvokerError::CommandError(_))) {
+            let mut sessions = self.sessions.lock().unwrap();
+            if sessions.get(session_id).map(|s| Arc::ptr_eq(s, &session)).unwrap_or(false) {
+                sessions.remove(session_id);
+            }
+        }
+        result
+    }
+}
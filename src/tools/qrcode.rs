@@ -0,0 +1,64 @@
+use base64::Engine;
+use image::{ImageFormat, Luma};
+use ::qrcode::QrCode;
+use std::io::Cursor;
+use thiserror::Error;
+
+/// Pixels per QR module in PNG output, large enough to scan from a screen.
+const PNG_MODULE_PIXELS: u32 = 8;
+
+#[derive(Error, Debug)]
+pub enum QrCodeError {
+    #[error("Cannot encode data as a QR code: {0}")]
+    Encode(#[from] ::qrcode::types::QrError),
+    #[error("Failed to render PNG: {0}")]
+    Image(#[from] image::ImageError),
+    #[error("Unknown format: {0}. Use \"png\" or \"ascii\".")]
+    UnknownFormat(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QrFormat {
+    Png,
+    Ascii,
+}
+
+impl QrFormat {
+    pub fn parse(format: &str) -> Result<Self, QrCodeError> {
+        match format.trim().to_lowercase().as_str() {
+            "png" | "image" => Ok(QrFormat::Png),
+            "ascii" | "text" => Ok(QrFormat::Ascii),
+            other => Err(QrCodeError::UnknownFormat(other.to_string())),
+        }
+    }
+}
+
+/// A rendered QR code: base64 PNG data, or the code drawn with `#` characters.
+pub enum QrOutput {
+    Png(String),
+    Ascii(String),
+}
+
+/// Encodes `data` as a QR code in the requested `format`. Data longer than the largest QR
+/// version can hold is rejected.
+pub fn generate_qr(data: &str, format: QrFormat) -> Result<QrOutput, QrCodeError> {
+    let code = QrCode::new(data.as_bytes())?;
+    match format {
+        QrFormat::Ascii => Ok(QrOutput::Ascii(
+            code.render::<char>()
+                .dark_color('#')
+                .light_color(' ')
+                // Terminal cells are about twice as tall as wide
+                .module_dimensions(2, 1)
+                .build(),
+        )),
+        QrFormat::Png => {
+            let image = code.render::<Luma<u8>>()
+                .module_dimensions(PNG_MODULE_PIXELS, PNG_MODULE_PIXELS)
+                .build();
+            let mut png = Vec::new();
+            image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+            Ok(QrOutput::Png(base64::engine::general_purpose::STANDARD.encode(&png)))
+        }
+    }
+}
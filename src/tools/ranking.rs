@@ -0,0 +1,68 @@
+use serde::Serialize;
+
+use crate::tools::websearch::{relevance_score, SearchResult};
+
+/// Orders search results after they have been scraped. Implement this to rank by recency,
+/// domain authority, embeddings and so on without touching the scraping code.
+pub trait ResultRanker: Send + Sync {
+    fn rank(&self, query: &str, results: Vec<SearchResult>) -> Vec<SearchResult>;
+}
+
+/// Keeps the search engine's own order.
+pub struct EngineOrderRanker;
+
+impl ResultRanker for EngineOrderRanker {
+    fn rank(&self, _query: &str, results: Vec<SearchResult>) -> Vec<SearchResult> {
+        results
+    }
+}
+
+/// Scores each result by term overlap with the query (see `relevance_score`) and sorts by
+/// score, keeping the engine's order for ties.
+pub struct TermOverlapRanker;
+
+impl ResultRanker for TermOverlapRanker {
+    fn rank(&self, query: &str, mut results: Vec<SearchResult>) -> Vec<SearchResult> {
+        for result in results.iter_mut() {
+            result.score = Some(relevance_score(query, &result.title, &result.content));
+        }
+        results.sort_by(|a, b| {
+            b.score.unwrap_or(0.0)
+                .partial_cmp(&a.score.unwrap_or(0.0))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        results
+    }
+}
+
+/// The built-in rankers, selectable with `SEARCH_RANKER`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RankerKind {
+    /// Search engine order.
+    Engine,
+    /// Term overlap with the query.
+    #[default]
+    TermOverlap,
+}
+
+impl RankerKind {
+    pub fn ranker(self) -> Box<dyn ResultRanker> {
+        match self {
+            RankerKind::Engine => Box::new(EngineOrderRanker),
+            RankerKind::TermOverlap => Box::new(TermOverlapRanker),
+        }
+    }
+}
+
+impl std::str::FromStr for RankerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "engine" => Ok(RankerKind::Engine),
+            "term_overlap" => Ok(RankerKind::TermOverlap),
+            other => Err(format!("unknown search ranker {:?}", other)),
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Delay before the first retry of a rate-limited search; each further retry doubles it.
+const BACKOFF_BASE: Duration = Duration::from_secs(1);
+/// Cap on a single backoff delay.
+const BACKOFF_MAX: Duration = Duration::from_secs(8);
+
+/// The delay before retry number `attempt` (from 0): `BACKOFF_BASE` doubled per attempt, capped
+/// at `BACKOFF_MAX`, then scaled by `jitter` (clamped to 0..=1) into its upper half, so clients
+/// rate limited together don't retry in lockstep.
+pub fn backoff_delay(attempt: u32, jitter: f64) -> Duration {
+    let exponential = BACKOFF_BASE.saturating_mul(2u32.saturating_pow(attempt)).min(BACKOFF_MAX);
+    exponential.mul_f64(0.5 + jitter.clamp(0.0, 1.0) / 2.0)
+}
+
+/// A random fraction in 0..1 for `backoff_delay`.
+pub fn jitter() -> f64 {
+    let mut bytes = [0u8; 4];
+    // Without randomness the delay is just not spread out
+    if getrandom::getrandom(&mut bytes).is_err() {
+        return 1.0;
+    }
+    u32::from_le_bytes(bytes) as f64 / u32::MAX as f64
+}
+
+#[derive(Default)]
+struct BreakerState {
+    /// Rate-limited requests in a row.
+    failures: u32,
+    /// Searches fail fast until then.
+    open_until: Option<Instant>,
+}
+
+/// Stops searching for `cooldown` once the search engine has rate limited `threshold` requests in
+/// a row, since hammering it only prolongs the block. After the cooldown one search is let through:
+/// success closes the breaker, another rate limit opens it again. A zero threshold disables it.
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self { threshold, cooldown, state: Mutex::new(BreakerState::default()) }
+    }
+
+    /// Ok when a search may run, or the time left until it may.
+    pub fn check(&self) -> Result<(), Duration> {
+        let state = self.state.lock().unwrap();
+        let now = Instant::now();
+        match state.open_until {
+            Some(until) if now < until => Err(until - now),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn record_success(&self) {
+        *self.state.lock().unwrap() = BreakerState::default();
+    }
+
+    /// Counts a rate-limited request, opening the breaker at the threshold. Returns whether it is
+    /// now open.
+    pub fn record_rate_limited(&self) -> bool {
+        if self.threshold == 0 {
+            return false;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.failures = state.failures.saturating_add(1);
+        if state.failures >= self.threshold {
+            state.open_until = Some(Instant::now() + self.cooldown);
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        assert_eq!(backoff_delay(0, 1.0), Duration::from_secs(1));
+        assert_eq!(backoff_delay(1, 1.0), Duration::from_secs(2));
+        assert_eq!(backoff_delay(2, 1.0), Duration::from_secs(4));
+        assert_eq!(backoff_delay(10, 1.0), BACKOFF_MAX);
+        assert_eq!(backoff_delay(u32::MAX, 1.0), BACKOFF_MAX);
+    }
+
+    #[test]
+    fn jitter_keeps_the_upper_half_of_the_delay() {
+        assert_eq!(backoff_delay(1, 0.0), Duration::from_secs(1));
+        assert_eq!(backoff_delay(1, 0.5), Duration::from_millis(1500));
+        for _ in 0..100 {
+            let delay = backoff_delay(1, jitter());
+            assert!(delay >= Duration::from_secs(1) && delay <= Duration::from_secs(2));
+        }
+    }
+
+    #[test]
+    fn opens_after_threshold_failures_in_a_row() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(60));
+        assert!(!breaker.record_rate_limited());
+        assert!(!breaker.record_rate_limited());
+        assert!(breaker.check().is_ok());
+        assert!(breaker.record_rate_limited());
+        let remaining = breaker.check().unwrap_err();
+        assert!(remaining > Duration::from_secs(59) && remaining <= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn success_resets_the_count() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+        breaker.record_rate_limited();
+        breaker.record_success();
+        assert!(!breaker.record_rate_limited());
+        assert!(breaker.check().is_ok());
+    }
+
+    #[test]
+    fn lets_a_trial_through_after_the_cooldown_and_reopens_on_failure() {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO);
+        assert!(breaker.record_rate_limited());
+        assert!(breaker.check().is_ok());
+        assert!(breaker.record_rate_limited());
+    }
+
+    #[test]
+    fn zero_threshold_never_opens() {
+        let breaker = CircuitBreaker::new(0, Duration::from_secs(60));
+        for _ in 0..10 {
+            assert!(!breaker.record_rate_limited());
+        }
+        assert!(breaker.check().is_ok());
+    }
+}
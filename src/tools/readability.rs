@@ -0,0 +1,108 @@
+use once_cell::sync::Lazy;
+use scraper::{ElementRef, Html, Selector};
+use std::collections::HashMap;
+
+static MAIN_SELECTOR: Lazy<Selector> = Lazy::new(|| parse_selector("article, main, [role=main]"));
+static PARAGRAPH_SELECTOR: Lazy<Selector> = Lazy::new(|| parse_selector("p"));
+static BLOCK_SELECTOR: Lazy<Selector> =
+    Lazy::new(|| parse_selector("p, h1, h2, h3, h4, h5, h6, li, pre, blockquote"));
+
+/// Elements whose text is never part of the main content.
+const BOILERPLATE_TAGS: &[&str] = &["nav", "footer", "header", "aside", "form", "script", "style", "noscript"];
+/// Class or id fragments that mark cookie banners, ads, share bars and related-article lists.
+const BOILERPLATE_HINTS: &[&str] = &["cookie", "consent", "banner", "advert", "promo", "related", "share", "newsletter", "sidebar", "comment"];
+const BLOCK_TAGS: &[&str] = &["p", "h1", "h2", "h3", "h4", "h5", "h6", "li", "pre", "blockquote"];
+
+/// Paragraphs shorter than this are ignored when scoring, they are usually captions or bylines.
+const MIN_PARAGRAPH_CHARS: usize = 25;
+/// An `<article>` or `<main>` with at least this much text is taken as the content outright.
+const MIN_MAIN_CHARS: usize = 500;
+/// Below this, extraction is considered failed and the caller falls back to the plain selector.
+const MIN_CONTENT_CHARS: usize = 200;
+
+fn parse_selector(selector: &str) -> Selector {
+    Selector::parse(selector).unwrap_or_else(|e| panic!("Invalid CSS selector {:?}: {:?}", selector, e))
+}
+
+fn is_boilerplate(element: ElementRef) -> bool {
+    let value = element.value();
+    if BOILERPLATE_TAGS.contains(&value.name()) {
+        return true;
+    }
+    let marker = format!("{} {}", value.attr("class").unwrap_or_default(), value.attr("id").unwrap_or_default()).to_lowercase();
+    BOILERPLATE_HINTS.iter().any(|hint| marker.contains(hint))
+}
+
+fn inside_boilerplate(element: ElementRef) -> bool {
+    is_boilerplate(element) || element.ancestors().filter_map(ElementRef::wrap).any(is_boilerplate)
+}
+
+fn text_of(element: ElementRef) -> String {
+    element.text().collect::<Vec<_>>().join(" ").split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Text of the block elements in `container`, skipping boilerplate and blocks nested in other blocks.
+fn block_text(container: ElementRef) -> String {
+    container
+        .select(&BLOCK_SELECTOR)
+        .filter(|block| !inside_boilerplate(*block))
+        .filter(|block| {
+            !block
+                .ancestors()
+                .take_while(|ancestor| ancestor.id() != container.id())
+                .filter_map(ElementRef::wrap)
+                .any(|ancestor| BLOCK_TAGS.contains(&ancestor.value().name()))
+        })
+        .map(text_of)
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Picks the element holding the page's main text, readability style: an `<article>`/`<main>`
+/// with enough text if there is one, otherwise the element whose paragraphs score highest.
+/// Each paragraph scores its parent by length and half that for its grandparent.
+fn main_container(document: &Html) -> Option<ElementRef<'_>> {
+    let main = document
+        .select(&MAIN_SELECTOR)
+        .filter(|element| !inside_boilerplate(*element))
+        .map(|element| (element, block_text(element).len()))
+        .max_by_key(|(_, len)| *len);
+    if let Some((element, len)) = main {
+        if len >= MIN_MAIN_CHARS {
+            return Some(element);
+        }
+    }
+
+    let mut scores = HashMap::new();
+    for paragraph in document.select(&PARAGRAPH_SELECTOR) {
+        let len = text_of(paragraph).len();
+        if len < MIN_PARAGRAPH_CHARS || inside_boilerplate(paragraph) {
+            continue;
+        }
+        let score = 1.0 + (len as f64 / 100.0).min(3.0);
+        let mut ancestors = paragraph.ancestors().filter_map(ElementRef::wrap);
+        if let Some(parent) = ancestors.next() {
+            *scores.entry(parent.id()).or_insert(0.0) += score;
+        }
+        if let Some(grandparent) = ancestors.next() {
+            *scores.entry(grandparent.id()).or_insert(0.0) += score / 2.0;
+        }
+    }
+
+    scores
+        .into_iter()
+        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+        .and_then(|(id, _)| document.tree.get(id))
+        .and_then(ElementRef::wrap)
+}
+
+/// Extracts the main article text of a page, without navigation, footers, banners and other
+/// boilerplate. Returns `None` when no convincing main content is found.
+pub fn extract_main_text(document: &Html) -> Option<String> {
+    let text = block_text(main_container(document)?);
+    if text.len() < MIN_CONTENT_CHARS {
+        return None;
+    }
+    Some(text)
+}
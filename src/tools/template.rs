@@ -0,0 +1,29 @@
+use minijinja::Environment;
+use serde_json::Value;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum TemplateError {
+    #[error("Template error: {0}")]
+    Render(String),
+    #[error("Context must be a JSON object, got: {0}")]
+    InvalidContext(String),
+}
+
+/// Renders a Jinja-style `template` with the variables in `context`, without running any code
+/// outside the template engine.
+pub fn render_template(template: &str, context: &Value) -> Result<String, TemplateError> {
+    if !context.is_object() && !context.is_null() {
+        return Err(TemplateError::InvalidContext(context.to_string()));
+    }
+
+    let env = Environment::new();
+    env.render_str(template, context).map_err(|e| {
+        // The detail usually names the offending expression or filter
+        let message = match e.detail() {
+            Some(detail) => format!("{} ({})", e, detail),
+            None => e.to_string(),
+        };
+        TemplateError::Render(message)
+    })
+}
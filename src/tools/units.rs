@@ -0,0 +1,229 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+use crate::config::Config;
+use crate::egress::{EgressError, EgressPolicy};
+use crate::http_client;
+
+/// How long fetched currency rates are reused before asking the API again.
+const CURRENCY_CACHE_TTL: Duration = Duration::from_secs(600);
+
+#[derive(Error, Debug)]
+pub enum ConversionError {
+    #[error("Unknown unit: {0}")]
+    UnknownUnit(String),
+    #[error("Cannot convert {0} to {1}: incompatible units")]
+    Incompatible(String, String),
+    #[error("Currency conversion is disabled: CURRENCY_API_URL is not configured")]
+    CurrencyDisabled,
+    #[error("No exchange rate available for {0}")]
+    UnknownCurrency(String),
+    #[error("Network error: {0}")]
+    NetworkError(#[from] reqwest::Error),
+    #[error("Currency API error: {0}")]
+    ApiError(String),
+    #[error(transparent)]
+    EgressDenied(#[from] EgressError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dimension {
+    Length,
+    Mass,
+    Time,
+    Temperature,
+}
+
+/// Unit aliases, their dimension and the factor to the dimension's base unit
+/// (metre, kilogram, second). Temperature is converted separately.
+const UNITS: &[(&[&str], Dimension, f64)] = &[
+    (&["m", "meter", "meters", "metre", "metres"], Dimension::Length, 1.0),
+    (&["km", "kilometer", "kilometers", "kilometre", "kilometres"], Dimension::Length, 1000.0),
+    (&["cm", "centimeter", "centimeters", "centimetre", "centimetres"], Dimension::Length, 0.01),
+    (&["mm", "millimeter", "millimeters", "millimetre", "millimetres"], Dimension::Length, 0.001),
+    (&["um", "micrometer", "micrometers", "micron", "microns"], Dimension::Length, 1e-6),
+    (&["nm", "nanometer", "nanometers"], Dimension::Length, 1e-9),
+    (&["mi", "mile", "miles"], Dimension::Length, 1609.344),
+    (&["yd", "yard", "yards"], Dimension::Length, 0.9144),
+    (&["ft", "foot", "feet"], Dimension::Length, 0.3048),
+    (&["in", "inch", "inches"], Dimension::Length, 0.0254),
+    (&["nmi", "nautical mile", "nautical miles"], Dimension::Length, 1852.0),
+    (&["kg", "kilogram", "kilograms"], Dimension::Mass, 1.0),
+    (&["g", "gram", "grams"], Dimension::Mass, 0.001),
+    (&["mg", "milligram", "milligrams"], Dimension::Mass, 1e-6),
+    (&["ug", "microgram", "micrograms"], Dimension::Mass, 1e-9),
+    (&["t", "tonne", "tonnes", "metric ton", "metric tons"], Dimension::Mass, 1000.0),
+    (&["lb", "lbs", "pound", "pounds"], Dimension::Mass, 0.45359237),
+    (&["oz", "ounce", "ounces"], Dimension::Mass, 0.028349523125),
+    (&["st", "stone", "stones"], Dimension::Mass, 6.35029318),
+    (&["s", "sec", "second", "seconds"], Dimension::Time, 1.0),
+    (&["ms", "millisecond", "milliseconds"], Dimension::Time, 0.001),
+    (&["us", "microsecond", "microseconds"], Dimension::Time, 1e-6),
+    (&["ns", "nanosecond", "nanoseconds"], Dimension::Time, 1e-9),
+    (&["min", "minute", "minutes"], Dimension::Time, 60.0),
+    (&["h", "hr", "hour", "hours"], Dimension::Time, 3600.0),
+    (&["d", "day", "days"], Dimension::Time, 86_400.0),
+    (&["wk", "week", "weeks"], Dimension::Time, 604_800.0),
+    // Julian year of 365.25 days
+    (&["yr", "year", "years"], Dimension::Time, 31_557_600.0),
+    (&["c", "celsius", "degc", "°c"], Dimension::Temperature, 0.0),
+    (&["f", "fahrenheit", "degf", "°f"], Dimension::Temperature, 0.0),
+    (&["k", "kelvin"], Dimension::Temperature, 0.0),
+];
+
+fn lookup_unit(unit: &str) -> Option<(&'static str, Dimension, f64)> {
+    let unit = unit.trim().to_lowercase();
+    UNITS
+        .iter()
+        .find(|(aliases, _, _)| aliases.contains(&unit.as_str()))
+        .map(|(aliases, dimension, factor)| (aliases[0], *dimension, *factor))
+}
+
+fn to_kelvin(value: f64, unit: &str) -> f64 {
+    match unit {
+        "c" => value + 273.15,
+        "f" => (value - 32.0) * 5.0 / 9.0 + 273.15,
+        _ => value,
+    }
+}
+
+fn from_kelvin(value: f64, unit: &str) -> f64 {
+    match unit {
+        "c" => value - 273.15,
+        "f" => (value - 273.15) * 9.0 / 5.0 + 32.0,
+        _ => value,
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ConversionResult {
+    pub value: f64,
+    pub from: String,
+    pub to: String,
+    pub result: f64,
+    /// Exchange rate used, for currency conversions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate: Option<f64>,
+    /// When the exchange rate was last updated by the provider.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_timestamp: Option<String>,
+}
+
+/// Converts between units of length, mass, time or temperature. Pure and offline.
+pub fn convert_units(value: f64, from: &str, to: &str) -> Result<ConversionResult, ConversionError> {
+    let (from_unit, from_dim, from_factor) =
+        lookup_unit(from).ok_or_else(|| ConversionError::UnknownUnit(from.to_string()))?;
+    let (to_unit, to_dim, to_factor) =
+        lookup_unit(to).ok_or_else(|| ConversionError::UnknownUnit(to.to_string()))?;
+    if from_dim != to_dim {
+        return Err(ConversionError::Incompatible(from.to_string(), to.to_string()));
+    }
+
+    let result = if from_dim == Dimension::Temperature {
+        from_kelvin(to_kelvin(value, from_unit), to_unit)
+    } else {
+        value * from_factor / to_factor
+    };
+
+    Ok(ConversionResult {
+        value,
+        from: from.to_string(),
+        to: to.to_string(),
+        result,
+        rate: None,
+        rate_timestamp: None,
+    })
+}
+
+fn is_currency_code(code: &str) -> bool {
+    let code = code.trim();
+    code.len() == 3 && code.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+/// Rates response in the shape used by open.er-api.com and compatible APIs.
+#[derive(Debug, Deserialize, Clone)]
+struct RatesResponse {
+    rates: HashMap<String, f64>,
+    #[serde(default)]
+    time_last_update_utc: Option<String>,
+}
+
+/// Unit converter with optional live currency rates.
+pub struct UnitConverter {
+    client: reqwest::Client,
+    /// Rates endpoint, with `{base}` replaced by the source currency code.
+    currency_api_url: Option<String>,
+    egress: EgressPolicy,
+    cache: Mutex<HashMap<String, (Instant, RatesResponse)>>,
+}
+
+impl UnitConverter {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            client: http_client::client(config),
+            currency_api_url: config.currency_api_url.clone(),
+            egress: EgressPolicy::from_config(config),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Converts `value` from one unit to another. Units that aren't recognised but look like
+    /// ISO 4217 currency codes are converted using live exchange rates.
+    pub async fn convert(&self, value: f64, from: &str, to: &str) -> Result<ConversionResult, ConversionError> {
+        if lookup_unit(from).is_none() && lookup_unit(to).is_none() && is_currency_code(from) && is_currency_code(to) {
+            return self.convert_currency(value, from, to).await;
+        }
+        convert_units(value, from, to)
+    }
+
+    async fn convert_currency(&self, value: f64, from: &str, to: &str) -> Result<ConversionResult, ConversionError> {
+        let from = from.trim().to_uppercase();
+        let to = to.trim().to_uppercase();
+        let rates = self.rates_for(&from).await?;
+        let rate = *rates.rates.get(&to).ok_or_else(|| ConversionError::UnknownCurrency(to.clone()))?;
+
+        Ok(ConversionResult {
+            value,
+            from,
+            to,
+            result: value * rate,
+            rate: Some(rate),
+            rate_timestamp: rates.time_last_update_utc,
+        })
+    }
+
+    async fn rates_for(&self, base: &str) -> Result<RatesResponse, ConversionError> {
+        if let Some((fetched_at, rates)) = self.cache.lock().unwrap().get(base) {
+            if fetched_at.elapsed() < CURRENCY_CACHE_TTL {
+                return Ok(rates.clone());
+            }
+        }
+
+        let template = self.currency_api_url.as_ref().ok_or(ConversionError::CurrencyDisabled)?;
+        let url = template.replace("{base}", base);
+        self.egress.check_url(&url)?;
+
+        info!("Fetching exchange rates for {}", base);
+        let response = self.client.get(&url).send().await?;
+        if !response.status().is_success() {
+            return Err(ConversionError::ApiError(format!("HTTP {}", response.status())));
+        }
+        let rates: RatesResponse = response
+            .json()
+            .await
+            .map_err(|e| ConversionError::ApiError(format!("Unexpected response: {}", e)))?;
+        if rates.rates.is_empty() {
+            return Err(ConversionError::UnknownCurrency(base.to_string()));
+        }
+
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(base.to_string(), (Instant::now(), rates.clone()));
+        Ok(rates)
+    }
+}
@@ -0,0 +1,263 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::config::Config;
+use crate::egress::{EgressError, EgressPolicy};
+use crate::http_client;
+
+const GEOCODING_API_URL: &str = "https://geocoding-api.open-meteo.com/v1/search";
+const FORECAST_API_URL: &str = "https://api.open-meteo.com/v1/forecast";
+const MAX_CANDIDATES: usize = 5;
+const FORECAST_DAYS: &str = "3";
+
+#[derive(Error, Debug)]
+pub enum WeatherError {
+    #[error("Network error: {0}")]
+    NetworkError(#[from] reqwest::Error),
+    #[error("Weather API error: {0}")]
+    ApiError(String),
+    #[error("Unknown location: {0}. Try adding the country, e.g. \"Paris, France\".")]
+    LocationNotFound(String),
+    #[error("Unknown units: {0}. Use \"metric\" or \"imperial\".")]
+    InvalidUnits(String),
+    #[error(transparent)]
+    EgressDenied(#[from] EgressError),
+}
+
+/// A place returned by the geocoding API.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Location {
+    pub name: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin1: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeocodingResponse {
+    #[serde(default)]
+    results: Vec<Location>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ForecastResponse {
+    current: CurrentResponse,
+    daily: DailyResponse,
+}
+
+#[derive(Debug, Deserialize)]
+struct CurrentResponse {
+    time: String,
+    temperature_2m: f64,
+    apparent_temperature: f64,
+    relative_humidity_2m: f64,
+    wind_speed_10m: f64,
+    weather_code: u8,
+}
+
+/// Open-Meteo reports `null` for days it has no value for, e.g. beyond a model's range.
+#[derive(Debug, Deserialize)]
+struct DailyResponse {
+    time: Vec<String>,
+    weather_code: Vec<Option<u8>>,
+    temperature_2m_max: Vec<Option<f64>>,
+    temperature_2m_min: Vec<Option<f64>>,
+    precipitation_sum: Vec<Option<f64>>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CurrentConditions {
+    pub time: String,
+    pub temperature: f64,
+    pub feels_like: f64,
+    pub humidity_percent: f64,
+    pub wind_speed: f64,
+    pub conditions: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct DailyForecast {
+    pub date: String,
+    pub conditions: String,
+    /// None when Open-Meteo has no value for the day.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature_max: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature_min: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub precipitation: Option<f64>,
+}
+
+/// Current conditions and a short forecast for a location, as returned by the `get_weather` tool.
+#[derive(Debug, Serialize, Clone)]
+pub struct WeatherReport {
+    pub location: Location,
+    pub units: WeatherUnits,
+    pub current: CurrentConditions,
+    pub forecast: Vec<DailyForecast>,
+    /// Other places matching the requested name, when it was ambiguous.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub other_candidates: Vec<Location>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WeatherUnits {
+    Metric,
+    Imperial,
+}
+
+impl WeatherUnits {
+    pub fn parse(units: &str) -> Result<Self, WeatherError> {
+        match units.trim().to_lowercase().as_str() {
+            "metric" | "celsius" | "c" => Ok(WeatherUnits::Metric),
+            "imperial" | "fahrenheit" | "f" => Ok(WeatherUnits::Imperial),
+            other => Err(WeatherError::InvalidUnits(other.to_string())),
+        }
+    }
+
+    fn query_params(self) -> [(&'static str, &'static str); 3] {
+        match self {
+            WeatherUnits::Metric => [("temperature_unit", "celsius"), ("wind_speed_unit", "kmh"), ("precipitation_unit", "mm")],
+            WeatherUnits::Imperial => [("temperature_unit", "fahrenheit"), ("wind_speed_unit", "mph"), ("precipitation_unit", "inch")],
+        }
+    }
+}
+
+/// Describes a WMO weather interpretation code as used by Open-Meteo.
+fn describe_weather_code(code: u8) -> &'static str {
+    match code {
+        0 => "clear sky",
+        1 => "mainly clear",
+        2 => "partly cloudy",
+        3 => "overcast",
+        45 | 48 => "fog",
+        51 | 53 | 55 => "drizzle",
+        56 | 57 => "freezing drizzle",
+        61 | 63 | 65 => "rain",
+        66 | 67 => "freezing rain",
+        71 | 73 | 75 | 77 => "snow",
+        80..=82 => "rain showers",
+        85 | 86 => "snow showers",
+        95 => "thunderstorm",
+        96 | 99 => "thunderstorm with hail",
+        _ => "unknown",
+    }
+}
+
+fn daily_forecast(daily: DailyResponse) -> Vec<DailyForecast> {
+    let value = |values: &[Option<f64>], i: usize| values.get(i).copied().flatten();
+    daily.time
+        .iter()
+        .enumerate()
+        .map(|(i, date)| DailyForecast {
+            date: date.clone(),
+            conditions: describe_weather_code(daily.weather_code.get(i).copied().flatten().unwrap_or(u8::MAX)).to_string(),
+            temperature_max: value(&daily.temperature_2m_max, i),
+            temperature_min: value(&daily.temperature_2m_min, i),
+            precipitation: value(&daily.precipitation_sum, i),
+        })
+        .collect()
+}
+
+pub struct WeatherClient {
+    client: reqwest::Client,
+    egress: EgressPolicy,
+}
+
+impl WeatherClient {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            client: http_client::client(config),
+            egress: EgressPolicy::from_config(config),
+        }
+    }
+
+    /// Looks up places matching `name`, best match first. A trailing ", country" or ", region"
+    /// qualifier narrows the matches, since the geocoding API only searches by place name.
+    async fn geocode(&self, name: &str) -> Result<Vec<Location>, WeatherError> {
+        self.egress.check_url(GEOCODING_API_URL)?;
+
+        let mut parts = name.split(',').map(|p| p.trim()).filter(|p| !p.is_empty());
+        let place = parts.next().unwrap_or_default();
+        let qualifiers: Vec<String> = parts.map(|p| p.to_lowercase()).collect();
+        let count = (MAX_CANDIDATES * 4).to_string();
+
+        let response = self.client
+            .get(GEOCODING_API_URL)
+            .query(&[("name", place), ("count", count.as_str()), ("language", "en"), ("format", "json")])
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(WeatherError::ApiError(format!("HTTP {}", response.status())));
+        }
+
+        let geocoding: GeocodingResponse = response.json().await?;
+        let matches: Vec<Location> = geocoding.results
+            .into_iter()
+            .filter(|location| {
+                qualifiers.iter().all(|qualifier| {
+                    [&location.country, &location.admin1]
+                        .iter()
+                        .any(|field| field.as_deref().map(|f| f.to_lowercase().contains(qualifier.as_str())).unwrap_or(false))
+                })
+            })
+            .take(MAX_CANDIDATES)
+            .collect();
+        Ok(matches)
+    }
+
+    /// Fetches current conditions and a three-day forecast for `location`.
+    pub async fn get_weather(&self, location: &str, units: WeatherUnits) -> Result<WeatherReport, WeatherError> {
+        info!("Fetching weather for location: {}", location);
+
+        let mut candidates = self.geocode(location).await?;
+        if candidates.is_empty() {
+            return Err(WeatherError::LocationNotFound(location.to_string()));
+        }
+        let place = candidates.remove(0);
+
+        self.egress.check_url(FORECAST_API_URL)?;
+        let latitude = place.latitude.to_string();
+        let longitude = place.longitude.to_string();
+        let response = self.client
+            .get(FORECAST_API_URL)
+            .query(&[
+                ("latitude", latitude.as_str()),
+                ("longitude", longitude.as_str()),
+                ("current", "temperature_2m,apparent_temperature,relative_humidity_2m,wind_speed_10m,weather_code"),
+                ("daily", "weather_code,temperature_2m_max,temperature_2m_min,precipitation_sum"),
+                ("forecast_days", FORECAST_DAYS),
+                ("timezone", "auto"),
+            ])
+            .query(&units.query_params())
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(WeatherError::ApiError(format!("HTTP {}", response.status())));
+        }
+
+        let forecast: ForecastResponse = response.json().await?;
+        let current = CurrentConditions {
+            time: forecast.current.time,
+            temperature: forecast.current.temperature_2m,
+            feels_like: forecast.current.apparent_temperature,
+            humidity_percent: forecast.current.relative_humidity_2m,
+            wind_speed: forecast.current.wind_speed_10m,
+            conditions: describe_weather_code(forecast.current.weather_code).to_string(),
+        };
+        let forecast = daily_forecast(forecast.daily);
+
+        Ok(WeatherReport {
+            location: place,
+            units,
+            current,
+            forecast,
+            other_candidates: candidates,
+        })
+    }
+}
@@ -1,24 +1,391 @@
+use chrono::{Duration as ChronoDuration, Local, NaiveDate};
+use futures::stream::{self, StreamExt};
 use log::{info, error};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use scraper::{Html, Selector};
+use once_cell::sync::Lazy;
+use std::time::{Duration, Instant};
+use url::Url;
 
-#[derive(Debug, Clone, Copy)]
-pub enum SearchEngine {
-    DuckDuckGo,
+use crate::config::Config;
+use crate::egress::{EgressError, EgressPolicy};
+use crate::http_client;
+use crate::tools::ranking::{RankerKind, ResultRanker};
+use crate::tools::rate_limit::{backoff_delay, jitter, CircuitBreaker};
+use crate::tools::readability;
+
+/// Number of results when the caller doesn't ask for a specific count.
+pub const DEFAULT_SEARCH_COUNT: usize = 5;
+/// Timeout of one search request when not configured.
+pub const DEFAULT_SEARCH_TIMEOUT_SECS: u64 = 10;
+/// Cap on a search results page when not configured.
+pub const DEFAULT_SEARCH_MAX_RESPONSE_BYTES: usize = 2 * 1024 * 1024;
+/// Upper bound on result pages fetched for one search, regardless of configuration.
+const MAX_SEARCH_PAGES: usize = 5;
+
+/// Upper bound on parallel page fetches, regardless of configuration, to stay polite to target sites.
+const MAX_FETCH_CONCURRENCY: usize = 16;
+/// Parallel page fetches when not configured.
+pub const DEFAULT_SEARCH_FETCH_CONCURRENCY: usize = 4;
+
+/// Retries of a rate-limited search, with exponential backoff, before reporting it.
+const RATE_LIMIT_RETRIES: u32 = 2;
+/// Rate-limited searches in a row that open the circuit breaker, when not configured.
+pub const DEFAULT_SEARCH_BREAKER_THRESHOLD: u32 = 5;
+pub const DEFAULT_SEARCH_BREAKER_COOLDOWN_SECS: u64 = 60;
+
+// DuckDuckGo search result selectors, parsed once on first use
+static RESULT_SELECTOR: Lazy<Selector> = Lazy::new(|| parse_selector(".result"));
+static TITLE_SELECTOR: Lazy<Selector> = Lazy::new(|| parse_selector(".result__title a"));
+static SNIPPET_SELECTOR: Lazy<Selector> = Lazy::new(|| parse_selector(".result__snippet"));
+static TIMESTAMP_SELECTOR: Lazy<Selector> = Lazy::new(|| parse_selector(".result__timestamp"));
+// The zero-click instant answer box shown above the results for factual queries
+static INSTANT_ANSWER_SELECTOR: Lazy<Selector> = Lazy::new(|| parse_selector(".zci"));
+static INSTANT_ANSWER_HEADING_SELECTOR: Lazy<Selector> = Lazy::new(|| parse_selector(".zci__heading"));
+static INSTANT_ANSWER_BODY_SELECTOR: Lazy<Selector> = Lazy::new(|| parse_selector(".zci__result"));
+static LINK_SELECTOR: Lazy<Selector> = Lazy::new(|| parse_selector("a[href]"));
+static PAGE_CONTENT_SELECTOR: Lazy<Selector> =
+    Lazy::new(|| parse_selector("p, h1, h2, h3, h4, h5, h6, article, section"));
+
+fn parse_selector(selector: &str) -> Selector {
+    Selector::parse(selector).unwrap_or_else(|e| panic!("Invalid CSS selector {:?}: {:?}", selector, e))
+}
+
+/// Parses all selectors up front so an invalid one fails at startup rather than mid-request.
+fn init_selectors() {
+    Lazy::force(&RESULT_SELECTOR);
+    Lazy::force(&TITLE_SELECTOR);
+    Lazy::force(&SNIPPET_SELECTOR);
+    Lazy::force(&TIMESTAMP_SELECTOR);
+    Lazy::force(&INSTANT_ANSWER_SELECTOR);
+    Lazy::force(&INSTANT_ANSWER_HEADING_SELECTOR);
+    Lazy::force(&INSTANT_ANSWER_BODY_SELECTOR);
+    Lazy::force(&LINK_SELECTOR);
+    Lazy::force(&PAGE_CONTENT_SELECTOR);
+}
+
+/// How text is extracted from fetched pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtractionMode {
+    /// All paragraphs, headings, articles and sections.
+    #[default]
+    All,
+    /// Only the main article body, falling back to `All` when none is found.
+    Readability,
+}
+
+impl std::str::FromStr for ExtractionMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "all" => Ok(ExtractionMode::All),
+            "readability" => Ok(ExtractionMode::Readability),
+            other => Err(format!("unknown extraction mode {:?}", other)),
+        }
+    }
 }
 
-impl Default for SearchEngine {
-    fn default() -> Self {
-        SearchEngine::DuckDuckGo
+/// DuckDuckGo's safe search level, sent as its `kp` parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SafeSearch {
+    Off,
+    Moderate,
+    Strict,
+}
+
+impl SafeSearch {
+    fn kp(self) -> &'static str {
+        match self {
+            SafeSearch::Off => "-2",
+            SafeSearch::Moderate => "-1",
+            SafeSearch::Strict => "1",
+        }
     }
 }
 
+impl std::str::FromStr for SafeSearch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "off" => Ok(SafeSearch::Off),
+            "moderate" => Ok(SafeSearch::Moderate),
+            "strict" => Ok(SafeSearch::Strict),
+            other => Err(format!("unknown safe search level {:?}, expected off, moderate or strict", other)),
+        }
+    }
+}
+
+/// Restrictions for one search. Unset region and safe search fall back to `SEARCH_REGION` and
+/// `SEARCH_SAFE_SEARCH`.
+#[derive(Debug, Clone, Default)]
+pub struct SearchFilters {
+    /// Only results from this domain and its subdomains.
+    pub site: Option<String>,
+    /// DuckDuckGo region code such as `us-en` or `uk-en`, sent as `kl`.
+    pub region: Option<String>,
+    pub safe_search: Option<SafeSearch>,
+}
+
+/// Checks a DuckDuckGo region code: two lowercase parts joined by a dash, e.g. `us-en` or `wt-wt`.
+pub fn normalize_region(region: &str) -> Result<String, WebSearchError> {
+    let region = region.trim().to_lowercase();
+    let valid = region
+        .split_once('-')
+        .map(|(country, language)| {
+            [country, language]
+                .iter()
+                .all(|part| (2..=3).contains(&part.len()) && part.chars().all(|c| c.is_ascii_lowercase()))
+        })
+        .unwrap_or(false);
+    if valid {
+        Ok(region)
+    } else {
+        Err(WebSearchError::InvalidRegion(region))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub enum SearchEngine {
+    #[default]
+    DuckDuckGo,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SearchResult {
     pub title: String,
     pub content: String,
     pub url: String,
+    /// Relevance of the result to the query, higher is better.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+    /// Truncated HTML of the fetched page, only set when debugging content extraction.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_html: Option<String>,
+    /// Publication date as `YYYY-MM-DD`, when the search engine shows one for the result.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published: Option<String>,
+    /// Set on DuckDuckGo's instant answer, which is always returned first.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub instant_answer: bool,
+}
+
+/// Text extracted from a fetched page, plus the raw HTML when requested.
+#[derive(Debug, Clone)]
+pub struct PageContent {
+    pub text: String,
+    pub raw_html: Option<String>,
+}
+
+const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36";
+
+/// Upper bound on the raw HTML returned for debugging.
+const RAW_HTML_MAX_CHARS: usize = 20_000;
+
+/// Media types fetched pages may have when `FETCH_CONTENT_TYPES` is unset.
+pub const DEFAULT_FETCH_CONTENT_TYPES: &[&str] = &["text/html", "application/xhtml+xml", "application/json", "text/plain"];
+
+/// How a fetched body is turned into text.
+#[derive(Debug, PartialEq, Eq)]
+enum PageKind {
+    /// Extracted with the page content selectors.
+    Html,
+    /// Pretty-printed.
+    Json,
+    /// Returned as-is.
+    Text,
+    /// Binary; only described.
+    Binary,
+}
+
+fn page_kind(media_type: &str) -> PageKind {
+    match media_type {
+        "text/html" | "application/xhtml+xml" => PageKind::Html,
+        "application/json" => PageKind::Json,
+        t if t.ends_with("+json") => PageKind::Json,
+        t if t.starts_with("text/") || t == "application/xml" || t.ends_with("+xml") => PageKind::Text,
+        _ => PageKind::Binary,
+    }
+}
+
+/// Whether `media_type` is listed in `allowed`. A `type/*` entry allows every subtype.
+fn content_type_allowed(allowed: &[String], media_type: &str) -> bool {
+    allowed.iter().any(|entry| match entry.strip_suffix("/*") {
+        Some(top_level) => media_type.split('/').next() == Some(top_level),
+        None => entry == media_type,
+    })
+}
+
+/// Extracts DuckDuckGo's instant answer box, if the page has one with text in it.
+fn parse_instant_answer(document: &Html) -> Option<SearchResult> {
+    let answer_box = document.select(&INSTANT_ANSWER_SELECTOR).next()?;
+    let collect_text = |selector: &Selector| {
+        answer_box
+            .select(selector)
+            .next()
+            .map(|e| e.text().collect::<Vec<_>>().join(" ").split_whitespace().collect::<Vec<_>>().join(" "))
+            .unwrap_or_default()
+    };
+    let content = collect_text(&INSTANT_ANSWER_BODY_SELECTOR);
+    if content.is_empty() {
+        return None;
+    }
+    let title = collect_text(&INSTANT_ANSWER_HEADING_SELECTOR);
+    let url = answer_box
+        .select(&LINK_SELECTOR)
+        .filter_map(|a| a.value().attr("href"))
+        .map(resolve_duckduckgo_link)
+        .find(|url| !url.is_empty())
+        .unwrap_or_default();
+
+    Some(SearchResult {
+        title: if title.is_empty() { "Instant answer".to_string() } else { title },
+        content,
+        url,
+        score: None,
+        raw_html: None,
+        published: None,
+        instant_answer: true,
+    })
+}
+
+/// With `recent_only`, results published longer ago than this are dropped.
+const RECENT_WINDOW_DAYS: i64 = 30;
+
+const ABSOLUTE_DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%b %d, %Y", "%B %d, %Y", "%d %b %Y", "%d %B %Y", "%m/%d/%Y"];
+
+/// Parses a date like "3 days ago", "yesterday", "Jan 5, 2024" or "2024-01-05" at the start of
+/// `text`, relative to `today`. DuckDuckGo prefixes dated snippets this way, separated from the
+/// rest by a dash or middle dot.
+pub fn parse_published(text: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let head = text
+        .trim()
+        .split(['·', '—', '–', '|', '\n'])
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .trim_end_matches(" -")
+        .trim();
+    if head.is_empty() || head.len() > 40 {
+        return None;
+    }
+
+    let lower = head.to_lowercase();
+    match lower.as_str() {
+        "today" | "just now" => return Some(today),
+        "yesterday" => return today.checked_sub_signed(ChronoDuration::try_days(1)?),
+        _ => {}
+    }
+
+    let words: Vec<&str> = lower.split_whitespace().collect();
+    if let [amount, unit, "ago"] = words.as_slice() {
+        let amount: i64 = match *amount {
+            "a" | "an" => 1,
+            n => n.parse().ok()?,
+        };
+        // Scraped text can hold any number, so out-of-range amounts read as no date rather than panic
+        let days = match unit.trim_end_matches('s') {
+            "second" | "sec" | "minute" | "min" | "hour" | "hr" => 0,
+            "day" => amount,
+            "week" => amount.checked_mul(7)?,
+            "month" => amount.checked_mul(30)?,
+            "year" => amount.checked_mul(365)?,
+            _ => return None,
+        };
+        return today.checked_sub_signed(ChronoDuration::try_days(days)?);
+    }
+
+    // Timestamps such as "2024-01-05T10:00:00" only need their date part
+    let date_part = head.split('T').next().unwrap_or(head);
+    ABSOLUTE_DATE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(date_part, format).ok())
+        .or_else(|| ABSOLUTE_DATE_FORMATS.iter().find_map(|format| NaiveDate::parse_from_str(head, format).ok()))
+}
+
+/// Keeps only results published within the last `RECENT_WINDOW_DAYS`, newest first. Results
+/// without a known date are dropped.
+fn retain_recent(results: &mut Vec<SearchResult>, today: NaiveDate) {
+    let cutoff = today - ChronoDuration::days(RECENT_WINDOW_DAYS);
+    let published = |r: &SearchResult| {
+        r.published.as_deref().and_then(|p| NaiveDate::parse_from_str(p, "%Y-%m-%d").ok())
+    };
+    results.retain(|r| published(r).map(|date| date >= cutoff).unwrap_or(false));
+    results.sort_by_key(|r| std::cmp::Reverse(published(r)));
+}
+
+/// Cuts each result's content to at most `max_chars` characters, marking the cut with an
+/// ellipsis. Zero leaves content untouched.
+pub fn truncate_contents(results: &mut [SearchResult], max_chars: usize) {
+    if max_chars == 0 {
+        return;
+    }
+    for result in results.iter_mut() {
+        // Byte offset of the first char past the limit, so the cut never splits a character
+        if let Some((cut, _)) = result.content.char_indices().nth(max_chars) {
+            result.content.truncate(cut);
+            result.content.push('…');
+        }
+    }
+}
+
+/// Renders results as a numbered Markdown list of linked titles, each followed by its snippet.
+pub fn format_markdown(results: &[SearchResult]) -> String {
+    let mut out = String::new();
+    for (i, result) in results.iter().enumerate() {
+        // Brackets in the title would end the link text early
+        let title = result.title.replace('[', "\\[").replace(']', "\\]");
+        // Parentheses in the URL would end the link target early
+        let url = result.url.replace('(', "%28").replace(')', "%29");
+        out.push_str(&format!("{}. [{}]({})\n", i + 1, title, url));
+        let snippet = result.content.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !snippet.is_empty() {
+            out.push_str(&format!("   {}\n", snippet));
+        }
+    }
+    out
+}
+
+/// Renders results as their URLs, one per line.
+pub fn format_urls(results: &[SearchResult]) -> String {
+    results.iter().map(|result| format!("{}\n", result.url)).collect()
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_lowercase())
+        .collect()
+}
+
+/// Scores a result by term-frequency overlap between the query and its title and content.
+/// Query terms appearing in the title count double.
+pub fn relevance_score(query: &str, title: &str, content: &str) -> f64 {
+    let mut query_terms = tokenize(query);
+    query_terms.sort();
+    query_terms.dedup();
+    if query_terms.is_empty() {
+        return 0.0;
+    }
+
+    let title_terms = tokenize(title);
+    let content_terms = tokenize(content);
+
+    let mut score = 0.0;
+    for term in &query_terms {
+        let title_tf = title_terms.iter().filter(|t| *t == term).count() as f64;
+        let content_tf = content_terms.iter().filter(|t| *t == term).count() as f64;
+        let tf = 2.0 * title_tf + content_tf;
+        if tf > 0.0 {
+            // Dampen repeated terms so a single keyword can't dominate
+            score += 1.0 + tf.ln();
+        }
+    }
+    score / query_terms.len() as f64
 }
 
 #[derive(Error, Debug)]
@@ -29,108 +396,689 @@ pub enum WebSearchError {
     UrlParseError(#[from] url::ParseError),
     #[error("Search error: {0}")]
     SearchError(String),
+    #[error("Search engine is rate limiting requests ({0}), try again later")]
+    RateLimited(String),
+    #[error("Search did not finish within {0:?}")]
+    Timeout(Duration),
+    #[error("Invalid site {0:?}, expected a domain such as docs.python.org")]
+    InvalidSite(String),
+    #[error("Invalid region {0:?}, expected a DuckDuckGo region code such as us-en or uk-en")]
+    InvalidRegion(String),
+    #[error("Pages of type {0} are not fetched; add it to FETCH_CONTENT_TYPES to allow it")]
+    UnsupportedContentType(String),
+    #[error(transparent)]
+    EgressDenied(#[from] EgressError),
+}
+
+impl WebSearchError {
+    /// Whether the caller's input was at fault, so it can correct it and retry.
+    pub fn is_invalid_input(&self) -> bool {
+        matches!(self, WebSearchError::InvalidSite(_) | WebSearchError::InvalidRegion(_))
+    }
+}
+
+/// Markers of DuckDuckGo's CAPTCHA ("anomaly") page, served instead of results when it
+/// suspects a bot.
+const ANOMALY_MARKERS: &[&str] = &["anomaly-modal", "challenge-form", "/anomaly.js", "bots use DuckDuckGo too"];
+
+fn is_anomaly_page(html: &str) -> bool {
+    ANOMALY_MARKERS.iter().any(|marker| html.contains(marker))
 }
 
 pub struct WebSearchClient {
     client: reqwest::Client,
     engine: SearchEngine,
+    /// Number of results when the caller doesn't ask for a specific count.
+    default_count: usize,
+    fetch_concurrency: usize,
+    egress: EgressPolicy,
+    /// Retry once with a simplified query when a search returns nothing.
+    reformulate_empty_queries: bool,
+    /// Keep DuckDuckGo bang syntax in queries instead of stripping it.
+    allow_bangs: bool,
+    /// Whether callers may ask for raw page HTML alongside extracted content.
+    allow_raw_html: bool,
+    extraction_mode: ExtractionMode,
+    /// Region used when a search doesn't name one; DuckDuckGo picks when unset.
+    region: Option<String>,
+    /// Safe search level used when a search doesn't name one; DuckDuckGo's default when unset.
+    safe_search: Option<SafeSearch>,
+    /// Media types fetched pages may have, lowercase; `type/*` allows a whole top-level type.
+    fetch_content_types: Vec<String>,
+    /// Limit on the whole request to the search engine, including reading the body.
+    search_timeout: Duration,
+    /// Limit on a whole search across all pages and the reformulated retry.
+    search_deadline: Option<Duration>,
+    /// Search result pages beyond this size are truncated before parsing.
+    search_max_response_bytes: usize,
+    /// Results with shorter snippets are dropped, and don't count towards stopping early.
+    min_content_len: usize,
+    /// Result pages fetched at most before giving up on reaching the requested count.
+    max_pages: usize,
+    /// Orders results after scraping, before they are truncated to the requested count.
+    ranker: Box<dyn ResultRanker>,
+    /// Stops searching for a while after repeated rate limiting.
+    breaker: CircuitBreaker,
+}
+
+/// Removes DuckDuckGo bang commands (`!g`, `!w`, ...) and the leading `\\` "feeling lucky"
+/// prefix, both of which redirect away from the results page.
+pub fn neutralize_bangs(query: &str) -> String {
+    query
+        .split_whitespace()
+        .filter(|word| !(word.starts_with('!') && word.len() > 1))
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_start_matches('\\')
+        .trim()
+        .to_string()
+}
+
+/// Simplifies an over-specific query: drops quotes, brackets and search operators
+/// (`site:`, `-term`), cuts trailing qualifiers after separators and keeps at most six words.
+pub fn simplify_query(query: &str) -> String {
+    let head = query
+        .split([',', '|', ';'])
+        .next()
+        .unwrap_or(query);
+    let head = head.split(" - ").next().unwrap_or(head);
+
+    let mut without_parens = String::new();
+    let mut depth = 0;
+    for c in head.chars() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = (depth - 1).max(0),
+            _ if depth == 0 => without_parens.push(c),
+            _ => {}
+        }
+    }
+
+    without_parens
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c: char| c == '"' || c == '\'' || c == '`'))
+        .filter(|word| !word.is_empty() && !word.contains(':') && !word.starts_with('-'))
+        .take(6)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Reduces a `site` restriction to a bare lowercase host, accepting forms like
+/// `https://www.example.com/docs` or `example.com`.
+pub fn normalize_site(site: &str) -> Result<String, WebSearchError> {
+    let site = site.trim().to_lowercase();
+    let without_scheme = site.split_once("://").map(|(_, rest)| rest).unwrap_or(&site);
+    let host = without_scheme
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or_default()
+        .trim_start_matches("www.")
+        .trim_end_matches('.');
+    let valid = host.contains('.')
+        && host.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+        && !host.split('.').any(str::is_empty);
+    if valid {
+        Ok(host.to_string())
+    } else {
+        Err(WebSearchError::InvalidSite(site))
+    }
+}
+
+/// Whether `url` is on `site` or one of its subdomains.
+fn is_on_site(url: &str, site: &str) -> bool {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_lowercase))
+        .map(|host| host == site || host.ends_with(&format!(".{}", site)))
+        .unwrap_or(false)
+}
+
+/// DuckDuckGo wraps result links in a redirect (`//duckduckgo.com/l/?uddg=<target>`).
+/// Returns the target URL, or the link unchanged when it isn't a redirect.
+fn resolve_duckduckgo_link(href: &str) -> String {
+    let absolute = if href.starts_with("//") {
+        format!("https:{}", href)
+    } else {
+        href.to_string()
+    };
+
+    Url::parse(&absolute)
+        .ok()
+        .and_then(|url| {
+            url.query_pairs()
+                .find(|(key, _)| key == "uddg")
+                .map(|(_, target)| target.into_owned())
+        })
+        .unwrap_or(absolute)
 }
 
 impl WebSearchClient {
     pub fn new() -> Self {
+        init_selectors();
         Self {
             client: reqwest::Client::builder()
-                .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36")
+                .user_agent(USER_AGENT)
                 .build()
                 .unwrap(),
             engine: SearchEngine::default(),
+            default_count: DEFAULT_SEARCH_COUNT,
+            fetch_concurrency: DEFAULT_SEARCH_FETCH_CONCURRENCY,
+            egress: EgressPolicy::default(),
+            reformulate_empty_queries: false,
+            allow_bangs: false,
+            allow_raw_html: false,
+            extraction_mode: ExtractionMode::default(),
+            region: None,
+            safe_search: None,
+            fetch_content_types: DEFAULT_FETCH_CONTENT_TYPES.iter().map(|t| t.to_string()).collect(),
+            search_timeout: Duration::from_secs(DEFAULT_SEARCH_TIMEOUT_SECS),
+            search_deadline: None,
+            search_max_response_bytes: DEFAULT_SEARCH_MAX_RESPONSE_BYTES,
+            min_content_len: 0,
+            max_pages: 1,
+            ranker: RankerKind::default().ranker(),
+            breaker: CircuitBreaker::new(
+                DEFAULT_SEARCH_BREAKER_THRESHOLD,
+                Duration::from_secs(DEFAULT_SEARCH_BREAKER_COOLDOWN_SECS),
+            ),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            client: http_client::build(http_client::builder(config).user_agent(USER_AGENT)),
+            default_count: config.default_search_count,
+            fetch_concurrency: config.search_fetch_concurrency.clamp(1, MAX_FETCH_CONCURRENCY),
+            egress: EgressPolicy::from_config(config),
+            reformulate_empty_queries: config.search_reformulate_empty,
+            allow_bangs: config.search_allow_bangs,
+            allow_raw_html: config.debug_raw_html,
+            extraction_mode: config.fetch_extraction_mode,
+            region: config.search_region.as_deref().and_then(|region| {
+                normalize_region(region)
+                    .map_err(|e| error!("Ignoring SEARCH_REGION: {}", e))
+                    .ok()
+            }),
+            safe_search: config.search_safe_search,
+            fetch_content_types: config.fetch_content_types.clone(),
+            search_timeout: Duration::from_secs(config.search_timeout_secs),
+            search_deadline: (config.search_deadline_secs > 0).then(|| Duration::from_secs(config.search_deadline_secs)),
+            search_max_response_bytes: config.search_max_response_bytes,
+            min_content_len: config.search_min_content_len,
+            max_pages: config.search_max_pages.clamp(1, MAX_SEARCH_PAGES),
+            ranker: config.search_ranker.ranker(),
+            breaker: CircuitBreaker::new(
+                config.search_breaker_threshold,
+                Duration::from_secs(config.search_breaker_cooldown_secs),
+            ),
+            ..Self::new()
         }
     }
 
     #[allow(dead_code)]
     pub fn with_engine(engine: SearchEngine) -> Self {
         Self {
-            client: reqwest::Client::builder()
-                .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36")
-                .build()
-                .unwrap(),
             engine,
+            ..Self::new()
         }
     }
 
-    pub async fn search(&self, query: String, count: usize) -> Result<Vec<SearchResult>, WebSearchError> {
-        match self.engine {
-            SearchEngine::DuckDuckGo => self.search_duckduckgo(&query, count).await,
+    /// Replaces the ranker chosen by `SEARCH_RANKER` with a custom one.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn with_ranker(self, ranker: impl ResultRanker + 'static) -> Self {
+        Self {
+            ranker: Box::new(ranker),
+            ..self
         }
     }
 
-    async fn search_duckduckgo(&self, query: &str, count: usize) -> Result<Vec<SearchResult>, WebSearchError> {
-        info!("Performing DuckDuckGo search for query: {}", query);
-        
-        let search_url = format!(
-            "https://html.duckduckgo.com/html/?q={}",
-            urlencoding::encode(query)
-        );
-
-        let response = self.client
-            .get(&search_url)
-            .send()
-            .await?
-            .text()
-            .await?;
-
-        let document = Html::parse_document(&response);
-        
-        // DuckDuckGo search result selectors
-        let result_selector = Selector::parse(".result").unwrap();
-        let title_selector = Selector::parse(".result__title a").unwrap();
-        let snippet_selector = Selector::parse(".result__snippet").unwrap();
+    pub fn default_count(&self) -> usize {
+        self.default_count
+    }
+
+    /// Searches with the configured engine, then orders results with the configured ranker
+    /// before truncating to `count`. With `recent_only`, only results dated within the last
+    /// month are kept, newest first. The whole search, retry included, is bounded by
+    /// `SEARCH_DEADLINE_SECS`; when it runs out the results gathered so far are returned.
+    /// `filters` can restrict results to a site and set the region and safe search level.
+    pub async fn search(&self, query: String, count: usize, recent_only: bool, filters: &SearchFilters) -> Result<Vec<SearchResult>, WebSearchError> {
+        let filters = &self.resolve_filters(filters)?;
+        let deadline = self.deadline();
+        // Filtering by date happens after the search, so it can't stop at the first `count` results
+        let wanted = if recent_only { usize::MAX } else { count };
+        let mut results = self.search_engine(&query, filters, wanted, deadline).await?;
+
+        let time_left = deadline.map(|d| Instant::now() < d).unwrap_or(true);
+        if results.is_empty() && self.reformulate_empty_queries && time_left {
+            let simplified = simplify_query(&query);
+            if !simplified.is_empty() && simplified != query {
+                info!("No results for query {:?}, retrying with reformulated query {:?}", query, simplified);
+                results = self.search_engine(&simplified, filters, wanted, deadline).await?;
+            }
+        }
+
+        let mut results = self.ranker.rank(&query, results);
+        if recent_only {
+            retain_recent(&mut results, Local::now().date_naive());
+        }
+        // The instant answer is the engine's direct answer, so it leads regardless of score
+        results.sort_by_key(|r| !r.instant_answer);
+        results.truncate(count);
+
+        Ok(results)
+    }
+
+    /// Streams results in engine order, passing each to `emit` as soon as it is parsed. Results are
+    /// scored but not sorted by relevance. Returns the number of results emitted.
+    pub async fn search_each(
+        &self,
+        query: &str,
+        count: usize,
+        filters: &SearchFilters,
+        mut emit: impl FnMut(SearchResult),
+    ) -> Result<usize, WebSearchError> {
+        let filters = self.resolve_filters(filters)?;
+        let mut emitted = 0;
+        self.search_engine_each(query, &filters, self.deadline(), &mut |mut result: SearchResult| {
+            if emitted >= count {
+                return false;
+            }
+            result.score = Some(relevance_score(query, &result.title, &result.content));
+            emit(result);
+            emitted += 1;
+            emitted < count
+        })
+        .await?;
+        Ok(emitted)
+    }
 
+    /// Validates `filters` and fills unset fields from the configured defaults.
+    fn resolve_filters(&self, filters: &SearchFilters) -> Result<SearchFilters, WebSearchError> {
+        Ok(SearchFilters {
+            site: filters.site.as_deref().map(normalize_site).transpose()?,
+            region: match &filters.region {
+                Some(region) => Some(normalize_region(region)?),
+                None => self.region.clone(),
+            },
+            safe_search: filters.safe_search.or(self.safe_search),
+        })
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.search_deadline.map(|limit| Instant::now() + limit)
+    }
+
+    /// Collects results in engine order, stopping as soon as `count` have been found.
+    async fn search_engine(
+        &self,
+        query: &str,
+        filters: &SearchFilters,
+        count: usize,
+        deadline: Option<Instant>,
+    ) -> Result<Vec<SearchResult>, WebSearchError> {
         let mut results = Vec::new();
-        
-        for result in document.select(&result_selector).take(count) {
-            if let (Some(title_elem), Some(snippet_elem)) = (
-                result.select(&title_selector).next(),
-                result.select(&snippet_selector).next()
-            ) {
-                let title = title_elem.text().collect::<String>();
-                let content = snippet_elem.text().collect::<String>();
-                let url = title_elem.value().attr("href").unwrap_or("").to_string();
-
-                // Only add results with valid URLs
-                if !url.is_empty() {
-                    results.push(SearchResult {
-                        title: title.trim().to_string(),
-                        content: content.trim().to_string(),
-                        url,
-                    });
+        self.search_engine_each(query, filters, deadline, &mut |result: SearchResult| {
+            results.push(result);
+            results.len() < count
+        })
+        .await?;
+        Ok(results)
+    }
+
+    /// Runs the configured engine, handing each result to `emit` as it is parsed.
+    /// Parsing stops early when `emit` returns false, and at `deadline`. `filters` must already
+    /// be resolved. A rate-limited search is retried with exponential backoff while nothing has
+    /// been emitted and the deadline allows; the circuit breaker fails searches fast after
+    /// repeated rate limiting.
+    async fn search_engine_each(
+        &self,
+        query: &str,
+        filters: &SearchFilters,
+        deadline: Option<Instant>,
+        emit: &mut dyn FnMut(SearchResult) -> bool,
+    ) -> Result<(), WebSearchError> {
+        let mut attempt = 0;
+        loop {
+            if let Err(remaining) = self.breaker.check() {
+                return Err(WebSearchError::RateLimited(format!(
+                    "backing off for another {}s after repeated rate limiting",
+                    remaining.as_secs() + 1
+                )));
+            }
+
+            let mut emitted = false;
+            let mut emit_once = |result: SearchResult| {
+                emitted = true;
+                emit(result)
+            };
+            let outcome = match self.engine {
+                SearchEngine::DuckDuckGo => self.search_duckduckgo(query, filters, deadline, &mut emit_once).await,
+            };
+
+            let reason = match outcome {
+                Err(WebSearchError::RateLimited(reason)) => reason,
+                outcome => {
+                    if outcome.is_ok() {
+                        self.breaker.record_success();
+                    }
+                    return outcome;
                 }
+            };
+            let opened = self.breaker.record_rate_limited();
+            let delay = backoff_delay(attempt, jitter());
+            let time_left = deadline.map(|d| Instant::now() + delay < d).unwrap_or(true);
+            // Retrying after results went out would emit them twice
+            if opened || emitted || attempt >= RATE_LIMIT_RETRIES || !time_left {
+                return Err(WebSearchError::RateLimited(reason));
             }
+            info!("Search rate limited ({}), retrying in {:?}", reason, delay);
+            tokio::time::sleep(delay).await;
+            attempt += 1;
         }
+    }
 
-        info!("Found {} DuckDuckGo search results", results.len());
-        Ok(results)
+    /// Reads a response body as text, stopping once `search_max_response_bytes` have been received.
+    async fn fetch_capped(&self, url: &str) -> Result<String, WebSearchError> {
+        let mut response = self.client.get(url).send().await?;
+        if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Err(WebSearchError::RateLimited(format!("HTTP {}", response.status())));
+        }
+        if response.url().path().contains("anomaly") {
+            return Err(WebSearchError::RateLimited("redirected to a CAPTCHA".to_string()));
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if body.len() >= self.search_max_response_bytes {
+                info!("Search response exceeded {} bytes, truncating", self.search_max_response_bytes);
+                body.truncate(self.search_max_response_bytes);
+                break;
+            }
+        }
+
+        Ok(String::from_utf8_lossy(&body).into_owned())
     }
 
-    #[allow(dead_code)]
-    pub async fn fetch_page_content(&self, url: &str) -> Result<String, WebSearchError> {
+    async fn search_duckduckgo(
+        &self,
+        query: &str,
+        filters: &SearchFilters,
+        deadline: Option<Instant>,
+        emit: &mut dyn FnMut(SearchResult) -> bool,
+    ) -> Result<(), WebSearchError> {
+        info!("Performing DuckDuckGo search for query: {}", query);
+
+        let query = if self.allow_bangs {
+            query.to_string()
+        } else {
+            let neutralized = neutralize_bangs(query);
+            if neutralized != query {
+                info!("Stripped DuckDuckGo bang syntax from query: {:?}", neutralized);
+            }
+            neutralized
+        };
+        if query.is_empty() {
+            return Err(WebSearchError::SearchError("query is empty".to_string()));
+        }
+        let site = filters.site.as_deref();
+        // Appended after bang handling, so the restriction can't be mistaken for a bang
+        let query = match site {
+            Some(site) => format!("{} site:{}", query, site),
+            None => query,
+        };
         
-        let response = self.client
-            .get(url)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let mut found = 0;
+        let mut offset = 0;
 
+        // Later pages are only fetched while `emit` still wants results
+        for page in 0..self.max_pages {
+            let mut search_url = format!(
+                "https://html.duckduckgo.com/html/?q={}",
+                urlencoding::encode(&query)
+            );
+            if let Some(region) = &filters.region {
+                search_url.push_str(&format!("&kl={}", urlencoding::encode(region)));
+            }
+            if let Some(safe_search) = filters.safe_search {
+                search_url.push_str(&format!("&kp={}", safe_search.kp()));
+            }
+            if offset > 0 {
+                search_url.push_str(&format!("&s={}&dc={}", offset, offset + 1));
+            }
+            self.egress.check_url(&search_url)?;
+
+            let page_timeout = match deadline {
+                Some(deadline) => self.search_timeout.min(deadline.saturating_duration_since(Instant::now())),
+                None => self.search_timeout,
+            };
+            let response = match tokio::time::timeout(page_timeout, self.fetch_capped(&search_url)).await {
+                Ok(response) => response?,
+                Err(_) if deadline.map(|d| Instant::now() >= d).unwrap_or(false) => {
+                    // Out of time for the whole search: keep what earlier pages produced
+                    if found > 0 {
+                        info!("Search deadline reached, returning {} result(s) found so far", found);
+                        return Ok(());
+                    }
+                    return Err(WebSearchError::Timeout(self.search_deadline.unwrap_or_default()));
+                }
+                Err(_) => return Err(WebSearchError::SearchError("search timed out".to_string())),
+            };
+
+            let document = Html::parse_document(&response);
+            let mut on_page = 0;
+
+            // The instant answer isn't tied to a page on the site
+            if page == 0 && site.is_none() {
+                if let Some(answer) = parse_instant_answer(&document) {
+                    found += 1;
+                    if !emit(answer) {
+                        info!("Found {} DuckDuckGo search results on 1 page(s)", found);
+                        return Ok(());
+                    }
+                }
+            }
+
+            for result in document.select(&RESULT_SELECTOR) {
+                if let (Some(title_elem), Some(snippet_elem)) = (
+                    result.select(&TITLE_SELECTOR).next(),
+                    result.select(&SNIPPET_SELECTOR).next()
+                ) {
+                    on_page += 1;
+                    let title = title_elem.text().collect::<String>();
+                    let content = snippet_elem.text().collect::<String>();
+                    let url = title_elem.value().attr("href")
+                        .map(resolve_duckduckgo_link)
+                        .unwrap_or_default();
+                    let today = Local::now().date_naive();
+                    let published = result.select(&TIMESTAMP_SELECTOR)
+                        .next()
+                        .and_then(|t| parse_published(&t.text().collect::<String>(), today))
+                        .or_else(|| parse_published(&content, today))
+                        .map(|date| date.format("%Y-%m-%d").to_string());
+
+                    // DuckDuckGo treats `site:` as a hint, so off-site results are dropped here
+                    let on_site = site.map(|site| is_on_site(&url, site)).unwrap_or(true);
+                    // Only add results with valid URLs and enough content to be worth reading
+                    if !url.is_empty() && on_site && content.trim().chars().count() >= self.min_content_len {
+                        found += 1;
+                        let keep_going = emit(SearchResult {
+                            title: title.trim().to_string(),
+                            content: content.trim().to_string(),
+                            url,
+                            score: None,
+                            raw_html: None,
+                            published,
+                            instant_answer: false,
+                        });
+                        if !keep_going {
+                            info!("Found {} DuckDuckGo search results on {} page(s)", found, page + 1);
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+
+            if on_page == 0 {
+                // An anomaly page has no results either, but must not pass for an empty search
+                if is_anomaly_page(&response) {
+                    return Err(WebSearchError::RateLimited("CAPTCHA page returned".to_string()));
+                }
+                break;
+            }
+            offset += on_page;
+        }
+
+        info!("Found {} DuckDuckGo search results", found);
+        Ok(())
+    }
+
+    /// Replaces each result's snippet with the full text of its page, fetching up to
+    /// `fetch_concurrency` pages at a time. A failed fetch leaves that result's content empty.
+    /// With `include_raw_html` (and `DEBUG_RAW_HTML` enabled) each result also carries its
+    /// truncated page HTML. The instant answer keeps its own text.
+    pub async fn fetch_full_contents(&self, results: &mut [SearchResult], include_raw_html: bool) {
+        let include_raw_html = include_raw_html && self.allow_raw_html;
+        let urls: Vec<(usize, String)> = results.iter()
+            .enumerate()
+            .filter(|(_, r)| !r.instant_answer)
+            .map(|(i, r)| (i, r.url.clone()))
+            .collect();
+
+        let fetched: Vec<(usize, PageContent)> = stream::iter(urls)
+            .map(|(i, url)| async move {
+                match self.fetch_page_content(&url, include_raw_html).await {
+                    Ok(page) => (i, page),
+                    Err(e) => {
+                        error!("Failed to fetch page content for {}: {}", url, e);
+                        (i, PageContent { text: String::new(), raw_html: None })
+                    }
+                }
+            })
+            .buffer_unordered(self.fetch_concurrency)
+            .collect()
+            .await;
+
+        for (i, page) in fetched {
+            results[i].content = page.text;
+            results[i].raw_html = page.raw_html;
+        }
+    }
+
+    /// Fetches `url` and returns its text. How depends on the `Content-Type`: HTML is extracted,
+    /// JSON pretty-printed and other text returned as-is. Types not in `FETCH_CONTENT_TYPES` are
+    /// rejected; allowed binary types are only described. Raw HTML is only kept for HTML pages.
+    pub async fn fetch_page_content(&self, url: &str, include_raw_html: bool) -> Result<PageContent, WebSearchError> {
+        self.egress.check_url(url)?;
+
+        let response = self.client.get(url).send().await?;
+        // Pages that don't say what they are are treated as HTML
+        let media_type = response.headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_lowercase())
+            .filter(|value| !value.is_empty())
+            .unwrap_or_else(|| "text/html".to_string());
+        if !content_type_allowed(&self.fetch_content_types, &media_type) {
+            return Err(WebSearchError::UnsupportedContentType(media_type));
+        }
+
+        let text = match page_kind(&media_type) {
+            PageKind::Html => return Ok(self.extract_html(response.text().await?, include_raw_html)),
+            PageKind::Json => {
+                let body = response.text().await?;
+                // Malformed JSON is still worth reading as text
+                serde_json::from_str::<serde_json::Value>(&body)
+                    .ok()
+                    .and_then(|value| serde_json::to_string_pretty(&value).ok())
+                    .unwrap_or(body)
+            }
+            PageKind::Text => response.text().await?,
+            PageKind::Binary => format!("<{} bytes of {}>", response.bytes().await?.len(), media_type),
+        };
+        Ok(PageContent { text: text.trim().to_string(), raw_html: None })
+    }
+
+    fn extract_html(&self, response: String, include_raw_html: bool) -> PageContent {
         let document = scraper::Html::parse_document(&response);
-        let selector = scraper::Selector::parse("p, h1, h2, h3, h4, h5, h6, article, section").unwrap();
-        
-        let content: String = document
-            .select(&selector)
-            .map(|element| element.text().collect::<String>())
-            .collect::<Vec<String>>()
-            .join("\n\n");
 
-        Ok(content.trim().to_string())
+        let main_text = match self.extraction_mode {
+            ExtractionMode::Readability => readability::extract_main_text(&document),
+            ExtractionMode::All => None,
+        };
+        let content: String = main_text.unwrap_or_else(|| {
+            document
+                .select(&PAGE_CONTENT_SELECTOR)
+                .map(|element| element.text().collect::<String>())
+                .collect::<Vec<String>>()
+                .join("\n\n")
+        });
+
+        let raw_html = include_raw_html.then(|| response.chars().take(RAW_HTML_MAX_CHARS).collect());
+        PageContent {
+            text: content.trim().to_string(),
+            raw_html,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// DuckDuckGo's HTML endpoint serving its CAPTCHA ("anomaly") page instead of results.
+    const ANOMALY_PAGE: &str = include_str!("testdata/duckduckgo_anomaly.html");
+
+    const RESULTS_PAGE: &str = r#"<html><body>
+        <div class="result">
+          <h2 class="result__title"><a href="//duckduckgo.com/l/?uddg=https%3A%2F%2Fblog.rust-lang.org%2F">Rust Blog</a></h2>
+          <a class="result__snippet">Announcing Rust 1.80.0</a>
+        </div>
+    </body></html>"#;
+
+    #[test]
+    fn anomaly_page_has_no_results_and_is_detected() {
+        let document = Html::parse_document(ANOMALY_PAGE);
+        assert_eq!(document.select(&RESULT_SELECTOR).count(), 0);
+        assert!(is_anomaly_page(ANOMALY_PAGE));
+    }
+
+    #[test]
+    fn results_page_is_not_an_anomaly() {
+        let document = Html::parse_document(RESULTS_PAGE);
+        assert_eq!(document.select(&RESULT_SELECTOR).count(), 1);
+        assert!(!is_anomaly_page(RESULTS_PAGE));
+    }
+
+    #[test]
+    fn empty_results_page_is_not_an_anomaly() {
+        assert!(!is_anomaly_page(r#"<html><body><div class="no-results">No results.</div></body></html>"#));
+    }
+
+    struct ReverseRanker;
+
+    impl ResultRanker for ReverseRanker {
+        fn rank(&self, _query: &str, mut results: Vec<SearchResult>) -> Vec<SearchResult> {
+            results.reverse();
+            results
+        }
+    }
+
+    #[test]
+    fn custom_ranker_replaces_the_configured_one() {
+        let result = |title: &str| SearchResult {
+            title: title.to_string(),
+            content: String::new(),
+            url: format!("https://example.com/{}", title),
+            score: None,
+            raw_html: None,
+            published: None,
+            instant_answer: false,
+        };
+        let client = WebSearchClient::new().with_ranker(ReverseRanker);
+        let ranked = client.ranker.rank("rust", vec![result("first"), result("second")]);
+        let titles: Vec<&str> = ranked.iter().map(|r| r.title.as_str()).collect();
+        assert_eq!(titles, ["second", "first"]);
     }
 }